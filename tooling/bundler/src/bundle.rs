@@ -3,14 +3,27 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
+mod appcast;
+mod arch;
+mod audit;
+mod cache;
 mod category;
+mod checksums;
 mod common;
+mod delta;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod libdmg;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
 mod path_utils;
+mod paths;
 mod platform;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod portable;
+mod preflight;
+mod remote;
 mod settings;
 mod updater_bundle;
 #[cfg(target_os = "windows")]
@@ -19,12 +32,26 @@ mod windows;
 pub use self::{
   category::AppCategory,
   settings::{
-    BundleBinary, BundleSettings, DebianSettings, MacOsSettings, PackageSettings, PackageType,
-    Settings, SettingsBuilder, UpdaterSettings,
+    AppcastSettings, BundleBinary, BundleSettings, CancellationToken, ChecksumFormat, CopyLimits,
+    CrashReporter, DebCompression, DebianFilePermissions, DebianSettings, DmgBackend, DmgFormat,
+    DmgLabelPosition, DmgLicenseLabels, DmgLicenseLanguage, DmgSettings, FileNameSanitization,
+    GpgSettings, HdiutilVerbosity, InfoPlistTransform, MacOsSettings, MultiArchStrategy,
+    PackageSettings, PackageType, PlatformResource, PreviousArtifact, ReleaseNotes, RemoteResource,
+    ResourceFiles, ResourcePlatform, ResourceScope, ScopedResource, Settings, SettingsBuilder,
+    UpdaterSettings, ZipSettings,
   },
 };
-use log::{info, warn};
-pub use settings::{WindowsSettings, WixLanguage, WixLanguageConfig, WixSettings};
+pub use delta::apply_delta;
+use log::{error, info, warn};
+#[cfg(target_os = "macos")]
+pub use macos::dmg::resign as resign_dmg;
+pub use paths::predict_output_paths;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub use portable::write_portable_zip;
+pub use settings::{
+  WindowsKeyProvider, WindowsSettings, WixLanguage, WixLanguageConfig, WixRegistryEntry,
+  WixSettings, WixUninstallCleanup, WixUpgradeCode,
+};
 
 use std::{fmt::Write, path::PathBuf};
 
@@ -39,41 +66,152 @@ pub struct Bundle {
 
 /// Bundles the project.
 /// Returns the list of paths where the bundles can be found.
-pub fn bundle_project(settings: Settings) -> crate::Result<Vec<Bundle>> {
+pub fn bundle_project(mut settings: Settings) -> crate::Result<Vec<Bundle>> {
   let mut bundles = Vec::new();
+  let mut failures = Vec::new();
   let package_types = settings.package_types()?;
 
+  preflight::check(&settings)?;
+  arch::validate_binaries(&settings)?;
+  arch::validate_universal(&settings)?;
+
+  if settings.skip_signing() {
+    warn!(
+      "signing and notarization are disabled, the bundles are unsigned and must not be released"
+    );
+  }
+
+  // the tool context of the settings is reset when this function returns
+  let _tool_context = common::enter(&settings)?;
+  settings.fetch_remote_resources()?;
+
+  // import the signing certificate once for the whole bundling process,
+  // the temporary keychain is deleted when this function returns
+  #[cfg(target_os = "macos")]
+  let _keychain = if settings
+    .macos()
+    .signing_identity
+    .as_deref()
+    .map_or(false, |identity| !macos::sign::is_ad_hoc(identity))
+  {
+    macos::sign::setup_keychain_from_env()?
+  } else {
+    None
+  };
+
+  // the file tree shared by the deb and AppImage bundles, staged once and removed when dropped
+  #[cfg(target_os = "linux")]
+  let mut linux_payload = None;
+
+  let mut cache = cache::BundleCache::load(&settings);
+
   for package_type in &package_types {
+    settings.check_cancelled()?;
+
+    let cache_key = if settings.incremental() {
+      cache::input_key(&settings, *package_type)?
+    } else {
+      None
+    };
+    if let Some(bundle_paths) = cache_key
+      .as_deref()
+      .and_then(|key| cache.get(*package_type, key))
+    {
+      info!(action = "Skipping"; "{} bundle, its inputs did not change", package_type.short_name());
+      bundles.push(Bundle {
+        package_type: package_type.to_owned(),
+        bundle_paths,
+      });
+      continue;
+    }
+
+    let max_level = log::max_level();
+    if let Some(level) = settings.log_level(*package_type) {
+      log::set_max_level(level);
+    }
     let bundle_paths = match package_type {
       #[cfg(target_os = "macos")]
-      PackageType::MacOsBundle => macos::app::bundle_project(&settings)?,
+      PackageType::MacOsBundle => macos::app::bundle_project(&settings),
       #[cfg(target_os = "macos")]
-      PackageType::IosBundle => macos::ios::bundle_project(&settings)?,
+      PackageType::IosBundle => macos::ios::bundle_project(&settings),
       #[cfg(target_os = "windows")]
-      PackageType::WindowsMsi => windows::msi::bundle_project(&settings, false)?,
+      PackageType::WindowsMsi => windows::msi::bundle_project(&settings, false),
       #[cfg(target_os = "linux")]
-      PackageType::Deb => linux::debian::bundle_project(&settings)?,
+      PackageType::Deb => linux::debian::bundle_project(&settings, &mut linux_payload),
       #[cfg(target_os = "linux")]
-      PackageType::Rpm => linux::rpm::bundle_project(&settings)?,
+      PackageType::Rpm => linux::rpm::bundle_project(&settings),
       #[cfg(target_os = "linux")]
-      PackageType::AppImage => linux::appimage::bundle_project(&settings)?,
+      PackageType::AppImage => linux::appimage::bundle_project(&settings, &mut linux_payload),
       // dmg is dependant of MacOsBundle, we send our bundles to prevent rebuilding
       #[cfg(target_os = "macos")]
-      PackageType::Dmg => macos::dmg::bundle_project(&settings, &bundles)?,
+      PackageType::Dmg if settings.dmg().backend != DmgBackend::Libdmg => {
+        macos::dmg::bundle_project(&settings, &bundles)
+      }
+      #[cfg(target_os = "linux")]
+      PackageType::Dmg if settings.dmg().backend != DmgBackend::Libdmg => {
+        Err(crate::Error::GenericError(format!(
+          "the {:?} DMG backend is only available on macOS",
+          settings.dmg().backend
+        )))
+      }
+      #[cfg(any(target_os = "macos", target_os = "linux"))]
+      PackageType::Dmg => libdmg::bundle_project(&settings),
+      // the zip is dependant of MacOsBundle, we send our bundles to prevent rebuilding
+      #[cfg(target_os = "macos")]
+      PackageType::Zip => macos::zip::bundle_project(&settings, &bundles),
+      #[cfg(any(target_os = "linux", target_os = "windows"))]
+      PackageType::Zip => portable::bundle_project(&settings),
       // updater is dependant of multiple bundle, we send our bundles to prevent rebuilding
-      PackageType::Updater => updater_bundle::bundle_project(&settings, &bundles)?,
+      PackageType::Updater => updater_bundle::bundle_project(&settings, &bundles),
       _ => {
         warn!("ignoring {:?}", package_type);
+        log::set_max_level(max_level);
         continue;
       }
     };
+    log::set_max_level(max_level);
+    let bundle_paths =
+      bundle_paths.and_then(|paths| delta::create(&settings, *package_type, paths));
+    #[cfg(target_os = "linux")]
+    let bundle_paths = match package_type {
+      PackageType::Deb | PackageType::AppImage => {
+        bundle_paths.and_then(|paths| linux::gpg::sign_all(&settings, paths))
+      }
+      _ => bundle_paths,
+    };
+    let bundle_paths =
+      bundle_paths.and_then(|paths| appcast::add_entry(&settings, *package_type, paths));
+    let bundle_paths = match bundle_paths {
+      Ok(bundle_paths) => bundle_paths,
+      // the cancellation may surface as the failure of a killed tool
+      Err(_) if settings.is_cancelled() => return Err(crate::Error::Cancelled),
+      Err(e) if !settings.fail_fast() => {
+        error!("failed to bundle {}: {}", package_type.short_name(), e);
+        failures.push((*package_type, e));
+        continue;
+      }
+      Err(e) => return Err(e),
+    };
 
+    if let Some(key) = cache_key {
+      cache.insert(*package_type, key, &bundle_paths)?;
+    }
+
+    info!(action = "Bundled", package_type = package_type.short_name(); "{} ({} files)", package_type.short_name(), bundle_paths.len());
     bundles.push(Bundle {
       package_type: package_type.to_owned(),
       bundle_paths,
     });
   }
 
+  let manifests = checksums::write_manifests(&settings, &bundles)?;
+  if !manifests.is_empty() {
+    bundles.push(Bundle {
+      package_type: PackageType::Checksums,
+      bundle_paths: manifests,
+    });
+  }
+
   let pluralised = if bundles.len() == 1 {
     "bundle"
   } else {
@@ -87,13 +225,22 @@ pub fn bundle_project(settings: Settings) -> crate::Result<Vec<Bundle>> {
       if bundle.package_type == crate::PackageType::Updater {
         note = " (updater)";
       }
+      // a structured event per file, for the loggers of CI tooling
+      info!(action = "Artifact", package_type = bundle.package_type.short_name(); "{}", path.display());
       writeln!(printable_paths, "        {}{}", path.display(), note).unwrap();
     }
   }
 
   info!(action = "Finished"; "{} {} at:\n{}", bundles.len(), pluralised, printable_paths);
+  if settings.skip_signing() {
+    warn!("the {} are UNSIGNED, do not release them", pluralised);
+  }
 
-  Ok(bundles)
+  if failures.is_empty() {
+    Ok(bundles)
+  } else {
+    Err(crate::Error::BundleFailures { bundles, failures })
+  }
 }
 
 /// Check to see if there are icons in the settings struct
@@ -0,0 +1,375 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::{
+  audit,
+  common::{self, CommandExt},
+};
+use crate::{AppcastSettings, PackageType, Settings};
+use anyhow::Context;
+use log::info;
+
+use std::{
+  env, fs,
+  path::{Path, PathBuf},
+  process::Command,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The package types an appcast entry is added for when [`AppcastSettings::package_types`] is empty.
+const DEFAULT_PACKAGE_TYPES: &[PackageType] = &[PackageType::Dmg];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Adds the entry of the first bundle path to the [`crate::BundleSettings::appcast`] feed,
+/// if it is set and lists the package type, and returns the bundle paths.
+///
+/// An entry for the same download URL, e.g. from a previous build of the version, is replaced.
+pub fn add_entry(
+  settings: &Settings,
+  package_type: PackageType,
+  bundle_paths: Vec<PathBuf>,
+) -> crate::Result<Vec<PathBuf>> {
+  let appcast = match settings.appcast() {
+    Some(appcast) => appcast,
+    None => return Ok(bundle_paths),
+  };
+  let package_types = if appcast.package_types.is_empty() {
+    DEFAULT_PACKAGE_TYPES
+  } else {
+    &appcast.package_types
+  };
+  if !package_types.contains(&package_type) {
+    return Ok(bundle_paths);
+  }
+  check_not_segmented(&bundle_paths)?;
+  let artifact = match bundle_paths.first().filter(|path| path.is_file()) {
+    Some(artifact) => artifact,
+    None => {
+      return Err(crate::Error::GenericError(format!(
+        "an appcast entry needs a file, the {} bundle is a directory",
+        package_type.short_name()
+      )))
+    }
+  };
+
+  info!(action = "Adding"; "{} to the appcast {}", artifact.display(), appcast.path.display());
+
+  let file_name = artifact
+    .file_name()
+    .expect("failed to get artifact filename")
+    .to_string_lossy();
+  let url = settings
+    .expand_file_name_template(&appcast.url_template)
+    .replace("{file_name}", &file_name);
+  let signature = match &appcast.sign_update_path {
+    Some(sign_update) => {
+      let identity = appcast
+        .ed_key_file
+        .as_ref()
+        .map_or_else(|| "keychain".into(), |key| key.display().to_string());
+      let result = ed_signature(sign_update, appcast, artifact);
+      Some(audit::record(
+        settings, "appcast", &identity, artifact, None, result,
+      )?)
+    }
+    None => None,
+  };
+  let item = item(
+    settings,
+    appcast,
+    &url,
+    fs::metadata(artifact)?.len(),
+    signature.as_deref(),
+    &rfc2822_date(pub_date()?),
+  )?;
+
+  let feed = if appcast.path.exists() {
+    Some(
+      fs::read_to_string(&appcast.path)
+        .with_context(|| format!("Failed to read the appcast {:?}", appcast.path))?,
+    )
+  } else {
+    None
+  };
+  let feed = add_item(feed.as_deref(), settings.product_name(), &url, &item)?;
+  let mut file = common::create_file(&appcast.path)?;
+  std::io::Write::write_all(&mut file, feed.as_bytes())?;
+
+  Ok(bundle_paths)
+}
+
+// Fails for a segmented DMG, since Sparkle downloads and verifies the enclosure as one file
+// while the first part only mounts with the `.dmgpart` files next to it.
+fn check_not_segmented(bundle_paths: &[PathBuf]) -> crate::Result<()> {
+  let segmented = bundle_paths
+    .iter()
+    .any(|path| path.extension().map_or(false, |ext| ext == "dmgpart"));
+  if segmented {
+    return Err(crate::Error::GenericError(
+      "an appcast entry cannot point at a segmented DMG, raise or unset `split_size_mb`".into(),
+    ));
+  }
+  Ok(())
+}
+
+// Renders the `<item>` of the artifact.
+fn item(
+  settings: &Settings,
+  appcast: &AppcastSettings,
+  url: &str,
+  length: u64,
+  signature: Option<&str>,
+  pub_date: &str,
+) -> crate::Result<String> {
+  let mut item = String::from("    <item>\n");
+  item.push_str(&format!(
+    "      <title>{}</title>\n",
+    escape(&format!("Version {}", settings.short_version_string()))
+  ));
+  item.push_str(&format!("      <pubDate>{}</pubDate>\n", pub_date));
+  item.push_str(&format!(
+    "      <sparkle:version>{}</sparkle:version>\n",
+    escape(
+      settings
+        .build_number()
+        .unwrap_or_else(|| settings.version_string())
+    )
+  ));
+  item.push_str(&format!(
+    "      <sparkle:shortVersionString>{}</sparkle:shortVersionString>\n",
+    escape(&settings.short_version_string())
+  ));
+  if let Some(version) = appcast
+    .minimum_system_version
+    .as_ref()
+    .or(settings.macos().minimum_system_version.as_ref())
+  {
+    item.push_str(&format!(
+      "      <sparkle:minimumSystemVersion>{}</sparkle:minimumSystemVersion>\n",
+      escape(version)
+    ));
+  }
+  if let Some(notes) = settings.release_notes()? {
+    item.push_str(&format!(
+      "      <description><![CDATA[{}]]></description>\n",
+      notes.replace("]]>", "]]]]><![CDATA[>")
+    ));
+  }
+  item.push_str(&format!(
+    "      <enclosure url=\"{}\" length=\"{}\" type=\"application/octet-stream\"",
+    escape(url),
+    length
+  ));
+  if let Some(signature) = signature {
+    item.push_str(&format!(" sparkle:edSignature=\"{}\"", escape(signature)));
+  }
+  item.push_str("/>\n    </item>\n");
+  Ok(item)
+}
+
+// Inserts the item at the top of the feed's channel, creating the feed if there is none,
+// and removes the items with the same download URL.
+fn add_item(feed: Option<&str>, title: &str, url: &str, item: &str) -> crate::Result<String> {
+  let mut feed = match feed {
+    Some(feed) => feed.to_string(),
+    None => format!(
+      "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\" xmlns:sparkle=\"http://www.andymatuschak.org/xml-namespaces/sparkle\">\n  <channel>\n    <title>{}</title>\n  </channel>\n</rss>\n",
+      escape(title)
+    ),
+  };
+
+  let enclosure = format!("url=\"{}\"", escape(url));
+  let mut search_from = 0;
+  while let Some(start) = feed[search_from..].find("<item>").map(|i| i + search_from) {
+    let end = match feed[start..].find("</item>") {
+      Some(i) => start + i + "</item>".len(),
+      None => break,
+    };
+    if feed[start..end].contains(&enclosure) {
+      // the whole lines of the item are removed, with their indentation
+      let line_start = feed[..start].rfind('\n').map_or(0, |i| i + 1);
+      let line_end = feed[end..].find('\n').map_or(feed.len(), |i| end + i + 1);
+      feed.replace_range(line_start..line_end, "");
+      search_from = line_start;
+    } else {
+      search_from = end;
+    }
+  }
+
+  let channel = feed
+    .find("<channel>")
+    .ok_or_else(|| crate::Error::GenericError("the appcast has no `<channel>` element".into()))?;
+  // after the channel's own elements, before its first item
+  let position = match feed[channel..].find("<item>") {
+    Some(i) => feed[..channel + i].rfind('\n').map_or(0, |i| i + 1),
+    None => {
+      let close = feed.find("</channel>").ok_or_else(|| {
+        crate::Error::GenericError("the appcast has no `</channel>` element".into())
+      })?;
+      feed[..close].rfind('\n').map_or(0, |i| i + 1)
+    }
+  };
+  feed.insert_str(position, item);
+  Ok(feed)
+}
+
+// Signs the artifact with Sparkle's `sign_update` tool, which prints the `sparkle:edSignature` attribute.
+fn ed_signature(
+  sign_update: &Path,
+  appcast: &AppcastSettings,
+  artifact: &Path,
+) -> crate::Result<String> {
+  let mut command = Command::new(sign_update);
+  if let Some(key_file) = &appcast.ed_key_file {
+    command.arg("--ed-key-file").arg(key_file);
+  }
+  let output = command
+    .arg(artifact)
+    .output_ok()
+    .with_context(|| format!("failed to sign {} with sign_update", artifact.display()))?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  parse_ed_signature(&stdout).ok_or_else(|| {
+    crate::Error::GenericError(format!(
+      "sign_update did not print an EdDSA signature: {}",
+      stdout.trim()
+    ))
+  })
+}
+
+fn parse_ed_signature(output: &str) -> Option<String> {
+  const ATTRIBUTE: &str = "sparkle:edSignature=\"";
+  let start = output.find(ATTRIBUTE)? + ATTRIBUTE.len();
+  let end = output[start..].find('"')? + start;
+  Some(output[start..end].to_string())
+}
+
+// The publication date, from `SOURCE_DATE_EPOCH` for reproducible feeds or the current time.
+fn pub_date() -> crate::Result<u64> {
+  match env::var("SOURCE_DATE_EPOCH") {
+    Ok(epoch) => epoch.trim().parse().map_err(|_| {
+      crate::Error::GenericError(format!(
+        "invalid SOURCE_DATE_EPOCH `{}`, expected a Unix timestamp",
+        epoch
+      ))
+    }),
+    Err(_) => Ok(
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default(),
+    ),
+  }
+}
+
+// Formats a Unix timestamp as an RFC 2822 date in UTC, as RSS requires.
+fn rfc2822_date(timestamp: u64) -> String {
+  let days = timestamp / 86400;
+  let seconds = timestamp % 86400;
+  // days to the civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+  let z = days as i64 + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  format!(
+    "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+    WEEKDAYS[(days % 7) as usize],
+    day,
+    MONTHS[(month - 1) as usize],
+    year,
+    seconds / 3600,
+    seconds % 3600 / 60,
+    seconds % 60
+  )
+}
+
+fn escape(value: &str) -> String {
+  value
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{add_item, check_not_segmented, parse_ed_signature, rfc2822_date};
+  use std::path::PathBuf;
+
+  #[test]
+  fn rejects_segmented_dmgs() {
+    let single = [PathBuf::from("App.dmg"), PathBuf::from("App.parts.json")];
+    assert!(check_not_segmented(&single).is_ok());
+    let segmented = [
+      PathBuf::from("App.dmg"),
+      PathBuf::from("App.002.dmgpart"),
+      PathBuf::from("App.parts.json"),
+    ];
+    assert!(check_not_segmented(&segmented).is_err());
+  }
+
+  #[test]
+  fn formats_rfc2822_dates() {
+    assert_eq!(rfc2822_date(0), "Thu, 01 Jan 1970 00:00:00 +0000");
+    assert_eq!(
+      rfc2822_date(1_709_210_096),
+      "Thu, 29 Feb 2024 12:34:56 +0000"
+    );
+  }
+
+  #[test]
+  fn adds_items_to_the_feed() {
+    let item = |url: &str| {
+      format!(
+        "    <item>\n      <enclosure url=\"{}\" length=\"1\"/>\n    </item>\n",
+        url
+      )
+    };
+    let feed = add_item(
+      None,
+      "App",
+      "https://example.com/1.dmg",
+      &item("https://example.com/1.dmg"),
+    )
+    .unwrap();
+    assert!(feed.contains("<title>App</title>\n    <item>"));
+    assert!(feed.ends_with("</item>\n  </channel>\n</rss>\n"));
+
+    // newer items come first and a rebuilt artifact replaces its item
+    let feed = add_item(
+      Some(&feed),
+      "App",
+      "https://example.com/2.dmg",
+      &item("https://example.com/2.dmg"),
+    )
+    .unwrap();
+    let feed = add_item(
+      Some(&feed),
+      "App",
+      "https://example.com/2.dmg",
+      &item("https://example.com/2.dmg"),
+    )
+    .unwrap();
+    assert_eq!(feed.matches("<item>").count(), 2);
+    assert!(feed.find("2.dmg").unwrap() < feed.find("1.dmg").unwrap());
+
+    assert!(add_item(Some("<rss></rss>"), "App", "url", "item").is_err());
+  }
+
+  #[test]
+  fn parses_sign_update_output() {
+    assert_eq!(
+      parse_ed_signature("sparkle:edSignature=\"c2lnbmF0dXJl\" length=\"1024\"\n").as_deref(),
+      Some("c2lnbmF0dXJl")
+    );
+    assert_eq!(parse_ed_signature("error"), None);
+  }
+}
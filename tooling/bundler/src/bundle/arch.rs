@@ -0,0 +1,326 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use crate::{MultiArchStrategy, Settings};
+use anyhow::Context;
+
+use std::{
+  fs::File,
+  io::Read,
+  path::{Path, PathBuf},
+};
+
+// How much of the binary is read to find its header, enough for the PE header offset of any linker.
+const HEADER_LEN: usize = 4096;
+
+/// Detects the architecture of an ELF, Mach-O or PE binary, in the format of [`Settings::binary_arch`].
+///
+/// Returns `None` for files that are not binaries or architectures the bundler does not target.
+pub fn detect(path: &Path) -> crate::Result<Option<&'static str>> {
+  let mut header = Vec::with_capacity(HEADER_LEN);
+  File::open(path)?
+    .take(HEADER_LEN as u64)
+    .read_to_end(&mut header)?;
+  Ok(detect_header(&header))
+}
+
+/// Lists the architectures of the slices of a Mach-O binary, one for a thin binary.
+///
+/// Returns an empty list for files that are not Mach-O binaries.
+pub fn macho_slices(path: &Path) -> crate::Result<Vec<&'static str>> {
+  let mut header = Vec::with_capacity(HEADER_LEN);
+  File::open(path)?
+    .take(HEADER_LEN as u64)
+    .read_to_end(&mut header)?;
+  Ok(macho_header_slices(&header))
+}
+
+fn macho_header_slices(header: &[u8]) -> Vec<&'static str> {
+  let u32_be = |offset: usize| {
+    header
+      .get(offset..offset + 4)
+      .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+  };
+  let entry_len = match header.get(0..4) {
+    Some([0xca, 0xfe, 0xba, 0xbe]) => 20,
+    Some([0xca, 0xfe, 0xba, 0xbf]) => 32,
+    _ => {
+      return match detect_header(header) {
+        Some(arch) if header.get(1..4) == Some(&[0xfa, 0xed, 0xfe]) => vec![arch],
+        _ => Vec::new(),
+      }
+    }
+  };
+  if detect_header(header) != Some("universal") {
+    return Vec::new();
+  }
+  let arch_count = u32_be(4).unwrap_or_default() as usize;
+  (0..arch_count)
+    .filter_map(|i| u32_be(8 + i * entry_len))
+    .filter_map(macho_cpu_arch)
+    .collect()
+}
+
+fn macho_cpu_arch(cpu_type: u32) -> Option<&'static str> {
+  match cpu_type {
+    0x0000_0007 => Some("x86"),
+    0x0100_0007 => Some("x86_64"),
+    0x0000_000c => Some("arm"),
+    0x0100_000c => Some("aarch64"),
+    _ => None,
+  }
+}
+
+fn detect_header(header: &[u8]) -> Option<&'static str> {
+  let u16_le = |offset: usize| {
+    header
+      .get(offset..offset + 2)
+      .map(|b| u16::from_le_bytes([b[0], b[1]]))
+  };
+  let u32_le = |offset: usize| {
+    header
+      .get(offset..offset + 4)
+      .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+  };
+
+  match header.get(0..4)? {
+    [0x7f, b'E', b'L', b'F'] => {
+      let machine = match header.get(5)? {
+        2 => header
+          .get(18..20)
+          .map(|b| u16::from_be_bytes([b[0], b[1]]))?,
+        _ => u16_le(18)?,
+      };
+      match machine {
+        0x03 => Some("x86"),
+        0x3e => Some("x86_64"),
+        0x28 => Some("arm"),
+        0xb7 => Some("aarch64"),
+        _ => None,
+      }
+    }
+    // 32 and 64 bit Mach-O, little-endian
+    [0xce, 0xfa, 0xed, 0xfe] | [0xcf, 0xfa, 0xed, 0xfe] => macho_cpu_arch(u32_le(4)?),
+    // universal binaries, told apart from Java class files by their small slice count
+    [0xca, 0xfe, 0xba, 0xbe] | [0xca, 0xfe, 0xba, 0xbf] => {
+      let arch_count = header
+        .get(4..8)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))?;
+      if arch_count > 0 && arch_count < 45 {
+        Some("universal")
+      } else {
+        None
+      }
+    }
+    [b'M', b'Z', ..] => {
+      let pe_offset = u32_le(0x3c)? as usize;
+      if header.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+        return None;
+      }
+      match u16_le(pe_offset + 4)? {
+        0x014c => Some("x86"),
+        0x8664 => Some("x86_64"),
+        0x01c0 | 0x01c4 => Some("arm"),
+        0xaa64 => Some("aarch64"),
+        _ => None,
+      }
+    }
+    _ => None,
+  }
+}
+
+/// Checks that the binaries to bundle were built for the target architecture.
+///
+/// Binaries whose architecture cannot be detected are not checked, and neither are the binaries
+/// of a [`crate::BundleSettings::prebuilt_bundle`], which are only used for their names.
+pub fn validate_binaries(settings: &Settings) -> crate::Result<()> {
+  if settings.prebuilt_bundle().is_some() {
+    return Ok(());
+  }
+  let expected = settings.binary_arch();
+  for bin in settings.binaries() {
+    let path = settings.binary_path(bin);
+    if !path.is_file() {
+      continue;
+    }
+    if let Some(arch) = detect(&path)? {
+      // a universal binary also runs on the architectures of its slices
+      if arch != expected && (arch != "universal" || expected == "universal") {
+        return Err(crate::Error::GenericError(format!(
+          "the binary {} was built for {}, but the bundle targets {} ({})",
+          path.display(),
+          arch,
+          expected,
+          settings.target()
+        )));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Checks that the main binary has both an `x86_64` and an `arm64` slice when
+/// [`crate::MacOsSettings::require_universal`] is set, counting the [`crate::MacOsSettings::arch_binaries`]
+/// that are merged into it with `lipo`.
+///
+/// Only macOS targets are checked, since the setting is shared with the other platforms.
+/// For a [`crate::BundleSettings::prebuilt_bundle`] the executable in its `Contents/MacOS` is checked.
+pub fn validate_universal(settings: &Settings) -> crate::Result<()> {
+  let macos = settings.macos();
+  if !macos.require_universal || !settings.target().ends_with("apple-darwin") {
+    return Ok(());
+  }
+  let main_binary = match settings.prebuilt_bundle() {
+    Some(prebuilt_bundle) => prebuilt_executable(settings, prebuilt_bundle)?,
+    None => settings.binary_path(
+      settings
+        .binaries()
+        .iter()
+        .find(|bin| bin.main())
+        .ok_or_else(|| crate::Error::GenericError("failed to find the main binary".into()))?,
+    ),
+  };
+  let mut paths = vec![main_binary.clone()];
+  // the arch binaries are not merged into a prebuilt bundle
+  if macos.multi_arch_strategy == MultiArchStrategy::Lipo && settings.prebuilt_bundle().is_none() {
+    paths.extend(macos.arch_binaries.values().cloned());
+  }
+
+  let mut slices = Vec::new();
+  for path in &paths {
+    slices.extend(macho_slices(path)?);
+  }
+  let missing = ["x86_64", "aarch64"]
+    .iter()
+    .filter(|arch| !slices.contains(arch))
+    .map(|arch| if *arch == "aarch64" { "arm64" } else { arch })
+    .collect::<Vec<_>>();
+  if missing.is_empty() {
+    return Ok(());
+  }
+  slices.sort_unstable();
+  slices.dedup();
+  Err(crate::Error::GenericError(format!(
+    "the app must be universal, but the main binary {} has no {} slice (found: {}); build for the \
+     `universal-apple-darwin` target or add the missing architectures to `arch_binaries`",
+    main_binary.display(),
+    missing.join(" and "),
+    if slices.is_empty() {
+      "none".to_string()
+    } else {
+      slices.join(", ")
+    }
+  )))
+}
+
+// The `CFBundleExecutable` of the prebuilt app bundle, defaulting to the main binary name.
+fn prebuilt_executable(settings: &Settings, prebuilt_bundle: &Path) -> crate::Result<PathBuf> {
+  let info_plist = prebuilt_bundle.join("Contents/Info.plist");
+  let executable = if info_plist.is_file() {
+    plist::Value::from_file(&info_plist)
+      .with_context(|| format!("Failed to read {:?}", info_plist))?
+      .as_dictionary()
+      .and_then(|info| info.get("CFBundleExecutable"))
+      .and_then(|executable| executable.as_string())
+      .map(ToString::to_string)
+  } else {
+    None
+  };
+  Ok(
+    prebuilt_bundle.join("Contents/MacOS").join(
+      executable
+        .as_deref()
+        .unwrap_or_else(|| settings.main_binary_name()),
+    ),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{detect_header, macho_header_slices, validate_universal};
+  use crate::{bundle::settings::test_settings, BundleSettings, MacOsSettings};
+  use std::fs;
+
+  #[test]
+  fn detects_binary_architectures() {
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1];
+    elf.resize(20, 0);
+    elf[18] = 0xb7;
+    assert_eq!(detect_header(&elf), Some("aarch64"));
+
+    let macho = [0xcf, 0xfa, 0xed, 0xfe, 0x07, 0x00, 0x00, 0x01];
+    assert_eq!(detect_header(&macho), Some("x86_64"));
+
+    let mut pe = vec![0; 0x90];
+    pe[0..2].copy_from_slice(b"MZ");
+    pe[0x3c] = 0x80;
+    pe[0x80..0x84].copy_from_slice(b"PE\0\0");
+    pe[0x84..0x86].copy_from_slice(&0x014c_u16.to_le_bytes());
+    assert_eq!(detect_header(&pe), Some("x86"));
+
+    assert_eq!(detect_header(b"#!/bin/sh\n"), None);
+  }
+
+  #[test]
+  fn lists_macho_slices() {
+    let thin = [0xcf, 0xfa, 0xed, 0xfe, 0x0c, 0x00, 0x00, 0x01];
+    assert_eq!(macho_header_slices(&thin), vec!["aarch64"]);
+
+    let mut fat = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 2];
+    for cpu_type in [0x0100_0007_u32, 0x0100_000c] {
+      fat.extend_from_slice(&cpu_type.to_be_bytes());
+      fat.extend_from_slice(&[0; 16]);
+    }
+    assert_eq!(macho_header_slices(&fat), vec!["x86_64", "aarch64"]);
+
+    let mut fat_64 = vec![0xca, 0xfe, 0xba, 0xbf, 0, 0, 0, 1];
+    fat_64.extend_from_slice(&0x0100_0007_u32.to_be_bytes());
+    fat_64.extend_from_slice(&[0; 28]);
+    assert_eq!(macho_header_slices(&fat_64), vec!["x86_64"]);
+
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1];
+    elf.resize(20, 0);
+    assert!(macho_header_slices(&elf).is_empty());
+  }
+
+  #[test]
+  fn checks_the_prebuilt_executable() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let prebuilt = tmp.path().join("App.app");
+    fs::create_dir_all(prebuilt.join("Contents/MacOS")).unwrap();
+    fs::write(
+      prebuilt.join("Contents/Info.plist"),
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>CFBundleExecutable</key><string>Prebuilt</string></dict></plist>"#,
+    )
+    .unwrap();
+    // the built main binary is not read for a prebuilt bundle
+    fs::write(tmp.path().join("app"), b"#!/bin/sh\n").unwrap();
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        prebuilt_bundle: Some(prebuilt.clone()),
+        macos: MacOsSettings {
+          require_universal: true,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      "x86_64-apple-darwin",
+    );
+
+    let thin = [0xcf, 0xfa, 0xed, 0xfe, 0x07, 0x00, 0x00, 0x01];
+    fs::write(prebuilt.join("Contents/MacOS/Prebuilt"), thin).unwrap();
+    let error = validate_universal(&settings).unwrap_err().to_string();
+    assert!(error.contains("Contents/MacOS/Prebuilt has no arm64 slice"));
+
+    let mut fat = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 2];
+    for cpu_type in [0x0100_0007_u32, 0x0100_000c] {
+      fat.extend_from_slice(&cpu_type.to_be_bytes());
+      fat.extend_from_slice(&[0; 16]);
+    }
+    fs::write(prebuilt.join("Contents/MacOS/Prebuilt"), fat).unwrap();
+    assert!(validate_universal(&settings).is_ok());
+  }
+}
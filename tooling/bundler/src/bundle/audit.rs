@@ -0,0 +1,116 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::common;
+use crate::Settings;
+
+use std::{
+  fs::OpenOptions,
+  io::Write,
+  path::Path,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Appends a signing action to the [`crate::BundleSettings::signing_audit_log`], if it is set,
+/// and returns the `result` of the action.
+///
+/// The `identity` must never be a secret: it is the certificate name, thumbprint, key ID or Apple ID.
+pub fn record<T>(
+  settings: &Settings,
+  action: &str,
+  identity: &str,
+  artifact: &Path,
+  output: Option<&str>,
+  result: crate::Result<T>,
+) -> crate::Result<T> {
+  let log_path = match settings.signing_audit_log() {
+    Some(log_path) => log_path,
+    None => return result,
+  };
+
+  // a file is hashed like `sha256sum` does, so the record can be checked against the artifact
+  let sha256 = if artifact.is_file() {
+    common::hash_file(artifact).ok()
+  } else {
+    common::hash_path(artifact).ok()
+  };
+  let timestamp = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default();
+  let entry = serde_json::json!({
+    "timestamp": timestamp,
+    "action": action,
+    "identity": identity,
+    "artifact": artifact,
+    "sha256": sha256,
+    "success": result.is_ok(),
+    "error": result.as_ref().err().map(ToString::to_string),
+    "output": output,
+  });
+
+  let mut file = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(log_path)?;
+  writeln!(file, "{}", entry)?;
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::record;
+  use crate::{bundle::settings::test_settings, BundleSettings};
+  use std::fs;
+
+  #[test]
+  fn appends_json_lines() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let log_path = tmp.path().join("audit.jsonl");
+    let artifact = tmp.path().join("app");
+    fs::write(&artifact, b"binary").unwrap();
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        signing_audit_log: Some(log_path.clone()),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+
+    record(
+      &settings,
+      "codesign",
+      "Developer ID",
+      &artifact,
+      None,
+      Ok(()),
+    )
+    .unwrap();
+    let failure: crate::Result<()> = Err(crate::Error::GenericError("rejected".into()));
+    assert!(record(
+      &settings,
+      "notarize",
+      "dev@example.com",
+      &artifact,
+      None,
+      failure
+    )
+    .is_err());
+
+    let log = fs::read_to_string(&log_path).unwrap();
+    let entries: Vec<serde_json::Value> = log
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["success"], true);
+    assert_eq!(
+      entries[0]["sha256"],
+      "9a3a45d01531a20e89ac6ae10b0b0beb0492acd7216a368aa062d1a5fecaf9cd"
+    );
+    assert_eq!(entries[1]["success"], false);
+    assert_eq!(entries[1]["error"], "`rejected`");
+  }
+}
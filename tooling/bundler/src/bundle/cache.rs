@@ -0,0 +1,300 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::common;
+use crate::{PackageType, Settings};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+const CACHE_FILE_NAME: &str = ".bundle-cache.json";
+
+/// Environment variables that change the bundled artifacts, e.g. the signing credentials.
+const ENV_PREFIXES: &[&str] = &["APPLE_", "TAURI_"];
+
+#[derive(Serialize, Deserialize)]
+struct Artifact {
+  path: PathBuf,
+  hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+  /// The hash of the inputs the artifacts were built from.
+  key: String,
+  artifacts: Vec<Artifact>,
+}
+
+/// The artifacts of the previous bundles, keyed by the package type short name.
+pub struct BundleCache {
+  path: PathBuf,
+  entries: BTreeMap<String, CacheEntry>,
+}
+
+impl BundleCache {
+  /// Loads the cache index of the output directory.
+  ///
+  /// A missing or unreadable index just means every package type is bundled again.
+  pub fn load(settings: &Settings) -> Self {
+    let path = settings
+      .project_out_directory()
+      .join("bundle")
+      .join(CACHE_FILE_NAME);
+    let entries = fs::read(&path)
+      .ok()
+      .and_then(|contents| serde_json::from_slice(&contents).ok())
+      .unwrap_or_default();
+    Self { path, entries }
+  }
+
+  /// Returns the artifacts of a previous bundle built from the same inputs,
+  /// if they all still exist unmodified.
+  pub fn get(&self, package_type: PackageType, key: &str) -> Option<Vec<PathBuf>> {
+    let entry = self.entries.get(package_type.short_name())?;
+    if entry.key != key || entry.artifacts.is_empty() {
+      return None;
+    }
+    for artifact in &entry.artifacts {
+      match common::hash_path(&artifact.path) {
+        Ok(hash) if hash == artifact.hash => {}
+        _ => return None,
+      }
+    }
+    Some(
+      entry
+        .artifacts
+        .iter()
+        .map(|artifact| artifact.path.clone())
+        .collect(),
+    )
+  }
+
+  /// Records the artifacts built from the inputs identified by `key` and writes the index.
+  pub fn insert(
+    &mut self,
+    package_type: PackageType,
+    key: String,
+    paths: &[PathBuf],
+  ) -> crate::Result<()> {
+    let artifacts = paths
+      .iter()
+      .map(|path| {
+        Ok(Artifact {
+          path: path.clone(),
+          hash: common::hash_path(path)?,
+        })
+      })
+      .collect::<crate::Result<_>>()?;
+    self.entries.insert(
+      package_type.short_name().to_string(),
+      CacheEntry { key, artifacts },
+    );
+    let file = common::create_file(&self.path)?;
+    serde_json::to_writer_pretty(file, &self.entries)?;
+    Ok(())
+  }
+}
+
+/// Hashes everything the bundle of the given package type is built from:
+/// the binaries, resources, icons and other referenced files, the settings and the signing environment.
+///
+/// Returns `None` when the inputs cannot be fully captured, in which case the bundle is always rebuilt.
+pub fn input_key(settings: &Settings, package_type: PackageType) -> crate::Result<Option<String>> {
+  // a transform closure can change the Info.plist in ways that cannot be hashed
+  if settings.macos().info_plist_transform.is_some() {
+    return Ok(None);
+  }
+
+  let mut hasher = sha2::Sha256::new();
+  hasher.update(env!("CARGO_PKG_VERSION"));
+  hasher.update(package_type.short_name());
+  hasher.update(settings.canonical_json()?);
+
+  let mut env_vars: Vec<_> = env::vars()
+    .filter(|(name, _)| ENV_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+    .collect();
+  env_vars.sort();
+  for (name, value) in env_vars {
+    hasher.update(format!("{}={}\0", name, value));
+  }
+
+  for path in input_files(settings)? {
+    hasher.update(path.to_string_lossy().as_bytes());
+    if path.exists() {
+      hasher.update(common::hash_path(&path)?);
+    }
+    hasher.update([0]);
+  }
+
+  Ok(Some(hex::encode(hasher.finalize())))
+}
+
+/// Every file or directory the settings reference.
+fn input_files(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
+  let mut paths: Vec<PathBuf> = settings
+    .binaries()
+    .iter()
+    .map(|bin| settings.binary_path(bin))
+    .chain(settings.prebuilt_bundle().map(PathBuf::from))
+    .collect();
+  for path in settings
+    .resource_files()
+    .chain(settings.shared_resource_files())
+  {
+    paths.push(path?);
+  }
+  for path in settings.icon_files().chain(settings.external_binaries()) {
+    paths.push(path?);
+  }
+  paths.extend(
+    settings
+      .remote_resource_files()
+      .iter()
+      .map(|(src, _)| src.clone()),
+  );
+
+  let macos = settings.macos();
+  paths.extend(
+    macos
+      .frameworks
+      .iter()
+      .flatten()
+      .chain(macos.license.iter())
+      .chain(macos.entitlements.iter())
+      .map(PathBuf::from),
+  );
+  paths.extend(
+    macos
+      .info_plist_path
+      .iter()
+      .chain(macos.icns_path.iter())
+      .chain(macos.arch_binaries.values())
+      .cloned(),
+  );
+  if let Some(crash_reporter) = &macos.crash_reporter {
+    paths.push(crash_reporter.path.clone());
+    paths.extend(crash_reporter.entitlements.iter().cloned());
+  }
+  let dmg = settings.dmg();
+  paths.extend(
+    dmg
+      .source_dir
+      .iter()
+      .chain(dmg.app_path.iter())
+      .chain(dmg.background.iter())
+      .chain(dmg.ds_store.iter())
+      .cloned(),
+  );
+  paths.extend(settings.release_notes_path().map(PathBuf::from));
+  for package_type in settings.package_types()? {
+    paths.extend(
+      settings
+        .previous_artifact(package_type)
+        .map(|previous| previous.path.clone()),
+    );
+  }
+  paths.extend(settings.deb().files.values().cloned());
+  // the key is either a key file or an ID in the keyring
+  paths.extend(
+    settings
+      .gpg()
+      .map(|gpg| PathBuf::from(&gpg.key))
+      .filter(|key| key.is_file()),
+  );
+
+  let windows = settings.windows();
+  paths.push(windows.icon_path.clone());
+  paths.extend(windows.webview_fixed_runtime_path.iter().cloned());
+  paths.extend(
+    windows
+      .key_provider
+      .iter()
+      .filter_map(|provider| provider.certificate_path.clone()),
+  );
+  if let Some(wix) = &windows.wix {
+    paths.extend(
+      wix
+        .template
+        .iter()
+        .chain(wix.fragment_paths.iter())
+        .chain(wix.license.iter())
+        .chain(wix.banner_path.iter())
+        .chain(wix.dialog_image_path.iter())
+        .cloned(),
+    );
+  }
+
+  paths.sort();
+  Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{input_key, BundleCache};
+  use crate::{bundle::settings::test_settings, BundleSettings, PackageType};
+  use std::fs;
+
+  #[test]
+  fn keys_do_not_depend_on_map_order() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    fs::write(tmp.path().join("app"), b"binary").expect("Failed to write binary");
+    // every map gets its own random order
+    let key = |value: &str| {
+      let tool_env = (0..32)
+        .map(|i| (format!("VAR_{}", i), value.to_string()))
+        .collect();
+      let bundle_settings = BundleSettings {
+        tool_env,
+        ..Default::default()
+      };
+      let settings = test_settings(tmp.path(), bundle_settings, "x86_64-unknown-linux-gnu");
+      input_key(&settings, PackageType::Deb)
+        .expect("Failed to hash inputs")
+        .expect("inputs are not cacheable")
+    };
+
+    assert_eq!(key("1"), key("1"));
+    assert_ne!(key("1"), key("2"));
+  }
+
+  #[test]
+  fn reuses_unchanged_artifacts() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let binary = tmp.path().join("app");
+    fs::write(&binary, b"binary").expect("Failed to write binary");
+    let settings = test_settings(tmp.path(), Default::default(), "x86_64-unknown-linux-gnu");
+
+    let key = input_key(&settings, PackageType::Deb)
+      .expect("Failed to hash inputs")
+      .expect("inputs are not cacheable");
+    let artifact = tmp.path().join("bundle/app.deb");
+    fs::create_dir_all(artifact.parent().unwrap()).expect("Failed to create dir");
+    fs::write(&artifact, b"package").expect("Failed to write artifact");
+
+    let mut cache = BundleCache::load(&settings);
+    cache
+      .insert(
+        PackageType::Deb,
+        key.clone(),
+        std::slice::from_ref(&artifact),
+      )
+      .expect("Failed to write cache");
+
+    let cache = BundleCache::load(&settings);
+    assert_eq!(
+      cache.get(PackageType::Deb, &key),
+      Some(vec![artifact.clone()])
+    );
+    assert_eq!(cache.get(PackageType::AppImage, &key), None);
+
+    // a modified artifact is rebuilt
+    fs::write(&artifact, b"modified").expect("Failed to write artifact");
+    assert_eq!(cache.get(PackageType::Deb, &key), None);
+
+    // and so is a bundle whose binary changed
+    fs::write(&binary, b"new binary").expect("Failed to write binary");
+    assert_ne!(input_key(&settings, PackageType::Deb).unwrap(), Some(key));
+  }
+}
@@ -0,0 +1,227 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::{common, paths};
+use crate::{Bundle, ChecksumFormat, Settings};
+use log::info;
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Writes the SHA-256 checksums of the bundle files to the bundle directory of the output directory,
+/// in each of the [`crate::BundleSettings::checksum_formats`], and returns the paths of the manifests.
+///
+/// [`crate::bundle_project`] returns them as the [`crate::PackageType::Checksums`] bundle.
+///
+/// Directory bundles like the `.app` are listed by the regular files they contain. The files copied
+/// into the bundles reuse the hashes computed while copying them, see [`common::copied_hash`].
+pub fn write_manifests(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<PathBuf>> {
+  let formats = settings.checksum_formats();
+  if formats.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  let bundle_dir = settings.project_out_directory().join("bundle");
+  let mut entries = Vec::new();
+  for path in bundles
+    .iter()
+    .flat_map(|bundle| &bundle.bundle_paths)
+    .filter(|path| path.exists())
+  {
+    for entry in walkdir::WalkDir::new(path) {
+      let entry = entry?;
+      if entry.file_type().is_file() {
+        let hash = match common::copied_hash(entry.path()) {
+          Some(hash) => hash,
+          None => common::hash_file(entry.path())?,
+        };
+        entries.push((relative_path(&bundle_dir, entry.path()), hash));
+      }
+    }
+  }
+  entries.sort();
+  entries.dedup();
+
+  let mut manifests = Vec::new();
+  for format in formats {
+    let manifest_path = paths::checksums_manifest_path(settings, *format);
+    info!(action = "Writing"; "checksums {}", manifest_path.display());
+    fs::create_dir_all(&bundle_dir)?;
+    fs::write(&manifest_path, render(*format, &entries)?)?;
+    manifests.push(manifest_path);
+  }
+  Ok(manifests)
+}
+
+// The path of a bundle file relative to the bundle directory, with forward slashes,
+// or its full path when it is outside of it.
+fn relative_path(bundle_dir: &Path, path: &Path) -> String {
+  match path.strip_prefix(bundle_dir) {
+    Ok(relative) => relative
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/"),
+    Err(_) => path.display().to_string(),
+  }
+}
+
+// Renders the (path, hash) entries in the format.
+fn render(format: ChecksumFormat, entries: &[(String, String)]) -> crate::Result<String> {
+  Ok(match format {
+    // two spaces mark the text mode, which `sha256sum -c` expects by default
+    ChecksumFormat::Sha256sum => entries
+      .iter()
+      .map(|(path, hash)| format!("{}  {}\n", hash, path))
+      .collect(),
+    ChecksumFormat::Bsd => entries
+      .iter()
+      .map(|(path, hash)| format!("SHA256 ({}) = {}\n", path, hash))
+      .collect(),
+    ChecksumFormat::Json => {
+      let files = entries
+        .iter()
+        .map(|(path, hash)| serde_json::json!({ "path": path, "sha256": hash }))
+        .collect::<Vec<_>>();
+      let mut json = serde_json::to_string_pretty(&serde_json::json!({ "files": files }))?;
+      json.push('\n');
+      json
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{render, write_manifests};
+  use crate::{
+    bundle::settings::test_settings, Bundle, BundleSettings, ChecksumFormat, PackageType,
+  };
+  use std::fs;
+
+  // Parses a manifest back into (path, hash) entries, like the checksum tools read it.
+  fn parse(format: ChecksumFormat, contents: &str) -> Vec<(String, String)> {
+    match format {
+      ChecksumFormat::Sha256sum => contents
+        .lines()
+        .map(|line| {
+          let (hash, path) = line.split_once("  ").expect("invalid sha256sum line");
+          (path.to_string(), hash.to_string())
+        })
+        .collect(),
+      ChecksumFormat::Bsd => contents
+        .lines()
+        .map(|line| {
+          let line = line.strip_prefix("SHA256 (").expect("invalid BSD line");
+          let (path, hash) = line.rsplit_once(") = ").expect("invalid BSD line");
+          (path.to_string(), hash.to_string())
+        })
+        .collect(),
+      ChecksumFormat::Json => {
+        let json: serde_json::Value = serde_json::from_str(contents).expect("invalid JSON");
+        json["files"]
+          .as_array()
+          .expect("missing files")
+          .iter()
+          .map(|file| {
+            (
+              file["path"].as_str().unwrap().to_string(),
+              file["sha256"].as_str().unwrap().to_string(),
+            )
+          })
+          .collect()
+      }
+    }
+  }
+
+  #[test]
+  fn checksum_formats_round_trip() {
+    let entries = vec![
+      ("deb/app_1.0.0_amd64.deb".to_string(), "ab".repeat(32)),
+      ("appimage/app (1).AppImage".to_string(), "cd".repeat(32)),
+    ];
+    for format in [
+      ChecksumFormat::Sha256sum,
+      ChecksumFormat::Bsd,
+      ChecksumFormat::Json,
+    ] {
+      let contents = render(format, &entries).unwrap();
+      assert_eq!(parse(format, &contents), entries, "{:?}", format);
+    }
+  }
+
+  #[test]
+  fn writes_checksum_manifests() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let deb_path = tmp.path().join("bundle/deb/app.deb");
+    fs::create_dir_all(deb_path.parent().unwrap()).unwrap();
+    fs::write(&deb_path, b"deb").unwrap();
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        checksum_formats: vec![ChecksumFormat::Sha256sum, ChecksumFormat::Bsd],
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+
+    let bundles = [Bundle {
+      package_type: PackageType::Deb,
+      bundle_paths: vec![deb_path],
+    }];
+    let manifests = write_manifests(&settings, &bundles).unwrap();
+    assert_eq!(
+      manifests,
+      vec![
+        tmp.path().join("bundle/SHA256SUMS"),
+        tmp.path().join("bundle/SHA256SUMS.bsd")
+      ]
+    );
+    // sha256 of "deb"
+    assert_eq!(
+      fs::read_to_string(&manifests[0]).unwrap(),
+      "9cfa1468c93fc18652e34a000f0c6614b0fa18f6f4887477ad9b0d36ca6a7eaa  deb/app.deb\n"
+    );
+  }
+
+  #[test]
+  fn lists_the_files_of_directory_bundles() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let app_path = tmp.path().join("bundle/macos/app.app");
+    fs::create_dir_all(app_path.join("Contents/MacOS")).unwrap();
+    fs::write(app_path.join("Contents/MacOS/app"), b"deb").unwrap();
+    fs::write(app_path.join("Contents/Info.plist"), b"deb").unwrap();
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        checksum_formats: vec![ChecksumFormat::Sha256sum],
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+
+    let bundles = [Bundle {
+      package_type: PackageType::MacOsBundle,
+      bundle_paths: vec![app_path],
+    }];
+    let manifests = write_manifests(&settings, &bundles).unwrap();
+    assert_eq!(
+      parse(
+        ChecksumFormat::Sha256sum,
+        &fs::read_to_string(&manifests[0]).unwrap()
+      ),
+      vec![
+        (
+          "macos/app.app/Contents/Info.plist".to_string(),
+          "9cfa1468c93fc18652e34a000f0c6614b0fa18f6f4887477ad9b0d36ca6a7eaa".to_string()
+        ),
+        (
+          "macos/app.app/Contents/MacOS/app".to_string(),
+          "9cfa1468c93fc18652e34a000f0c6614b0fa18f6f4887477ad9b0d36ca6a7eaa".to_string()
+        ),
+      ]
+    );
+  }
+}
@@ -3,17 +3,130 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
+use crate::{CancellationToken, CopyLimits, Settings};
 use log::debug;
 
 use std::{
+  cell::RefCell,
+  collections::HashMap,
   ffi::OsStr,
   fs::{self, File},
-  io::{self, BufReader, BufWriter},
-  path::Path,
-  process::{Command, Output, Stdio},
+  io::{self, BufReader, BufWriter, Write},
+  path::{Path, PathBuf},
+  process::{Child, Command, Output, Stdio},
   sync::{Arc, Mutex},
+  time::{Duration, SystemTime},
 };
 
+/// The settings of the external tools and directory copies of a bundling run, see [`enter`].
+#[derive(Default)]
+struct ToolContext {
+  /// The environment variables set for the external tools, see [`crate::Settings::tool_env`].
+  env: HashMap<String, String>,
+  /// The token cancelling the tools, see [`crate::BundleSettings::cancellation`].
+  cancellation: Option<CancellationToken>,
+  /// The paths the tools are run from instead of `PATH`, see [`crate::Settings::tool_paths`].
+  paths: HashMap<&'static str, PathBuf>,
+  /// The limits of the copied directories and resources, see [`crate::BundleSettings::copy_limits`].
+  copy_limits: CopyLimits,
+  /// Whether the copied files are hashed, when [`crate::BundleSettings::checksum_formats`] is set.
+  hash_copies: bool,
+  /// The hashes of the copied files with the size and modification time they had, see [`copied_hash`].
+  copied_hashes: HashMap<PathBuf, (u64, SystemTime, String)>,
+}
+
+thread_local! {
+  static TOOL_CONTEXT: RefCell<ToolContext> = RefCell::new(ToolContext::default());
+}
+
+/// Restores the tool context the thread had before [`enter`] when it is dropped.
+#[must_use = "the tool context is reset when the guard is dropped"]
+pub struct ToolContextGuard {
+  previous: Option<ToolContext>,
+}
+
+impl Drop for ToolContextGuard {
+  fn drop(&mut self) {
+    if let Some(previous) = self.previous.take() {
+      TOOL_CONTEXT.with(|context| *context.borrow_mut() = previous);
+    }
+  }
+}
+
+/// Applies the tool environment, cancellation token, tool paths and copy limits of the settings
+/// to the tools run and the files copied on this thread, until the returned guard is dropped.
+///
+/// When checksums are written, the copied files are also hashed while they are copied, see [`copied_hash`].
+///
+/// The context is per thread, so a caller bundling from several threads enters it on each of them.
+pub fn enter(settings: &Settings) -> crate::Result<ToolContextGuard> {
+  let paths = settings.tool_paths();
+  for (tool, path) in &paths {
+    if !is_executable(path) {
+      return Err(crate::Error::GenericError(format!(
+        "the {} path {} is not an executable file",
+        tool,
+        path.display()
+      )));
+    }
+  }
+  let context = ToolContext {
+    env: settings.tool_env().clone(),
+    cancellation: settings.cancellation().cloned(),
+    paths,
+    copy_limits: settings.copy_limits(),
+    hash_copies: !settings.checksum_formats().is_empty(),
+    copied_hashes: HashMap::new(),
+  };
+  let previous = TOOL_CONTEXT.with(|current| current.replace(context));
+  Ok(ToolContextGuard {
+    previous: Some(previous),
+  })
+}
+
+/// Returns the path of the tool pinned with [`enter`], or its name to look it up on `PATH`.
+#[allow(dead_code)]
+pub fn tool_path(tool: &str) -> PathBuf {
+  TOOL_CONTEXT.with(|context| {
+    context
+      .borrow()
+      .paths
+      .get(tool)
+      .cloned()
+      .unwrap_or_else(|| PathBuf::from(tool))
+  })
+}
+
+/// Creates a command running the tool from its [`tool_path`].
+#[allow(dead_code)]
+pub fn tool_command(tool: &str) -> Command {
+  Command::new(tool_path(tool))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+  use std::os::unix::fs::PermissionsExt;
+  fs::metadata(path).map_or(false, |metadata| {
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+  })
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+  path.is_file()
+}
+
+/// Returns the limits set with [`enter`] for the copied directories and resources.
+pub fn copy_limits() -> CopyLimits {
+  TOOL_CONTEXT.with(|context| context.borrow().copy_limits)
+}
+
+/// Returns the environment variables set with [`enter`] for the external tools.
+#[allow(dead_code)]
+pub fn tool_env() -> HashMap<String, String> {
+  TOOL_CONTEXT.with(|context| context.borrow().env.clone())
+}
+
 /// Returns true if the path has a filename indicating that it is a high-density
 /// "retina" icon.  Specifically, returns true the file stem ends with
 /// "@2x" (a convention specified by the [Apple developer docs](
@@ -84,14 +197,13 @@ pub fn copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> crate::Result<
   }
   let dest_dir = to.parent().expect("No data in parent");
   fs::create_dir_all(dest_dir)?;
-  fs::copy(from, to)?;
-  Ok(())
+  copy_regular_file(from, to)
 }
 
 /// Recursively copies a directory file from one path to another, creating any
 /// parent directories of the destination path as necessary.  Fails if the
 /// source path is not a directory or doesn't exist, or if the destination path
-/// already exists.
+/// already exists, or if the directory exceeds the limits set with [`enter`].
 #[allow(dead_code)]
 pub fn copy_dir(from: &Path, to: &Path) -> crate::Result<()> {
   if !from.exists() {
@@ -114,11 +226,20 @@ pub fn copy_dir(from: &Path, to: &Path) -> crate::Result<()> {
   }
   let parent = to.parent().expect("No data in parent");
   fs::create_dir_all(parent)?;
+  let limits = copy_limits();
+  let (mut files, mut size) = (0u64, 0u64);
   for entry in walkdir::WalkDir::new(from) {
     let entry = entry?;
     debug_assert!(entry.path().starts_with(from));
     let rel_path = entry.path().strip_prefix(from)?;
     let dest_path = to.join(rel_path);
+    if !entry.file_type().is_dir() {
+      files += 1;
+      if entry.file_type().is_file() {
+        size += entry.metadata()?.len();
+      }
+      check_copy_limits(&limits, &from.display().to_string(), files, size)?;
+    }
     if entry.file_type().is_symlink() {
       let target = fs::read_link(entry.path())?;
       if entry.path().is_dir() {
@@ -129,83 +250,335 @@ pub fn copy_dir(from: &Path, to: &Path) -> crate::Result<()> {
     } else if entry.file_type().is_dir() {
       fs::create_dir(dest_path)?;
     } else {
+      copy_regular_file(entry.path(), &dest_path)?;
+    }
+  }
+  Ok(())
+}
+
+// Copies a file with `fs::copy`, or through a SHA-256 hasher when the copies are hashed with [`enter`].
+fn copy_regular_file(from: &Path, to: &Path) -> crate::Result<()> {
+  if TOOL_CONTEXT.with(|context| context.borrow().hash_copies) {
+    let hash = copy_file_hashed(from, to)?;
+    let metadata = fs::metadata(to)?;
+    let key = (metadata.len(), metadata.modified()?, hash);
+    TOOL_CONTEXT.with(|context| {
+      context
+        .borrow_mut()
+        .copied_hashes
+        .insert(to.to_path_buf(), key)
+    });
+  } else {
+    fs::copy(from, to)?;
+  }
+  Ok(())
+}
+
+// Copies a file through a SHA-256 hasher, keeping its permissions like `fs::copy`.
+fn copy_file_hashed(from: &Path, to: &Path) -> crate::Result<String> {
+  use sha2::Digest;
+
+  struct HashingWriter<W> {
+    inner: W,
+    hasher: sha2::Sha256,
+  }
+
+  impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      let written = self.inner.write(buf)?;
+      self.hasher.update(&buf[..written]);
+      Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      self.inner.flush()
+    }
+  }
+
+  let mut writer = HashingWriter {
+    inner: BufWriter::new(File::create(to)?),
+    hasher: sha2::Sha256::new(),
+  };
+  io::copy(&mut File::open(from)?, &mut writer)?;
+  writer.flush()?;
+  drop(writer.inner);
+  fs::set_permissions(to, fs::metadata(from)?.permissions())?;
+  Ok(hex::encode(writer.hasher.finalize()))
+}
+
+/// Returns the hash of a file copied by [`copy_file`] or [`copy_dir`] since [`enter`],
+/// unless its size or modification time changed after the copy, e.g. when it was signed.
+pub fn copied_hash(path: &Path) -> Option<String> {
+  let metadata = fs::metadata(path).ok()?;
+  let modified = metadata.modified().ok()?;
+  TOOL_CONTEXT.with(|context| {
+    context
+      .borrow()
+      .copied_hashes
+      .get(path)
+      .filter(|(len, time, _)| *len == metadata.len() && *time == modified)
+      .map(|(_, _, hash)| hash.clone())
+  })
+}
+
+/// Fails when the files copied so far from the source, a directory or resource pattern, exceed the limits.
+pub fn check_copy_limits(
+  limits: &CopyLimits,
+  source: &str,
+  files: u64,
+  size: u64,
+) -> crate::Result<()> {
+  if let Some(max_files) = limits.max_files.filter(|max| files > *max) {
+    return Err(crate::Error::GenericError(format!(
+      "{} contains more than {} files, check that the resource globs do not match more than intended",
+      source,
+      max_files
+    )));
+  }
+  if let Some(max_size) = limits.max_size.filter(|max| size > *max) {
+    return Err(crate::Error::GenericError(format!(
+      "{} contains more than {} bytes of files, check that the resource globs do not match more than intended",
+      source,
+      max_size
+    )));
+  }
+  Ok(())
+}
+
+/// Recursively mirrors a directory like [`copy_dir`], but hard links the regular files
+/// instead of copying them when the file system allows it.
+///
+/// The linked files share their contents with the source, so they must be replaced
+/// instead of modified in place.
+#[allow(dead_code)]
+pub fn hard_link_dir(from: &Path, to: &Path) -> crate::Result<()> {
+  if !from.is_dir() {
+    return Err(crate::Error::GenericError(format!(
+      "{:?} is not a Directory",
+      from
+    )));
+  }
+  if to.exists() {
+    return Err(crate::Error::GenericError(format!(
+      "{:?} already exists",
+      to
+    )));
+  }
+  for entry in walkdir::WalkDir::new(from) {
+    let entry = entry?;
+    let rel_path = entry.path().strip_prefix(from)?;
+    let dest_path = to.join(rel_path);
+    if entry.file_type().is_symlink() {
+      let target = fs::read_link(entry.path())?;
+      if entry.path().is_dir() {
+        symlink_dir(&target, &dest_path)?;
+      } else {
+        symlink_file(&target, &dest_path)?;
+      }
+    } else if entry.file_type().is_dir() {
+      fs::create_dir_all(dest_path)?;
+    } else if fs::hard_link(entry.path(), &dest_path).is_err() {
       fs::copy(entry.path(), dest_path)?;
     }
   }
   Ok(())
 }
 
+/// Computes the SHA-256 hash of a file or directory, returned as a lowercase hex string.
+///
+/// Directories are walked in file name order and every entry contributes its relative path
+/// and its contents (or its target, for symbolic links), so the hash changes whenever
+/// anything inside the directory changes.
+#[allow(dead_code)]
+pub fn hash_path(path: &Path) -> crate::Result<String> {
+  use sha2::Digest;
+
+  let mut hasher = sha2::Sha256::new();
+  for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+    let entry = entry?;
+    let rel_path = entry.path().strip_prefix(path)?;
+    hasher.update(rel_path.to_string_lossy().as_bytes());
+    hasher.update([0]);
+    if entry.file_type().is_symlink() {
+      let target = fs::read_link(entry.path())?;
+      hasher.update(target.to_string_lossy().as_bytes());
+    } else if entry.file_type().is_file() {
+      let mut file = File::open(entry.path())?;
+      io::copy(&mut file, &mut hasher)?;
+    }
+    hasher.update([0]);
+  }
+  Ok(hex::encode(hasher.finalize()))
+}
+
+/// Computes the hex-encoded SHA-256 hash of a file's contents.
+pub fn hash_file(path: &Path) -> crate::Result<String> {
+  use sha2::Digest;
+
+  let mut hasher = sha2::Sha256::new();
+  io::copy(&mut File::open(path)?, &mut hasher)?;
+  Ok(hex::encode(hasher.finalize()))
+}
+
+/// The command line of the command as it is logged, with the secrets replaced by `<redacted>`.
+pub fn command_line(command: &Command, secrets: &[&str]) -> String {
+  let mut line = command.get_program().to_string_lossy().into_owned();
+  for arg in command.get_args() {
+    let mut arg = arg.to_string_lossy().into_owned();
+    for secret in secrets.iter().filter(|secret| !secret.is_empty()) {
+      arg = arg.replace(secret, "<redacted>");
+    }
+    line.push(' ');
+    line.push_str(&arg);
+  }
+  line
+}
+
 pub trait CommandExt {
   fn output_ok(&mut self) -> crate::Result<Output>;
+  /// Runs the command like [`CommandExt::output_ok`], but replaces the secrets passed in its arguments
+  /// with `<redacted>` in the logged command line.
+  fn output_ok_redacted(&mut self, secrets: &[&str]) -> crate::Result<Output>;
+  /// Runs the command like [`CommandExt::output_ok`], but returns its output even if it failed.
+  fn output_unchecked(&mut self) -> crate::Result<Output>;
 }
 
 impl CommandExt for Command {
   fn output_ok(&mut self) -> crate::Result<Output> {
-    let program = self.get_program().to_string_lossy().into_owned();
-    debug!(action = "Running"; "Command `{} {}`", program, self.get_args().map(|arg| arg.to_string_lossy()).fold(String::new(), |acc, arg| format!("{} {}", acc, arg)));
+    self.output_ok_redacted(&[])
+  }
+
+  fn output_ok_redacted(&mut self, secrets: &[&str]) -> crate::Result<Output> {
+    let output = run(self, secrets)?;
+    if output.status.success() {
+      Ok(output)
+    } else {
+      Err(crate::Error::GenericError(format!(
+        "failed to run {}",
+        self.get_program().to_string_lossy()
+      )))
+    }
+  }
+
+  fn output_unchecked(&mut self) -> crate::Result<Output> {
+    run(self, &[])
+  }
+}
 
-    self.stdout(Stdio::piped());
-    self.stderr(Stdio::piped());
+fn run(command: &mut Command, secrets: &[&str]) -> crate::Result<Output> {
+  TOOL_CONTEXT.with(|context| {
+    command.envs(context.borrow().env.iter());
+  });
+  debug!(action = "Running"; "Command `{}`", command_line(command, secrets));
 
-    let mut child = self.spawn()?;
+  command.stdout(Stdio::piped());
+  command.stderr(Stdio::piped());
 
-    let mut stdout = child.stdout.take().map(BufReader::new).unwrap();
-    let stdout_lines = Arc::new(Mutex::new(Vec::new()));
-    let stdout_lines_ = stdout_lines.clone();
-    std::thread::spawn(move || {
-      let mut buf = Vec::new();
-      let mut lines = stdout_lines_.lock().unwrap();
-      loop {
-        buf.clear();
-        match tauri_utils::io::read_line(&mut stdout, &mut buf) {
-          Ok(s) if s == 0 => break,
-          _ => (),
+  let cancellation = TOOL_CONTEXT.with(|context| context.borrow().cancellation.clone());
+  if cancellation.as_ref().map_or(false, |c| c.is_cancelled()) {
+    return Err(crate::Error::Cancelled);
+  }
+  // A cancellable tool runs in its own process group, so the processes it starts are killed with it.
+  #[cfg(unix)]
+  if cancellation.is_some() {
+    use std::os::unix::process::CommandExt as _;
+    unsafe {
+      command.pre_exec(|| {
+        if libc::setpgid(0, 0) == 0 {
+          Ok(())
+        } else {
+          Err(io::Error::last_os_error())
         }
-        debug!(action = "stdout"; "{}", String::from_utf8_lossy(&buf));
-        lines.extend(buf.clone());
-        lines.push(b'\n');
+      });
+    }
+  }
+
+  let mut child = command.spawn()?;
+
+  let mut stdout = child.stdout.take().map(BufReader::new).unwrap();
+  let stdout_lines = Arc::new(Mutex::new(Vec::new()));
+  let stdout_lines_ = stdout_lines.clone();
+  std::thread::spawn(move || {
+    let mut buf = Vec::new();
+    let mut lines = stdout_lines_.lock().unwrap();
+    loop {
+      buf.clear();
+      match tauri_utils::io::read_line(&mut stdout, &mut buf) {
+        Ok(s) if s == 0 => break,
+        _ => (),
       }
-    });
+      debug!(action = "stdout"; "{}", String::from_utf8_lossy(&buf));
+      lines.extend(buf.clone());
+      lines.push(b'\n');
+    }
+  });
 
-    let mut stderr = child.stderr.take().map(BufReader::new).unwrap();
-    let stderr_lines = Arc::new(Mutex::new(Vec::new()));
-    let stderr_lines_ = stderr_lines.clone();
-    std::thread::spawn(move || {
-      let mut buf = Vec::new();
-      let mut lines = stderr_lines_.lock().unwrap();
-      loop {
-        buf.clear();
-        match tauri_utils::io::read_line(&mut stderr, &mut buf) {
-          Ok(s) if s == 0 => break,
-          _ => (),
-        }
-        debug!(action = "stderr"; "{}", String::from_utf8_lossy(&buf));
-        lines.extend(buf.clone());
-        lines.push(b'\n');
+  let mut stderr = child.stderr.take().map(BufReader::new).unwrap();
+  let stderr_lines = Arc::new(Mutex::new(Vec::new()));
+  let stderr_lines_ = stderr_lines.clone();
+  std::thread::spawn(move || {
+    let mut buf = Vec::new();
+    let mut lines = stderr_lines_.lock().unwrap();
+    loop {
+      buf.clear();
+      match tauri_utils::io::read_line(&mut stderr, &mut buf) {
+        Ok(s) if s == 0 => break,
+        _ => (),
       }
-    });
+      debug!(action = "stderr"; "{}", String::from_utf8_lossy(&buf));
+      lines.extend(buf.clone());
+      lines.push(b'\n');
+    }
+  });
 
-    let status = child.wait()?;
-    let output = Output {
-      status,
-      stdout: std::mem::take(&mut *stdout_lines.lock().unwrap()),
-      stderr: std::mem::take(&mut *stderr_lines.lock().unwrap()),
-    };
+  let status = match &cancellation {
+    Some(cancellation) => loop {
+      if let Some(status) = child.try_wait()? {
+        break status;
+      }
+      if cancellation.is_cancelled() {
+        kill_tree(&mut child)?;
+        child.wait()?;
+        return Err(crate::Error::Cancelled);
+      }
+      std::thread::sleep(Duration::from_millis(100));
+    },
+    None => child.wait()?,
+  };
+  let output = Output {
+    status,
+    stdout: std::mem::take(&mut *stdout_lines.lock().unwrap()),
+    stderr: std::mem::take(&mut *stderr_lines.lock().unwrap()),
+  };
+  Ok(output)
+}
 
-    if output.status.success() {
-      Ok(output)
-    } else {
-      Err(crate::Error::GenericError(format!(
-        "failed to run {}",
-        program
-      )))
+/// Kills the child and the processes it started, e.g. the `hdiutil` calls of the DMG script.
+fn kill_tree(child: &mut Child) -> io::Result<()> {
+  #[cfg(unix)]
+  {
+    // the child leads its own process group, see `run`
+    let group = -(child.id() as libc::pid_t);
+    if unsafe { libc::kill(group, libc::SIGKILL) } == 0 {
+      return Ok(());
+    }
+  }
+  #[cfg(windows)]
+  {
+    let killed = Command::new("taskkill")
+      .args(["/T", "/F", "/PID", &child.id().to_string()])
+      .output()
+      .map_or(false, |output| output.status.success());
+    if killed {
+      return Ok(());
     }
   }
+  child.kill()
 }
 
 #[cfg(test)]
 mod tests {
-  use super::{create_file, is_retina};
+  use super::{create_file, hash_path, is_retina};
   use std::{io::Write, path::PathBuf};
   use tauri_utils::resources::resource_relpath;
 
@@ -273,6 +646,123 @@ mod tests {
     );
   }
 
+  #[test]
+  fn copy_dir_hashes_the_copies_for_checksums() {
+    use super::{copied_hash, copy_dir, enter, hash_file};
+    use crate::{bundle::settings::test_settings, BundleSettings, ChecksumFormat};
+
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let orig = tmp.path().join("orig");
+    std::fs::create_dir_all(orig.join("sub")).expect("Failed to create dir");
+    std::fs::write(orig.join("sub/file.txt"), b"Hello, world!\n").expect("Failed to write file");
+    std::fs::write(orig.join("empty"), b"").expect("Failed to write file");
+
+    // the fast path does not hash
+    copy_dir(&orig, &tmp.path().join("plain")).expect("Failed to copy dir");
+    assert_eq!(copied_hash(&tmp.path().join("plain/empty")), None);
+
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        checksum_formats: vec![ChecksumFormat::Sha256sum],
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+    let _tool_context = enter(&settings).expect("Failed to set the tool context");
+    let copy = tmp.path().join("copy");
+    copy_dir(&orig, &copy).expect("Failed to copy dir");
+    for rel_path in ["empty", "sub/file.txt"] {
+      assert_eq!(
+        std::fs::read(orig.join(rel_path)).expect("Failed to read file"),
+        std::fs::read(copy.join(rel_path)).expect("Failed to read file")
+      );
+      assert_eq!(
+        copied_hash(&copy.join(rel_path)),
+        Some(hash_file(&copy.join(rel_path)).expect("Failed to hash file"))
+      );
+    }
+
+    // a file modified after the copy is hashed again
+    std::fs::write(copy.join("sub/file.txt"), b"changed").expect("Failed to write file");
+    assert_eq!(copied_hash(&copy.join("sub/file.txt")), None);
+  }
+
+  #[test]
+  fn copy_dir_enforces_limits() {
+    use super::{copy_dir, enter};
+    use crate::{bundle::settings::test_settings, BundleSettings, CopyLimits};
+
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    for name in ["a.txt", "sub/b.txt"] {
+      let mut file =
+        create_file(&tmp.path().join("orig").join(name)).expect("Unable to create file");
+      file.write_all(b"1234").expect("Unable to write to file");
+    }
+    let limits = |max_files, max_size| {
+      let settings = test_settings(
+        tmp.path(),
+        BundleSettings {
+          copy_limits: CopyLimits {
+            max_files,
+            max_size,
+          },
+          ..Default::default()
+        },
+        "x86_64-unknown-linux-gnu",
+      );
+      enter(&settings).expect("Failed to set the tool context")
+    };
+
+    let _limits = limits(Some(1), None);
+    let error = copy_dir(&tmp.path().join("orig"), &tmp.path().join("files")).unwrap_err();
+    assert!(error
+      .to_string()
+      .contains("orig contains more than 1 files"));
+
+    let _limits = limits(None, Some(7));
+    let error = copy_dir(&tmp.path().join("orig"), &tmp.path().join("size")).unwrap_err();
+    assert!(error.to_string().contains("more than 7 bytes"));
+
+    let _limits = limits(Some(2), Some(8));
+    copy_dir(&tmp.path().join("orig"), &tmp.path().join("copy")).expect("within the limits");
+  }
+
+  #[test]
+  fn hash_path_tracks_contents() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let dir = tmp.path().join("app");
+    std::fs::create_dir_all(dir.join("sub")).expect("Failed to create dir");
+    std::fs::write(dir.join("sub/file.txt"), b"Hello").expect("Failed to write file");
+
+    let first = hash_path(&dir).expect("Failed to hash dir");
+    assert_eq!(first, hash_path(&dir).expect("Failed to hash dir"));
+
+    std::fs::write(dir.join("sub/file.txt"), b"Hello, world!").expect("Failed to write file");
+    let second = hash_path(&dir).expect("Failed to hash dir");
+    assert_ne!(first, second);
+
+    std::fs::rename(dir.join("sub/file.txt"), dir.join("sub/other.txt"))
+      .expect("Failed to rename file");
+    assert_ne!(second, hash_path(&dir).expect("Failed to hash dir"));
+  }
+
+  #[test]
+  fn redacts_secrets_in_the_command_line() {
+    use super::command_line;
+
+    let mut command = std::process::Command::new("signtool");
+    command.args(["sign", "/kc", "[{{1234}}]=container", "app.exe"]);
+    assert_eq!(
+      command_line(&command, &["1234", ""]),
+      "signtool sign /kc [{{<redacted>}}]=container app.exe"
+    );
+    assert_eq!(
+      command_line(&command, &[]),
+      "signtool sign /kc [{{1234}}]=container app.exe"
+    );
+  }
+
   #[test]
   fn retina_icon_paths() {
     assert!(!is_retina("data/icons/512x512.png"));
@@ -294,4 +784,107 @@ mod tests {
       PathBuf::from("_root_/home/ferris/crab.png")
     );
   }
+
+  #[cfg(unix)]
+  #[test]
+  fn cancellation_kills_the_tool() {
+    use super::{enter, CommandExt};
+    use crate::{bundle::settings::test_settings, BundleSettings, CancellationToken};
+
+    let token = CancellationToken::new();
+    let settings = test_settings(
+      "target",
+      BundleSettings {
+        cancellation: Some(token.clone()),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+    let _tool_context = enter(&settings).expect("Failed to set the tool context");
+    let canceller = token.clone();
+    std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(200));
+      canceller.cancel();
+    });
+
+    let started = std::time::Instant::now();
+    let result = std::process::Command::new("sleep").arg("10").output_ok();
+    assert!(matches!(result, Err(crate::Error::Cancelled)));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn cancellation_kills_the_started_processes() {
+    use super::{enter, CommandExt};
+    use crate::{bundle::settings::test_settings, BundleSettings, CancellationToken};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let pid_file = tmp.path().join("pid");
+    let token = CancellationToken::new();
+    let settings = test_settings(
+      "target",
+      BundleSettings {
+        cancellation: Some(token.clone()),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+    let _tool_context = enter(&settings).expect("Failed to set the tool context");
+    let canceller = token.clone();
+    std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(300));
+      canceller.cancel();
+    });
+
+    let result = std::process::Command::new("sh")
+      .arg("-c")
+      .arg(format!("sleep 30 & echo $! > {}; wait", pid_file.display()))
+      .output_ok();
+    assert!(matches!(result, Err(crate::Error::Cancelled)));
+
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    let stat = format!("/proc/{}/stat", pid.trim());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    // the killed process is gone or a zombie waiting for its reparented parent
+    let running = std::fs::read_to_string(stat).map_or(false, |stat| !stat.contains(") Z "));
+    assert!(!running, "the process started by the tool was not killed");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn pinned_tool_paths() {
+    use super::{enter, tool_env, tool_path};
+    use crate::{bundle::settings::test_settings, BundleSettings, MacOsSettings};
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().expect("Unable to create temp dir");
+    let tool = tmp.path().join("codesign");
+    std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        macos: MacOsSettings {
+          codesign_path: Some(tool.clone()),
+          ..Default::default()
+        },
+        tool_env: [("LANG".to_string(), "C".to_string())].into(),
+        ..Default::default()
+      },
+      "x86_64-apple-darwin",
+    );
+    assert!(enter(&settings).is_err());
+    assert_eq!(tool_path("codesign"), PathBuf::from("codesign"));
+
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+    {
+      let _tool_context = enter(&settings).expect("the tool is executable");
+      assert_eq!(tool_path("codesign"), tool);
+      assert_eq!(tool_path("hdiutil"), PathBuf::from("hdiutil"));
+      assert_eq!(tool_env()["LANG"], "C");
+    }
+    // the previous context is restored with the guard
+    assert_eq!(tool_path("codesign"), PathBuf::from("codesign"));
+    assert!(tool_env().is_empty());
+  }
 }
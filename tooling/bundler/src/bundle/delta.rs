@@ -0,0 +1,230 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::{common, paths};
+use crate::{PackageType, Settings};
+use anyhow::Context;
+use log::info;
+use serde::Serialize;
+use zstd::zstd_safe::{self, CCtx, CParameter, DCtx, DParameter, InBuffer, OutBuffer};
+
+use std::{
+  fs,
+  io::Write,
+  path::{Path, PathBuf},
+};
+
+/// The format of the deltas: the new artifact compressed by zstd with the previous artifact as its reference prefix,
+/// like `zstd --patch-from`.
+const DELTA_FORMAT: &str = "zstd-patch-from";
+/// zstd's highest regular compression level.
+const COMPRESSION_LEVEL: i32 = 19;
+/// The largest zstd window on 64-bit platforms, which limits the artifacts to 2GB.
+const MAX_WINDOW_LOG: u32 = 31;
+
+/// The metadata file written next to a delta, describing the artifacts it converts between.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeltaMetadata {
+  format: &'static str,
+  /// The zstd window log the delta must be applied with, see [`apply_delta`].
+  window_log: u32,
+  from: FileMetadata,
+  to: FileMetadata,
+  delta: FileMetadata,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileMetadata {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  version: Option<String>,
+  file_name: String,
+  size: u64,
+  sha256: String,
+}
+
+/// Creates the delta of the first bundle path against the [`crate::BundleSettings::previous_artifacts`] entry
+/// of the package type, if any, and returns the bundle paths followed by the delta and its metadata file.
+///
+/// The delta is applied to the previous artifact before it is written, so a delta that does not recreate the bundle fails the build.
+pub fn create(
+  settings: &Settings,
+  package_type: PackageType,
+  mut bundle_paths: Vec<PathBuf>,
+) -> crate::Result<Vec<PathBuf>> {
+  let previous = match settings.previous_artifact(package_type) {
+    Some(previous) => previous,
+    None => return Ok(bundle_paths),
+  };
+  let artifact = match bundle_paths.first().filter(|path| path.is_file()) {
+    Some(artifact) => artifact.clone(),
+    None => {
+      return Err(crate::Error::GenericError(format!(
+        "a delta package needs a file, the {} bundle is a directory",
+        package_type.short_name()
+      )))
+    }
+  };
+
+  let delta_path = paths::delta_path(&artifact, &previous.version);
+  info!(action = "Creating"; "delta package {}", delta_path.display());
+
+  let old = fs::read(&previous.path)
+    .with_context(|| format!("Failed to read the previous artifact {:?}", previous.path))?;
+  let new = fs::read(&artifact)?;
+  let window_log = window_log(old.len().max(new.len()))?;
+  let delta = diff(&old, &new, window_log)?;
+  if apply_delta(&old, &delta, window_log)? != new {
+    return Err(crate::Error::GenericError(format!(
+      "the delta package {} does not recreate {}",
+      delta_path.display(),
+      artifact.display()
+    )));
+  }
+  fs::write(&delta_path, &delta)?;
+
+  let metadata = DeltaMetadata {
+    format: DELTA_FORMAT,
+    window_log,
+    from: file_metadata(&previous.path, Some(previous.version.clone()))?,
+    to: file_metadata(&artifact, Some(settings.version_string().to_string()))?,
+    delta: file_metadata(&delta_path, None)?,
+  };
+  let metadata_path = paths::delta_metadata_path(&delta_path);
+  let mut file = common::create_file(&metadata_path)?;
+  serde_json::to_writer_pretty(&mut file, &metadata)?;
+  file.flush()?;
+
+  bundle_paths.push(delta_path);
+  bundle_paths.push(metadata_path);
+  Ok(bundle_paths)
+}
+
+/// Recreates an artifact from the previous artifact and a delta created by the bundler,
+/// with the `windowLog` of the delta metadata.
+///
+/// The artifact is decoded in chunks, so the size recorded in the delta is checked rather than trusted
+/// to reserve memory.
+pub fn apply_delta(old: &[u8], delta: &[u8], window_log: u32) -> crate::Result<Vec<u8>> {
+  let size = zstd_safe::get_frame_content_size(delta);
+  if size == zstd_safe::CONTENTSIZE_UNKNOWN || size == zstd_safe::CONTENTSIZE_ERROR {
+    return Err(crate::Error::GenericError(
+      "invalid delta, it does not record the size of the artifact".into(),
+    ));
+  }
+  let mut dctx: DCtx<'_> = DCtx::create();
+  dctx
+    .set_parameter(DParameter::WindowLogMax(window_log))
+    .map_err(zstd_error)?;
+  dctx.ref_prefix(old).map_err(zstd_error)?;
+  let mut new = Vec::new();
+  let mut chunk = vec![0; DCtx::out_size()];
+  let mut input = InBuffer::around(delta);
+  loop {
+    let (remaining, written) = {
+      let mut output = OutBuffer::around(&mut chunk[..]);
+      let remaining = dctx
+        .decompress_stream(&mut output, &mut input)
+        .map_err(zstd_error)?;
+      (remaining, output.pos())
+    };
+    new.extend_from_slice(&chunk[..written]);
+    if new.len() as u64 > size {
+      return Err(crate::Error::GenericError(format!(
+        "invalid delta, the artifact is larger than the {} bytes it records",
+        size
+      )));
+    }
+    if remaining == 0 {
+      break;
+    }
+    if written == 0 && input.pos() == delta.len() {
+      return Err(crate::Error::GenericError(
+        "invalid delta, it is truncated".into(),
+      ));
+    }
+  }
+  if new.len() as u64 != size {
+    return Err(crate::Error::GenericError(format!(
+      "invalid delta, the artifact has {} bytes instead of the {} bytes it records",
+      new.len(),
+      size
+    )));
+  }
+  Ok(new)
+}
+
+// Compresses the new artifact with the old one as reference, so content they share is stored as matches.
+fn diff(old: &[u8], new: &[u8], window_log: u32) -> crate::Result<Vec<u8>> {
+  let mut cctx: CCtx<'_> = CCtx::create();
+  for parameter in [
+    CParameter::CompressionLevel(COMPRESSION_LEVEL),
+    CParameter::WindowLog(window_log),
+    CParameter::EnableLongDistanceMatching(true),
+  ] {
+    cctx.set_parameter(parameter).map_err(zstd_error)?;
+  }
+  cctx.ref_prefix(old).map_err(zstd_error)?;
+  let mut delta = Vec::with_capacity(zstd_safe::compress_bound(new.len()));
+  cctx.compress2(&mut delta, new).map_err(zstd_error)?;
+  Ok(delta)
+}
+
+// The smallest window covering the larger artifact, so matches can reference all of the old one.
+fn window_log(size: usize) -> crate::Result<u32> {
+  let bits = usize::BITS - size.saturating_sub(1).leading_zeros();
+  if bits > MAX_WINDOW_LOG {
+    return Err(crate::Error::GenericError(
+      "delta packages are limited to artifacts smaller than 2GB".into(),
+    ));
+  }
+  Ok(bits.max(10))
+}
+
+fn file_metadata(path: &Path, version: Option<String>) -> crate::Result<FileMetadata> {
+  Ok(FileMetadata {
+    version,
+    file_name: path
+      .file_name()
+      .expect("failed to get file name")
+      .to_string_lossy()
+      .into_owned(),
+    size: fs::metadata(path)?.len(),
+    sha256: common::hash_file(path)?,
+  })
+}
+
+fn zstd_error(code: usize) -> crate::Error {
+  crate::Error::GenericError(format!(
+    "failed to create the delta: {}",
+    zstd_safe::get_error_name(code)
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{apply_delta, diff, window_log};
+
+  #[test]
+  fn delta_recreates_the_artifact() {
+    let old: Vec<u8> = (0..200_000u32)
+      .flat_map(|i| (i * 7919).to_le_bytes())
+      .collect();
+    let mut new = old.clone();
+    new[1000..1010].copy_from_slice(b"new header");
+    new.extend_from_slice(b"appended data");
+
+    let window_log = window_log(old.len().max(new.len())).unwrap();
+    let delta = diff(&old, &new, window_log).unwrap();
+    assert!(delta.len() < new.len() / 100);
+    assert_eq!(apply_delta(&old, &delta, window_log).unwrap(), new);
+    assert!(apply_delta(&old, &delta[..delta.len() / 2], window_log).is_err());
+
+    // the delta only applies to the artifact it was created from
+    let mut other = old;
+    other[0..4].copy_from_slice(b"xxxx");
+    assert_ne!(apply_delta(&other, &delta, window_log).ok(), Some(new));
+  }
+}
@@ -0,0 +1,106 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::{common::CommandExt, paths};
+use crate::{DmgFormat, HdiutilVerbosity, Settings};
+use anyhow::Context;
+use log::{info, warn};
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+/// Bundles the project with the [`crate::DmgBackend::Libdmg`] backend.
+/// Returns a vector of PathBuf that shows where the DMG was created.
+///
+/// The [`crate::DmgSettings::source_dir`] is written to an ISO 9660 image with Apple extensions,
+/// which libdmg-hfsplus then converts to a zlib compressed DMG.
+pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
+  let source_dir = match &settings.dmg().source_dir {
+    Some(source_dir) => env::current_dir()?.join(source_dir),
+    None => return Err(crate::Error::GenericError(
+      "the libdmg DMG backend requires a `source_dir`, the app bundle can only be created on macOS"
+        .into(),
+    )),
+  };
+  if !source_dir.is_dir() {
+    return Err(crate::Error::GenericError(format!(
+      "DMG source directory {} does not exist",
+      source_dir.display()
+    )));
+  }
+  if settings.macos().signing_identity.is_some() && settings.dmg().sign_dmg {
+    return Err(crate::Error::GenericError(
+      "signing a DMG requires macOS and the hdiutil backend, disable `sign_dmg` to create an unsigned DMG"
+        .into(),
+    ));
+  }
+
+  if settings.dmg().format.unwrap_or(DmgFormat::Udzo) != DmgFormat::Udzo
+    || settings.dmg().max_compression
+  {
+    warn!(
+      "the libdmg DMG backend only creates zlib compressed (UDZO) images, ignoring the DMG format"
+    );
+  }
+  if settings.macos().license.is_some()
+    || settings.macos().icns_path.is_some()
+    || settings.dmg().background.is_some()
+    || settings.dmg().ds_store.is_some()
+  {
+    warn!("the libdmg DMG backend does not support the license, volume icon, background and .DS_Store, ignoring them");
+  }
+  if settings.dmg().case_sensitive {
+    return Err(crate::Error::GenericError(
+      "the libdmg DMG backend does not support case-sensitive volumes, use the hdiutil backend on macOS"
+        .into(),
+    ));
+  }
+  if settings.dmg().volume_date.is_some() {
+    warn!("the libdmg DMG backend does not support the volume date, ignoring it");
+  }
+  if settings.dmg().comment.is_some() {
+    warn!("the libdmg DMG backend does not support Finder comments, ignoring the comment");
+  }
+  if settings.dmg().hdiutil_verbosity != HdiutilVerbosity::Normal {
+    warn!("the libdmg DMG backend does not use hdiutil, ignoring the hdiutil verbosity");
+  }
+  if settings.dmg().size_mb.is_some() {
+    warn!("the libdmg DMG backend sizes the volume to fit its contents, ignoring the DMG size");
+  }
+
+  let dmg_path = paths::dmg_path(settings);
+  let output_path = dmg_path.parent().expect("No data in parent").to_path_buf();
+  let dmg_name = format!("{}.dmg", settings.dmg_file_stem());
+  let iso_path = output_path.join(format!("{}.iso", settings.dmg_file_stem()));
+
+  if output_path.exists() {
+    fs::remove_dir_all(&output_path)
+      .with_context(|| format!("Failed to remove old {}", dmg_name))?;
+  }
+  fs::create_dir_all(&output_path)?;
+
+  info!(action = "Bundling"; "{} ({})", dmg_name, dmg_path.display());
+
+  Command::new("genisoimage")
+    .args(["-quiet", "-D", "-R", "-apple", "-no-pad"])
+    .arg("-V")
+    .arg(settings.product_name())
+    .arg("-o")
+    .arg(&iso_path)
+    .arg(&source_dir)
+    .output_ok()
+    .context("failed to create the DMG volume with genisoimage, is it installed?")?;
+
+  Command::new("dmg")
+    .arg("dmg")
+    .arg(&iso_path)
+    .arg(&dmg_path)
+    .output_ok()
+    .context(
+      "failed to convert the DMG volume with the libdmg-hfsplus `dmg` tool, is it installed?",
+    )?;
+
+  fs::remove_file(&iso_path)?;
+
+  Ok(vec![dmg_path])
+}
@@ -4,8 +4,11 @@
 // SPDX-License-Identifier: MIT
 
 use super::{
-  super::{common::CommandExt, path_utils},
-  debian,
+  super::{
+    common::{self, CommandExt},
+    path_utils, paths,
+  },
+  payload::{self, Payload},
 };
 use crate::Settings;
 use anyhow::Context;
@@ -14,24 +17,20 @@ use log::info;
 use std::{
   collections::BTreeMap,
   fs::{remove_dir_all, write},
-  path::PathBuf,
+  path::{Path, PathBuf},
   process::{Command, Stdio},
 };
 
 /// Bundles the project.
 /// Returns a vector of PathBuf that shows where the AppImage was created.
-pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
-  // generate the deb binary name
-  let arch = match settings.binary_arch() {
-    "x86" => "i386",
-    "x86_64" => "amd64",
-    other => other,
-  };
-  let package_dir = settings.project_out_directory().join("bundle/appimage_deb");
-
-  // generate deb_folder structure
-  let (_, icons) = debian::generate_data(settings, &package_dir)?;
-  let icons: Vec<debian::DebIcon> = icons.into_iter().collect();
+///
+/// The AppDir is created from the shared `payload`, staging it if the deb bundler did not.
+pub fn bundle_project(
+  settings: &Settings,
+  payload: &mut Option<Payload>,
+) -> crate::Result<Vec<PathBuf>> {
+  // the deb folder structure, shared with the deb bundle
+  let payload = payload::stage(settings, payload)?;
 
   let output_path = settings.project_out_directory().join("bundle/appimage");
   if output_path.exists() {
@@ -39,14 +38,14 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
   }
   std::fs::create_dir_all(output_path.clone())?;
   let app_dir_path = output_path.join(format!("{}.AppDir", settings.main_binary_name()));
-  let appimage_filename = format!(
-    "{}_{}_{}.AppImage",
-    settings.main_binary_name(),
-    settings.version_string(),
-    arch
-  );
-  let appimage_path = output_path.join(&appimage_filename);
-  path_utils::create(app_dir_path, true)?;
+  let appimage_path = paths::appimage_path(settings);
+  let appimage_filename = appimage_path
+    .file_name()
+    .expect("failed to get AppImage filename")
+    .to_string_lossy()
+    .into_owned();
+  path_utils::create(&app_dir_path, true)?;
+  create_app_dir(payload, &app_dir_path)?;
 
   let upcase_app_name = settings.main_binary_name().to_uppercase();
 
@@ -66,14 +65,17 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
   std::fs::create_dir_all(&tauri_tools_path)?;
   let tauri_tools_path_str = tauri_tools_path.to_string_lossy();
   sh_map.insert("tauri_tools_path", &tauri_tools_path_str);
-  let larger_icon = icons
+  let larger_icon = payload
+    .icons
     .iter()
     .filter(|i| i.width == i.height)
     .max_by_key(|i| i.width)
-    .expect("couldn't find a square icon to use as AppImage icon");
+    .ok_or_else(|| {
+      crate::Error::GenericError("couldn't find a square icon to use as AppImage icon".into())
+    })?;
   let larger_icon_path = larger_icon
     .path
-    .strip_prefix(package_dir.join("data"))
+    .strip_prefix(&payload.data_dir)
     .unwrap()
     .to_string_lossy()
     .to_string();
@@ -109,6 +111,13 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
     .output_ok()
     .context("error running appimage.sh")?;
 
-  remove_dir_all(&package_dir)?;
   Ok(vec![appimage_path])
 }
+
+/// Copies the `usr` tree of the payload to the AppDir. It is copied rather than linked,
+/// since linuxdeploy patches the binaries in place.
+pub(crate) fn create_app_dir(payload: &Payload, app_dir_path: &Path) -> crate::Result<()> {
+  common::copy_dir(&payload.data_dir.join("usr"), &app_dir_path.join("usr"))
+    .context("Failed to copy the payload to the AppDir")?;
+  Ok(())
+}
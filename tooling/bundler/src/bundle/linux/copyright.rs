@@ -0,0 +1,188 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::super::common;
+use crate::Settings;
+
+use std::{io::Write, path::Path};
+
+/// The text of a license in the copyright file.
+enum LicenseText {
+  /// The full license text, for licenses Debian does not ship.
+  Full(&'static str),
+  /// The name of the license file in `/usr/share/common-licenses`.
+  Common(&'static str),
+}
+
+/// The supported licenses: their SPDX identifier, their DEP-5 short name and their text.
+const LICENSES: &[(&str, &str, LicenseText)] = &[
+  (
+    "MIT",
+    "Expat",
+    LicenseText::Full(include_str!("templates/licenses/MIT")),
+  ),
+  (
+    "ISC",
+    "ISC",
+    LicenseText::Full(include_str!("templates/licenses/ISC")),
+  ),
+  (
+    "BSD-2-Clause",
+    "BSD-2-clause",
+    LicenseText::Full(include_str!("templates/licenses/BSD-2-Clause")),
+  ),
+  (
+    "BSD-3-Clause",
+    "BSD-3-clause",
+    LicenseText::Full(include_str!("templates/licenses/BSD-3-Clause")),
+  ),
+  (
+    "Apache-2.0",
+    "Apache-2.0",
+    LicenseText::Common("Apache-2.0"),
+  ),
+  ("MPL-2.0", "MPL-2.0", LicenseText::Common("MPL-2.0")),
+  ("CC0-1.0", "CC0-1.0", LicenseText::Common("CC0-1.0")),
+  ("GPL-2.0-only", "GPL-2", LicenseText::Common("GPL-2")),
+  ("GPL-2.0-or-later", "GPL-2+", LicenseText::Common("GPL-2")),
+  ("GPL-3.0-only", "GPL-3", LicenseText::Common("GPL-3")),
+  ("GPL-3.0-or-later", "GPL-3+", LicenseText::Common("GPL-3")),
+  ("LGPL-2.1-only", "LGPL-2.1", LicenseText::Common("LGPL-2.1")),
+  (
+    "LGPL-2.1-or-later",
+    "LGPL-2.1+",
+    LicenseText::Common("LGPL-2.1"),
+  ),
+  ("LGPL-3.0-only", "LGPL-3", LicenseText::Common("LGPL-3")),
+  (
+    "LGPL-3.0-or-later",
+    "LGPL-3+",
+    LicenseText::Common("LGPL-3"),
+  ),
+];
+
+/// Writes the machine-readable copyright file of [`crate::DebianSettings::license`]
+/// to `usr/share/doc/<package>/copyright` in the `data_dir`, unless a custom file is installed there.
+pub fn generate_copyright_file(
+  settings: &Settings,
+  data_dir: &Path,
+  package: &str,
+) -> crate::Result<()> {
+  let license = match &settings.deb().license {
+    Some(license) => license,
+    None => return Ok(()),
+  };
+  let copyright_path = data_dir
+    .join("usr/share/doc")
+    .join(package)
+    .join("copyright");
+  if copyright_path.exists() {
+    return Ok(());
+  }
+
+  let holder = settings
+    .deb()
+    .copyright_holder
+    .clone()
+    .or_else(|| settings.copyright_string().map(ToString::to_string))
+    .or_else(|| settings.authors_comma_separated())
+    .ok_or_else(|| {
+      crate::Error::GenericError(
+        "the deb copyright file needs a copyright holder, set `copyright_holder`, the copyright or the authors".into(),
+      )
+    })?;
+
+  let contents = copyright_file(settings, license, &holder)?;
+  let mut file = common::create_file(&copyright_path)?;
+  file.write_all(contents.as_bytes())?;
+  file.flush()?;
+  Ok(())
+}
+
+// Renders the DEP-5 copyright file, see https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/
+fn copyright_file(settings: &Settings, license: &str, holder: &str) -> crate::Result<String> {
+  let mut expression = Vec::new();
+  let mut licenses = Vec::new();
+  for token in license.split_whitespace() {
+    match token {
+      "OR" | "AND" => expression.push(token.to_lowercase()),
+      id => {
+        let (_, name, text) = LICENSES
+          .iter()
+          .find(|(spdx, _, _)| *spdx == id)
+          .ok_or_else(|| {
+            crate::Error::GenericError(format!(
+              "unsupported deb license `{}`, use a combination of {} with OR and AND",
+              id,
+              LICENSES
+                .iter()
+                .map(|(spdx, _, _)| *spdx)
+                .collect::<Vec<_>>()
+                .join(", ")
+            ))
+          })?;
+        expression.push(name.to_string());
+        if !licenses.iter().any(|(n, _)| n == name) {
+          licenses.push((*name, text));
+        }
+      }
+    }
+  }
+  if licenses.is_empty() {
+    return Err(crate::Error::GenericError(
+      "the deb license must not be empty".into(),
+    ));
+  }
+
+  let mut contents = String::new();
+  contents.push_str("Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/\n");
+  contents.push_str(&format!("Upstream-Name: {}\n", settings.product_name()));
+  if !settings.homepage_url().is_empty() {
+    contents.push_str(&format!("Source: {}\n", settings.homepage_url()));
+  }
+  contents.push_str(&format!(
+    "\nFiles: *\nCopyright: {}\nLicense: {}\n",
+    holder,
+    expression.join(" ")
+  ));
+
+  for (name, text) in licenses {
+    contents.push_str(&format!("\nLicense: {}\n", name));
+    let text = match text {
+      LicenseText::Full(text) => text.to_string(),
+      LicenseText::Common(file) => format!(
+        "On Debian systems, the complete text of the {} license can be found in\n`/usr/share/common-licenses/{}'.",
+        name, file
+      ),
+    };
+    for line in text.trim_end().lines() {
+      if line.trim().is_empty() {
+        contents.push_str(" .\n");
+      } else {
+        contents.push_str(&format!(" {}\n", line));
+      }
+    }
+  }
+  Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::copyright_file;
+  use crate::bundle::settings::test_settings;
+
+  #[test]
+  fn renders_dep5_copyright() {
+    let settings = test_settings("target", Default::default(), "x86_64-unknown-linux-gnu");
+
+    let contents =
+      copyright_file(&settings, "MIT OR Apache-2.0", "2022 Ferris").expect("Failed to render");
+    assert!(contents.contains("Copyright: 2022 Ferris\nLicense: Expat or Apache-2.0\n"));
+    assert!(contents.contains("\nLicense: Expat\n Permission is hereby granted"));
+    assert!(contents.contains(" .\n"));
+    assert!(contents.contains("`/usr/share/common-licenses/Apache-2.0'."));
+
+    assert!(copyright_file(&settings, "WTFPL", "2022 Ferris").is_err());
+  }
+}
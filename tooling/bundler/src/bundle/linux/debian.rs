@@ -12,34 +12,54 @@
 //         md5sums                  # Checksums for files in data.tar.gz below
 //         postinst                 # Post-installation script (optional)
 //         prerm                    # Pre-uninstallation script (optional)
-//     data.tar.gz             # Contains files to be installed:
+//     data.tar.gz             # Contains files to be installed (data.tar.xz with xz compression):
 //         usr/bin/foobar                            # Binary executable file
 //         usr/share/applications/foobar.desktop     # Desktop file (for apps)
 //         usr/share/icons/hicolor/...               # Icon files (for apps)
 //         usr/lib/foobar/...                        # Other resource files
 //
+// The `usr` prefix can be changed with the `install_prefix` setting.
+//
 // For cargo-bundle, we put bundle resource files under /usr/lib/package_name/,
 // and then generate the desktop file and control file from the bundle
-// metadata, as well as generating the md5sums file.  Currently we do not
-// generate postinst or prerm files.
+// metadata, as well as generating the md5sums file. The postinst and postrm
+// files only refresh the desktop, icon and MIME caches.
 
-use super::super::common;
-use crate::Settings;
+use super::{
+  super::{
+    common::{self, CommandExt},
+    paths,
+  },
+  payload::Payload,
+};
+use crate::{DebCompression, DebianFilePermissions, DebianSettings, Settings};
 use anyhow::Context;
 use heck::AsKebabCase;
 use image::{self, codecs::png::PngDecoder, ImageDecoder};
 use libflate::gzip;
-use log::info;
+use log::{error, info, warn};
 use walkdir::WalkDir;
 
 use std::{
-  collections::BTreeSet,
-  ffi::OsStr,
+  collections::{BTreeSet, HashMap},
+  ffi::{OsStr, OsString},
   fs::{self, File},
   io::{self, Write},
+  os::unix::fs::PermissionsExt,
   path::{Path, PathBuf},
+  process::Command,
 };
 
+/// Validated file modes and ownership, keyed by the path relative to the data directory.
+type FilePermissions<'a> = HashMap<PathBuf, (u32, &'a DebianFilePermissions)>;
+
+/// The installation prefix used when `install_prefix` is not set.
+pub const DEFAULT_INSTALL_PREFIX: &str = "/usr";
+/// The container image of the install test when `install_test_image` is not set.
+const DEFAULT_INSTALL_TEST_IMAGE: &str = "debian:stable-slim";
+/// The xz preset level used when `xz_level` is not set, dpkg's default.
+const DEFAULT_XZ_LEVEL: u32 = 6;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
 pub struct DebIcon {
   pub width: u32,
@@ -50,42 +70,83 @@ pub struct DebIcon {
 
 /// Bundles the project.
 /// Returns a vector of PathBuf that shows where the DEB was created.
-pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
-  let arch = match settings.binary_arch() {
-    "x86" => "i386",
-    "x86_64" => "amd64",
-    // ARM64 is detected differently, armel isn't supported, so armhf is the only reasonable choice here.
-    "arm" => "armhf",
-    "aarch64" => "arm64",
-    other => other,
-  };
-  let package_base_name = format!(
-    "{}_{}_{}",
-    settings.main_binary_name(),
-    settings.version_string(),
-    arch
-  );
+///
+/// With the default install prefix or a prebuilt bundle, the data tree is linked from the shared `payload`,
+/// staging it if needed.
+pub fn bundle_project(
+  settings: &Settings,
+  payload: &mut Option<Payload>,
+) -> crate::Result<Vec<PathBuf>> {
+  let arch = paths::deb_arch(settings);
+  // Debian architecture names are lowercase alphanumerics, with dashes in names like `musl-linux-amd64`
+  if arch.is_empty()
+    || !arch
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+  {
+    return Err(crate::Error::GenericError(format!(
+      "invalid debian architecture `{}`",
+      arch
+    )));
+  }
+  validate_compression(settings.deb())?;
+  let package = package_name(settings)?;
+  let package_base_name = paths::deb_package_base_name(settings);
   let package_name = format!("{}.deb", package_base_name);
 
-  let base_dir = settings.project_out_directory().join("bundle/deb");
-  let package_dir = base_dir.join(&package_base_name);
+  let package_path = paths::deb_package_path(settings);
+  let package_dir = package_path.with_file_name(&package_base_name);
   if package_dir.exists() {
     fs::remove_dir_all(&package_dir)
       .with_context(|| format!("Failed to remove old {}", package_base_name))?;
   }
-  let package_path = base_dir.join(&package_name);
 
   info!(action = "Bundling"; "{} ({})", package_name, package_path.display());
 
-  let (data_dir, _) = generate_data(settings, &package_dir)
-    .with_context(|| "Failed to build data folders and files")?;
+  let install_prefix = install_prefix(settings)?;
+  let data_dir = if install_prefix == Path::new(DEFAULT_INSTALL_PREFIX) {
+    let payload = super::payload::stage(settings, payload)?;
+    let data_dir = package_dir.join("data");
+    common::hard_link_dir(&payload.data_dir, &data_dir)
+      .with_context(|| "Failed to link the staged payload")?;
+    data_dir
+  } else {
+    generate_data(settings, &package_dir, &install_prefix)
+      .with_context(|| "Failed to build data folders and files")?
+      .0
+  };
   copy_custom_files(settings, &data_dir).with_context(|| "Failed to copy custom files")?;
+  super::copyright::generate_copyright_file(settings, &data_dir, &package)
+    .with_context(|| "Failed to create copyright file")?;
+
+  let dbgsym_package_path = paths::deb_dbgsym_package_path(settings);
+  let dbgsym_dir = dbgsym_package_path.with_extension("");
+  let build_ids = if settings.deb().dbgsym {
+    if dbgsym_dir.exists() {
+      fs::remove_dir_all(&dbgsym_dir)?;
+    }
+    Some(
+      split_debug_symbols(
+        settings,
+        &data_dir,
+        &install_prefix,
+        &dbgsym_dir.join("data"),
+      )
+      .with_context(|| "Failed to split debug symbols")?,
+    )
+  } else {
+    None
+  };
 
   // Generate control files.
   let control_dir = package_dir.join("control");
-  generate_control_file(settings, arch, &control_dir, &data_dir)
+  generate_control_file(settings, &package, arch, &control_dir, &data_dir)
     .with_context(|| "Failed to create control file")?;
   generate_md5sums(&control_dir, &data_dir).with_context(|| "Failed to create md5sums file")?;
+  if !settings.deb().skip_desktop_cache_refresh {
+    generate_maintainer_scripts(&control_dir, &data_dir, &install_prefix)
+      .with_context(|| "Failed to create maintainer scripts")?;
+  }
 
   // Generate `debian-binary` file; see
   // http://www.tldp.org/HOWTO/Debian-Binary-Package-Building-HOWTO/x60.html#AEN66
@@ -93,27 +154,397 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
   create_file_with_data(&debian_binary_path, "2.0\n")
     .with_context(|| "Failed to create debian-binary file")?;
 
+  let file_permissions = resolve_file_permissions(settings, &data_dir)?;
+
   // Apply tar/gzip/ar to create the final package file.
-  let control_tar_gz_path =
-    tar_and_gzip_dir(control_dir).with_context(|| "Failed to tar/gzip control directory")?;
-  let data_tar_gz_path =
-    tar_and_gzip_dir(data_dir).with_context(|| "Failed to tar/gzip data directory")?;
+  let control_tar_gz_path = tar_and_gzip_dir(control_dir, &HashMap::new())
+    .with_context(|| "Failed to tar/gzip control directory")?;
+  let data_tar_gz_path = tar_and_compress_dir(data_dir, &file_permissions, settings.deb())
+    .with_context(|| "Failed to tar/compress data directory")?;
   create_archive(
     vec![debian_binary_path, control_tar_gz_path, data_tar_gz_path],
     &package_path,
   )
   .with_context(|| "Failed to create package archive")?;
-  Ok(vec![package_path])
+
+  if settings.deb().lintian {
+    run_lintian(&package_path, settings.deb().lintian_strict)?;
+  }
+  if settings.deb().install_test {
+    run_install_test(&package_path, settings.deb(), paths::deb_arch(settings))?;
+  }
+
+  let mut package_paths = vec![package_path];
+  if let Some(build_ids) = build_ids {
+    info!(action = "Bundling"; "{}", dbgsym_package_path.display());
+    create_dbgsym_package(
+      settings,
+      &package,
+      arch,
+      &dbgsym_dir,
+      &dbgsym_package_path,
+      &build_ids,
+    )
+    .with_context(|| "Failed to create debug symbols package")?;
+    package_paths.push(dbgsym_package_path);
+  }
+
+  Ok(package_paths)
+}
+
+/// Moves the debug symbols of the binaries in the `data_dir` to `usr/lib/debug` in the `dbgsym_data_dir`
+/// and links the stripped binaries to them, see https://wiki.debian.org/AutomaticDebugPackages.
+///
+/// Returns the build IDs of the binaries, which name their symbol files when present.
+fn split_debug_symbols(
+  settings: &Settings,
+  data_dir: &Path,
+  install_prefix: &Path,
+  dbgsym_data_dir: &Path,
+) -> crate::Result<Vec<String>> {
+  let bin_dir = data_dir
+    .join(install_prefix.strip_prefix("/").unwrap_or(install_prefix))
+    .join("bin");
+  let debug_dir = dbgsym_data_dir.join("usr/lib/debug");
+  let mut build_ids = Vec::new();
+  for bin in settings.binaries() {
+    let bin_path = bin_dir.join(bin.name());
+    let debug_path = match build_id(&bin_path)? {
+      Some(build_id) => {
+        let debug_path = debug_dir
+          .join(".build-id")
+          .join(&build_id[..2])
+          .join(format!("{}.debug", &build_id[2..]));
+        build_ids.push(build_id);
+        debug_path
+      }
+      // debuggers also look for the debug link next to the binary path in the debug directory
+      None => debug_dir
+        .join(bin_path.strip_prefix(data_dir)?)
+        .with_file_name(format!("{}.debug", bin.name())),
+    };
+    fs::create_dir_all(debug_path.parent().expect("debug path has a parent"))?;
+
+    Command::new("objcopy")
+      .arg("--only-keep-debug")
+      .arg(&bin_path)
+      .arg(&debug_path)
+      .output_ok()
+      .context("failed to extract the debug symbols with objcopy, is binutils installed?")?;
+    fs::set_permissions(&debug_path, fs::Permissions::from_mode(0o644))?;
+
+    // the stripped copy replaces the binary, so a hard-linked payload keeps its symbols
+    let stripped_path = bin_path.with_file_name(format!("{}.stripped", bin.name()));
+    Command::new("objcopy")
+      .arg("--strip-debug")
+      .arg(format!("--add-gnu-debuglink={}", debug_path.display()))
+      .arg(&bin_path)
+      .arg(&stripped_path)
+      .output_ok()
+      .context("failed to strip the debug symbols with objcopy")?;
+    fs::rename(&stripped_path, &bin_path)?;
+  }
+  Ok(build_ids)
+}
+
+/// Reads the GNU build ID of a binary with `readelf`.
+fn build_id(path: &Path) -> crate::Result<Option<String>> {
+  let output = Command::new("readelf")
+    .arg("-n")
+    .arg(path)
+    .output_ok()
+    .context("failed to read the build ID with readelf, is binutils installed?")?;
+  Ok(
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .find_map(|line| line.trim().strip_prefix("Build ID: "))
+      .map(|build_id| build_id.trim().to_string())
+      .filter(|build_id| build_id.len() > 2),
+  )
+}
+
+/// Creates the `<package>-dbgsym` package from the data tree in the `dbgsym_dir`.
+fn create_dbgsym_package(
+  settings: &Settings,
+  package: &str,
+  arch: &str,
+  dbgsym_dir: &Path,
+  package_path: &Path,
+  build_ids: &[String],
+) -> crate::Result<()> {
+  let data_dir = dbgsym_dir.join("data");
+  let control_dir = dbgsym_dir.join("control");
+
+  let mut file = common::create_file(&control_dir.join("control"))?;
+  writeln!(file, "Package: {}-dbgsym", package)?;
+  writeln!(file, "Version: {}", settings.version_string())?;
+  writeln!(file, "Architecture: {}", arch)?;
+  writeln!(
+    file,
+    "Installed-Size: {}",
+    total_dir_size(&data_dir)? / 1024
+  )?;
+  let authors = settings.authors_comma_separated().unwrap_or_default();
+  writeln!(file, "Maintainer: {}", authors)?;
+  writeln!(
+    file,
+    "Depends: {} (= {})",
+    package,
+    settings.version_string()
+  )?;
+  writeln!(file, "Section: debug")?;
+  writeln!(file, "Priority: optional")?;
+  writeln!(file, "Auto-Built-Package: debug-symbols")?;
+  if !build_ids.is_empty() {
+    writeln!(file, "Build-Ids: {}", build_ids.join(" "))?;
+  }
+  writeln!(file, "Description: debug symbols for {}", package)?;
+  file.flush()?;
+  generate_md5sums(&control_dir, &data_dir)?;
+
+  let debian_binary_path = dbgsym_dir.join("debian-binary");
+  create_file_with_data(&debian_binary_path, "2.0\n")?;
+  let control_tar_gz_path = tar_and_gzip_dir(control_dir, &HashMap::new())?;
+  let data_tar_gz_path = tar_and_compress_dir(data_dir, &HashMap::new(), settings.deb())?;
+  create_archive(
+    vec![debian_binary_path, control_tar_gz_path, data_tar_gz_path],
+    package_path,
+  )
+}
+
+/// Returns the validated [`crate::DebianSettings::package_name`],
+/// or the kebab-cased product name normalized to a valid package name.
+fn package_name(settings: &Settings) -> crate::Result<String> {
+  let name = match &settings.deb().package_name {
+    Some(name) => name.clone(),
+    None => {
+      let kebab = AsKebabCase(settings.product_name()).to_string();
+      let name = normalize_package_name(&kebab);
+      if name != kebab {
+        warn!(
+          "using `{}` as the debian package name, since `{}` is not a valid package name",
+          name, kebab
+        );
+      }
+      name
+    }
+  };
+  if is_valid_package_name(&name) {
+    Ok(name)
+  } else {
+    Err(crate::Error::GenericError(format!(
+      "invalid debian package name `{}`, it must be at least two characters long, start with a letter or digit and only contain lowercase letters, digits, `+`, `-` and `.`",
+      name
+    )))
+  }
+}
+
+// Lowercases the name and replaces the characters Debian does not allow with dashes.
+fn normalize_package_name(name: &str) -> String {
+  let mut normalized = String::with_capacity(name.len());
+  for c in name.chars().flat_map(char::to_lowercase) {
+    if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '+' || c == '.' {
+      normalized.push(c);
+    } else if !normalized.ends_with('-') {
+      normalized.push('-');
+    }
+  }
+  normalized
+    .trim_start_matches(|c: char| !c.is_ascii_alphanumeric())
+    .trim_end_matches('-')
+    .to_string()
+}
+
+// Whether the name matches `^[a-z0-9][a-z0-9+.-]+$`, see https://www.debian.org/doc/debian-policy/ch-controlfields.html#source
+fn is_valid_package_name(name: &str) -> bool {
+  name.len() >= 2
+    && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+    && name
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '+' || c == '-' || c == '.')
+}
+
+/// Runs `lintian` on the package, failing on errors if `strict` is set.
+fn run_lintian(package_path: &Path, strict: bool) -> crate::Result<()> {
+  info!(action = "Running"; "lintian on {}", package_path.display());
+
+  let output = match Command::new("lintian")
+    .envs(common::tool_env())
+    .arg(package_path)
+    .output()
+  {
+    Ok(output) => output,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+      warn!("skipping lintian check, `lintian` is not installed");
+      return Ok(());
+    }
+    Err(e) => return Err(e.into()),
+  };
+
+  let mut errors = 0;
+  for line in String::from_utf8_lossy(&output.stdout).lines() {
+    if line.starts_with("E: ") {
+      errors += 1;
+      error!("lintian: {}", line);
+    } else if line.starts_with("W: ") {
+      warn!("lintian: {}", line);
+    }
+  }
+
+  if strict && errors > 0 {
+    return Err(crate::Error::GenericError(format!(
+      "lintian reported {} error(s) for {}",
+      errors,
+      package_path.display()
+    )));
+  }
+
+  Ok(())
+}
+
+/// Installs the package with `apt-get` in a throwaway container, which resolves its dependencies
+/// and runs its maintainer scripts, and fails with the install log if it cannot be installed.
+fn run_install_test(package_path: &Path, deb: &DebianSettings, arch: &str) -> crate::Result<()> {
+  let platform = match arch {
+    "all" => None,
+    arch if arch == host_deb_arch() => host_container_platform(),
+    arch => {
+      warn!(
+        "skipping the deb install test, the {} package cannot run on this {} host",
+        arch,
+        host_deb_arch()
+      );
+      return Ok(());
+    }
+  };
+  // `info` fails when the daemon or the podman machine is not running, unlike `--version`
+  let runtime = match ["docker", "podman"].into_iter().find(|runtime| {
+    common::tool_command(runtime)
+      .arg("info")
+      .output()
+      .map_or(false, |output| output.status.success())
+  }) {
+    Some(runtime) => runtime,
+    None => {
+      warn!(
+        "skipping the deb install test, neither `docker` nor `podman` is installed and running"
+      );
+      return Ok(());
+    }
+  };
+  let image = deb
+    .install_test_image
+    .as_deref()
+    .unwrap_or(DEFAULT_INSTALL_TEST_IMAGE);
+
+  info!(action = "Running"; "install test of {} in {}", package_path.display(), image);
+
+  let output = common::tool_command(runtime)
+    .args(install_test_args(package_path, image, platform)?)
+    .output_unchecked()
+    .with_context(|| format!("failed to run the install test container with {}", runtime))?;
+  if !output.status.success() {
+    return Err(crate::Error::GenericError(format!(
+      "{} failed to install in {}:\n{}{}",
+      package_path.display(),
+      image,
+      String::from_utf8_lossy(&output.stdout),
+      String::from_utf8_lossy(&output.stderr)
+    )));
+  }
+  Ok(())
+}
+
+// The Debian architecture of the host the bundler runs on.
+fn host_deb_arch() -> &'static str {
+  match std::env::consts::ARCH {
+    "x86_64" => "amd64",
+    "x86" => "i386",
+    "aarch64" => "arm64",
+    "arm" => "armhf",
+    "powerpc64" if cfg!(target_endian = "little") => "ppc64el",
+    "loongarch64" => "loong64",
+    other => other,
+  }
+}
+
+// The container platform of the host, so a multi-arch image runs the variant matching the package.
+fn host_container_platform() -> Option<&'static str> {
+  match host_deb_arch() {
+    "amd64" => Some("linux/amd64"),
+    "i386" => Some("linux/386"),
+    "arm64" => Some("linux/arm64"),
+    "armhf" => Some("linux/arm/v7"),
+    "ppc64el" => Some("linux/ppc64le"),
+    "s390x" => Some("linux/s390x"),
+    "riscv64" => Some("linux/riscv64"),
+    _ => None,
+  }
+}
+
+// The container `run` arguments installing the package, mounted read-only at `/deb`.
+fn install_test_args(
+  package_path: &Path,
+  image: &str,
+  platform: Option<&str>,
+) -> crate::Result<Vec<OsString>> {
+  let package_path = package_path.canonicalize()?;
+  let package_dir = package_path.parent().expect("No data in parent");
+  let file_name = package_path
+    .file_name()
+    .expect("failed to get package filename")
+    .to_string_lossy();
+  let mut volume = OsString::from(package_dir);
+  volume.push(":/deb:ro");
+  let mut args: Vec<OsString> = vec!["run".into(), "--rm".into()];
+  if let Some(platform) = platform {
+    args.push("--platform".into());
+    args.push(platform.into());
+  }
+  args.extend([
+    "--env".into(),
+    "DEBIAN_FRONTEND=noninteractive".into(),
+    "--volume".into(),
+    volume,
+    image.into(),
+    "sh".into(),
+    "-c".into(),
+    // a path starting with `./` or `/` makes apt-get install the file instead of a package of that name
+    format!(
+      "apt-get update -qq && apt-get install -y --no-install-recommends '/deb/{}'",
+      file_name.replace('\'', "'\\''")
+    )
+    .into(),
+  ]);
+  Ok(args)
+}
+
+/// Returns the validated installation prefix of the package.
+fn install_prefix(settings: &Settings) -> crate::Result<PathBuf> {
+  match &settings.deb().install_prefix {
+    // the debug symbols and maintainer scripts would look for the files under the prefix
+    Some(_) if settings.prebuilt_bundle().is_some() => Err(crate::Error::GenericError(
+      "the debian install prefix cannot be combined with a prebuilt bundle, which is installed at `/` as is".into(),
+    )),
+    Some(prefix) if !prefix.is_absolute() => Err(crate::Error::GenericError(format!(
+      "the debian install prefix must be an absolute path, got {}",
+      prefix.display()
+    ))),
+    Some(prefix) => Ok(prefix.clone()),
+    None => Ok(PathBuf::from(DEFAULT_INSTALL_PREFIX)),
+  }
 }
 
-/// Generate the debian data folders and files.
+/// Generate the debian data folders and files under the given absolute `install_prefix`.
 pub fn generate_data(
   settings: &Settings,
   package_dir: &Path,
+  install_prefix: &Path,
 ) -> crate::Result<(PathBuf, BTreeSet<DebIcon>)> {
   // Generate data files.
   let data_dir = package_dir.join("data");
-  let bin_dir = data_dir.join("usr/bin");
+  let prefix_dir = data_dir.join(install_prefix.strip_prefix("/").unwrap_or(install_prefix));
+  let bin_dir = prefix_dir.join("bin");
 
   for bin in settings.binaries() {
     let bin_path = settings.binary_path(bin);
@@ -121,26 +552,80 @@ pub fn generate_data(
       .with_context(|| format!("Failed to copy binary from {:?}", bin_path))?;
   }
 
-  copy_resource_files(settings, &data_dir).with_context(|| "Failed to copy resource files")?;
+  copy_resource_files(settings, &prefix_dir).with_context(|| "Failed to copy resource files")?;
+  settings
+    .copy_shared_resources(
+      &prefix_dir
+        .join("share")
+        .join(settings.shared_resources_name()),
+    )
+    .with_context(|| "Failed to copy shared resource files")?;
+  if install_prefix != Path::new(DEFAULT_INSTALL_PREFIX) {
+    warn!(
+      "the resources are installed in {}, but the Tauri runtime looks them up in /usr/lib/{}",
+      install_prefix
+        .join("lib")
+        .join(settings.main_binary_name())
+        .display(),
+      settings.main_binary_name()
+    );
+  }
+  let metadata_dir = prefix_dir.join("lib").join(settings.main_binary_name());
+  settings
+    .write_build_info(&metadata_dir)
+    .with_context(|| "Failed to write build info")?;
+  settings
+    .write_release_notes(&metadata_dir)
+    .with_context(|| "Failed to write release notes")?;
 
   settings
     .copy_binaries(&bin_dir)
     .with_context(|| "Failed to copy external binaries")?;
 
   let icons =
-    generate_icon_files(settings, &data_dir).with_context(|| "Failed to create icon files")?;
-  generate_desktop_file(settings, &data_dir).with_context(|| "Failed to create desktop file")?;
+    generate_icon_files(settings, &prefix_dir).with_context(|| "Failed to create icon files")?;
+  generate_desktop_file(settings, &data_dir, install_prefix, &icons)
+    .with_context(|| "Failed to create desktop file")?;
 
   Ok((data_dir, icons))
 }
 
-/// Generate the application desktop file and store it under the `data_dir`.
-fn generate_desktop_file(settings: &Settings, data_dir: &Path) -> crate::Result<()> {
+/// Generate the application desktop file and store it under the `install_prefix` in the `data_dir`.
+fn generate_desktop_file(
+  settings: &Settings,
+  data_dir: &Path,
+  install_prefix: &Path,
+  icons: &BTreeSet<DebIcon>,
+) -> crate::Result<()> {
   let bin_name = settings.main_binary_name();
   let desktop_file_name = format!("{}.desktop", bin_name);
   let desktop_file_path = data_dir
-    .join("usr/share/applications")
+    .join(install_prefix.strip_prefix("/").unwrap_or(install_prefix))
+    .join("share/applications")
     .join(desktop_file_name);
+  // Outside of `/usr` the binary is not on the `PATH` and the icons are not in the icon theme,
+  // so they are referenced by their absolute paths.
+  let (exec, icon) = if install_prefix == Path::new(DEFAULT_INSTALL_PREFIX) {
+    (bin_name.to_string(), bin_name.to_string())
+  } else {
+    let icon = match icons.iter().next_back() {
+      Some(icon) => Path::new("/")
+        .join(icon.path.strip_prefix(data_dir)?)
+        .display()
+        .to_string(),
+      None => bin_name.to_string(),
+    };
+    (
+      desktop_exec_arg(
+        &install_prefix
+          .join("bin")
+          .join(bin_name)
+          .display()
+          .to_string(),
+      ),
+      icon,
+    )
+  };
   let file = &mut common::create_file(&desktop_file_path)?;
   // For more information about the format of this file, see
   // https://developer.gnome.org/integration-guide/stable/desktop-files.html.en
@@ -153,17 +638,42 @@ fn generate_desktop_file(settings: &Settings, data_dir: &Path) -> crate::Result<
   if !settings.short_description().is_empty() {
     writeln!(file, "Comment={}", settings.short_description())?;
   }
-  writeln!(file, "Exec={}", bin_name)?;
-  writeln!(file, "Icon={}", bin_name)?;
+  writeln!(file, "Exec={}", exec)?;
+  writeln!(file, "Icon={}", icon)?;
   writeln!(file, "Name={}", settings.product_name())?;
   writeln!(file, "Terminal=false")?;
   writeln!(file, "Type=Application")?;
   Ok(())
 }
 
+// Quotes an `Exec` argument as the desktop entry specification requires for reserved characters,
+// then escapes it as a string value.
+fn desktop_exec_arg(arg: &str) -> String {
+  const RESERVED: &[char] = &[
+    ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')',
+    '`',
+  ];
+  let arg = arg.replace('%', "%%");
+  let arg = if arg.contains(RESERVED) {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+      if matches!(c, '"' | '`' | '$' | '\\') {
+        quoted.push('\\');
+      }
+      quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+  } else {
+    arg
+  };
+  arg.replace('\\', "\\\\")
+}
+
 /// Generates the debian control file and stores it under the `control_dir`.
 fn generate_control_file(
   settings: &Settings,
+  package: &str,
   arch: &str,
   control_dir: &Path,
   data_dir: &Path,
@@ -172,7 +682,7 @@ fn generate_control_file(
   // https://www.debian.org/doc/debian-policy/ch-controlfields.html
   let dest_path = control_dir.join("control");
   let mut file = common::create_file(&dest_path)?;
-  writeln!(file, "Package: {}", AsKebabCase(settings.product_name()))?;
+  writeln!(file, "Package: {}", package)?;
   writeln!(file, "Version: {}", settings.version_string())?;
   writeln!(file, "Architecture: {}", arch)?;
   // Installed-Size must be divided by 1024, see https://www.debian.org/doc/debian-policy/ch-controlfields.html#installed-size
@@ -208,6 +718,61 @@ fn generate_control_file(
   Ok(())
 }
 
+/// Creates the `postinst` and `postrm` scripts in the `control_dir`, refreshing the caches of the desktop files,
+/// hicolor icons and MIME types the `data_dir` installs. No scripts are created when it installs none of them.
+fn generate_maintainer_scripts(
+  control_dir: &Path,
+  data_dir: &Path,
+  install_prefix: &Path,
+) -> crate::Result<()> {
+  let share_dir = install_prefix.join("share");
+  let installed = |path: &str| {
+    let dir = data_dir
+      .join(share_dir.strip_prefix("/").unwrap_or(&share_dir))
+      .join(path);
+    fs::read_dir(dir).map_or(false, |mut entries| entries.next().is_some())
+  };
+
+  let mut commands = Vec::new();
+  if installed("applications") {
+    commands.push((
+      "update-desktop-database",
+      format!("-q {}", share_dir.join("applications").display()),
+    ));
+  }
+  if installed("icons/hicolor") {
+    commands.push((
+      "gtk-update-icon-cache",
+      format!("-q -t -f {}", share_dir.join("icons/hicolor").display()),
+    ));
+  }
+  if installed("mime/packages") {
+    commands.push((
+      "update-mime-database",
+      share_dir.join("mime").display().to_string(),
+    ));
+  }
+  if commands.is_empty() {
+    return Ok(());
+  }
+
+  // the tools are optional, minimal systems may not have them
+  let mut script = String::from("#!/bin/sh\nset -e\n\n");
+  for (tool, args) in commands {
+    script.push_str(&format!(
+      "if command -v {tool} >/dev/null 2>&1; then\n  {tool} {args} || true\nfi\n",
+      tool = tool,
+      args = args
+    ));
+  }
+  for name in ["postinst", "postrm"] {
+    let path = control_dir.join(name);
+    create_file_with_data(&path, &script)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+  }
+  Ok(())
+}
+
 /// Create an `md5sums` file in the `control_dir` containing the MD5 checksums
 /// for each file within the `data_dir`.
 fn generate_md5sums(control_dir: &Path, data_dir: &Path) -> crate::Result<()> {
@@ -236,9 +801,9 @@ fn generate_md5sums(control_dir: &Path, data_dir: &Path) -> crate::Result<()> {
 }
 
 /// Copy the bundle's resource files into an appropriate directory under the
-/// `data_dir`.
-fn copy_resource_files(settings: &Settings, data_dir: &Path) -> crate::Result<()> {
-  let resource_dir = data_dir.join("usr/lib").join(settings.main_binary_name());
+/// `prefix_dir`.
+fn copy_resource_files(settings: &Settings, prefix_dir: &Path) -> crate::Result<()> {
+  let resource_dir = prefix_dir.join("lib").join(settings.main_binary_name());
   settings.copy_resources(&resource_dir)
 }
 
@@ -251,14 +816,14 @@ fn copy_custom_files(settings: &Settings, data_dir: &Path) -> crate::Result<()>
       deb_path
     };
     if path.is_file() {
-      common::copy_file(path, data_dir.join(deb_path))?;
+      copy_custom_file(path, &data_dir.join(deb_path))?;
     } else {
       let out_dir = data_dir.join(deb_path);
       for entry in walkdir::WalkDir::new(&path) {
         let entry_path = entry?.into_path();
         if entry_path.is_file() {
           let without_prefix = entry_path.strip_prefix(&path).unwrap();
-          common::copy_file(&entry_path, out_dir.join(without_prefix))?;
+          copy_custom_file(&entry_path, &out_dir.join(without_prefix))?;
         }
       }
     }
@@ -266,9 +831,47 @@ fn copy_custom_files(settings: &Settings, data_dir: &Path) -> crate::Result<()>
   Ok(())
 }
 
-/// Generate the icon files and store them under the `data_dir`.
-fn generate_icon_files(settings: &Settings, data_dir: &Path) -> crate::Result<BTreeSet<DebIcon>> {
-  let base_dir = data_dir.join("usr/share/icons/hicolor");
+/// Copies a custom file, replacing the destination instead of writing through it
+/// since it may be hard linked to the shared payload.
+fn copy_custom_file(from: &Path, to: &Path) -> crate::Result<()> {
+  if to.is_file() {
+    fs::remove_file(to)?;
+  }
+  common::copy_file(from, to)
+}
+
+/// Validates the custom file permissions, keyed by the path relative to the `data_dir`.
+fn resolve_file_permissions<'a>(
+  settings: &'a Settings,
+  data_dir: &Path,
+) -> crate::Result<FilePermissions<'a>> {
+  let mut resolved = HashMap::new();
+  for (deb_path, permissions) in settings.deb().file_permissions.iter() {
+    let rel_path = deb_path.strip_prefix("/").unwrap_or(deb_path);
+    if !data_dir.join(rel_path).exists() {
+      return Err(crate::Error::GenericError(format!(
+        "cannot set the permissions of {}, the file is not part of the package",
+        deb_path.display()
+      )));
+    }
+    let mode = u32::from_str_radix(&permissions.mode, 8)
+      .ok()
+      .filter(|mode| *mode <= 0o7777)
+      .ok_or_else(|| {
+        crate::Error::GenericError(format!(
+          "invalid mode `{}` for {}, expected an octal mode such as `0755`",
+          permissions.mode,
+          deb_path.display()
+        ))
+      })?;
+    resolved.insert(rel_path.to_path_buf(), (mode, permissions));
+  }
+  Ok(resolved)
+}
+
+/// Generate the icon files and store them under the `prefix_dir`.
+fn generate_icon_files(settings: &Settings, prefix_dir: &Path) -> crate::Result<BTreeSet<DebIcon>> {
+  let base_dir = prefix_dir.join("share/icons/hicolor");
   let get_dest_path = |width: u32, height: u32, is_high_density: bool| {
     base_dir.join(format!(
       "{}x{}{}/apps/{}.png",
@@ -326,7 +929,11 @@ fn total_dir_size(dir: &Path) -> crate::Result<u64> {
 }
 
 /// Writes a tar file to the given writer containing the given directory.
-fn create_tar_from_dir<P: AsRef<Path>, W: Write>(src_dir: P, dest_file: W) -> crate::Result<W> {
+fn create_tar_from_dir<P: AsRef<Path>, W: Write>(
+  src_dir: P,
+  dest_file: W,
+  file_permissions: &FilePermissions<'_>,
+) -> crate::Result<W> {
   let src_dir = src_dir.as_ref();
   let mut tar_builder = tar::Builder::new(dest_file);
   for entry in WalkDir::new(&src_dir) {
@@ -336,7 +943,21 @@ fn create_tar_from_dir<P: AsRef<Path>, W: Write>(src_dir: P, dest_file: W) -> cr
       continue;
     }
     let dest_path = src_path.strip_prefix(&src_dir)?;
-    if entry.file_type().is_dir() {
+    if let Some((mode, permissions)) = file_permissions.get(dest_path) {
+      let mut header = tar::Header::new_gnu();
+      header.set_metadata(&entry.metadata()?);
+      header.set_mode(*mode);
+      header.set_uid(0);
+      header.set_gid(0);
+      header.set_username(&permissions.owner)?;
+      header.set_groupname(&permissions.group)?;
+      if entry.file_type().is_dir() {
+        header.set_size(0);
+        tar_builder.append_data(&mut header, dest_path, io::empty())?;
+      } else {
+        tar_builder.append_data(&mut header, dest_path, fs::File::open(src_path)?)?;
+      }
+    } else if entry.file_type().is_dir() {
       tar_builder.append_dir(dest_path, src_path)?;
     } else {
       let mut src_file = fs::File::open(src_path)?;
@@ -350,17 +971,89 @@ fn create_tar_from_dir<P: AsRef<Path>, W: Write>(src_dir: P, dest_file: W) -> cr
 /// Creates a `.tar.gz` file from the given directory (placing the new file
 /// within the given directory's parent directory), then deletes the original
 /// directory and returns the path to the new file.
-fn tar_and_gzip_dir<P: AsRef<Path>>(src_dir: P) -> crate::Result<PathBuf> {
+fn tar_and_gzip_dir<P: AsRef<Path>>(
+  src_dir: P,
+  file_permissions: &FilePermissions<'_>,
+) -> crate::Result<PathBuf> {
   let src_dir = src_dir.as_ref();
   let dest_path = src_dir.with_extension("tar.gz");
   let dest_file = common::create_file(&dest_path)?;
   let gzip_encoder = gzip::Encoder::new(dest_file)?;
-  let gzip_encoder = create_tar_from_dir(src_dir, gzip_encoder)?;
+  let gzip_encoder = create_tar_from_dir(src_dir, gzip_encoder, file_permissions)?;
   let mut dest_file = gzip_encoder.finish().into_result()?;
   dest_file.flush()?;
   Ok(dest_path)
 }
 
+/// Creates the compressed tar of the data directory like [`tar_and_gzip_dir`],
+/// with the [`DebianSettings::compression`].
+fn tar_and_compress_dir(
+  src_dir: PathBuf,
+  file_permissions: &FilePermissions<'_>,
+  deb: &DebianSettings,
+) -> crate::Result<PathBuf> {
+  match deb.compression {
+    DebCompression::Gzip => tar_and_gzip_dir(src_dir, file_permissions),
+    DebCompression::Xz => {
+      let tar_path = src_dir.with_extension("tar");
+      let mut tar_file =
+        create_tar_from_dir(&src_dir, common::create_file(&tar_path)?, file_permissions)?;
+      tar_file.flush()?;
+      drop(tar_file);
+
+      let mut filter = format!(
+        "--lzma2=preset={}",
+        deb.xz_level.unwrap_or(DEFAULT_XZ_LEVEL)
+      );
+      if let Some(dict_size) = deb.xz_dict_size {
+        filter.push_str(&format!(",dict={}", dict_size));
+      }
+      // a single thread keeps the output the same on every machine
+      common::tool_command("xz")
+        .args([
+          "--compress",
+          "--force",
+          "--format=xz",
+          "--check=crc64",
+          "--threads=1",
+        ])
+        .arg(filter)
+        .arg(&tar_path)
+        .output_ok()
+        .context("failed to compress the data archive with xz, is it installed?")?;
+      Ok(src_dir.with_extension("tar.xz"))
+    }
+  }
+}
+
+/// Checks the xz settings, which are ignored with gzip compression.
+fn validate_compression(deb: &DebianSettings) -> crate::Result<()> {
+  if deb.compression != DebCompression::Xz {
+    if deb.xz_level.is_some() || deb.xz_dict_size.is_some() {
+      warn!(
+        "the deb data archive is compressed with gzip, ignoring the xz level and dictionary size"
+      );
+    }
+    return Ok(());
+  }
+  if let Some(level) = deb.xz_level.filter(|level| *level > 9) {
+    return Err(crate::Error::GenericError(format!(
+      "invalid xz level {}, expected a level from 0 to 9",
+      level
+    )));
+  }
+  if let Some(dict_size) = deb
+    .xz_dict_size
+    .filter(|size| !(4096..=1536 * 1024 * 1024).contains(size))
+  {
+    return Err(crate::Error::GenericError(format!(
+      "invalid xz dictionary size of {} bytes, expected a size from 4 KiB to 1.5 GiB",
+      dict_size
+    )));
+  }
+  Ok(())
+}
+
 /// Creates an `ar` archive from the given source files and writes it to the
 /// given destination path.
 fn create_archive(srcs: Vec<PathBuf>, dest: &Path) -> crate::Result<()> {
@@ -371,3 +1064,172 @@ fn create_archive(srcs: Vec<PathBuf>, dest: &Path) -> crate::Result<()> {
   builder.into_inner()?.flush()?;
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    bundle_project, desktop_exec_arg, generate_desktop_file, generate_maintainer_scripts,
+    install_test_args, is_valid_package_name, normalize_package_name, split_debug_symbols,
+    validate_compression,
+  };
+  use crate::{bundle::settings::test_settings, BundleSettings, DebCompression, DebianSettings};
+  use std::{fs, path::Path, process::Command};
+  use walkdir::WalkDir;
+
+  #[test]
+  fn normalizes_package_names() {
+    assert_eq!(normalize_package_name("my-app"), "my-app");
+    assert_eq!(normalize_package_name("grüße_app"), "gr-e-app");
+    assert_eq!(normalize_package_name("-app+plus"), "app+plus");
+    assert!(is_valid_package_name("my-app2.0"));
+    assert!(!is_valid_package_name("a"));
+    assert!(!is_valid_package_name("My_App"));
+    assert!(!is_valid_package_name("-app"));
+  }
+
+  #[test]
+  fn compresses_data_with_xz() {
+    let deb = |xz_level, xz_dict_size| DebianSettings {
+      compression: DebCompression::Xz,
+      xz_level,
+      xz_dict_size,
+      ..Default::default()
+    };
+    assert!(validate_compression(&deb(Some(9), Some(1024 * 1024))).is_ok());
+    assert!(validate_compression(&deb(Some(10), None)).is_err());
+    assert!(validate_compression(&deb(None, Some(1024))).is_err());
+
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    fs::write(tmp.path().join("app"), b"binary").expect("Failed to write binary");
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        deb: deb(Some(1), Some(64 * 1024)),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+    let deb_path = bundle_project(&settings, &mut None).expect("Failed to bundle deb")[0].clone();
+
+    let mut archive = ar::Archive::new(fs::File::open(&deb_path).unwrap());
+    let mut members = Vec::new();
+    while let Some(entry) = archive.next_entry() {
+      members.push(String::from_utf8(entry.unwrap().header().identifier().to_vec()).unwrap());
+    }
+    assert_eq!(members, ["debian-binary", "control.tar.gz", "data.tar.xz"]);
+    let data_archive = deb_path.with_extension("").join("data.tar.xz");
+    let output = Command::new("xz")
+      .args(["--list", "--verbose", "--verbose"])
+      .arg(&data_archive)
+      .output()
+      .expect("failed to run xz");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--lzma2=dict=64KiB"));
+  }
+
+  #[test]
+  fn installs_in_a_container() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let package_path = tmp.path().join("it's_1.0.0_amd64.deb");
+    fs::write(&package_path, b"deb").unwrap();
+    let args = install_test_args(&package_path, "debian:bookworm", None).unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    assert_eq!(args[5], format!("{}:/deb:ro", dir.display()).as_str());
+    assert_eq!(args[6], "debian:bookworm");
+    assert_eq!(
+      args[9],
+      "apt-get update -qq && apt-get install -y --no-install-recommends '/deb/it'\\''s_1.0.0_amd64.deb'"
+    );
+
+    // the container runs the variant of a multi-arch image matching the package
+    let args = install_test_args(&package_path, "debian:bookworm", Some("linux/arm64")).unwrap();
+    assert_eq!(args[2], "--platform");
+    assert_eq!(args[3], "linux/arm64");
+    assert_eq!(args[8], "debian:bookworm");
+  }
+
+  #[test]
+  fn desktop_file_with_a_custom_prefix() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings = test_settings(tmp.path(), Default::default(), "x86_64-unknown-linux-gnu");
+    let data_dir = tmp.path().join("data");
+    generate_desktop_file(
+      &settings,
+      &data_dir,
+      Path::new("/opt/my app"),
+      &Default::default(),
+    )
+    .unwrap();
+
+    let desktop_file =
+      fs::read_to_string(data_dir.join("opt/my app/share/applications/app.desktop")).unwrap();
+    assert!(desktop_file.contains("\nExec=\"/opt/my app/bin/app\"\n"));
+    assert!(desktop_file.contains("\nIcon=app\n"));
+
+    assert_eq!(desktop_exec_arg("/opt/app/bin/app"), "/opt/app/bin/app");
+    assert_eq!(desktop_exec_arg("/opt/100%/app"), "/opt/100%%/app");
+    // the quoted backslash is escaped again as a string value
+    assert_eq!(desktop_exec_arg("/opt/a$b\\c"), "\"/opt/a\\\\$b\\\\\\\\c\"");
+  }
+
+  #[test]
+  fn refreshes_desktop_caches() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let data_dir = tmp.path().join("data");
+    let control_dir = tmp.path().join("control");
+    fs::create_dir_all(&control_dir).unwrap();
+
+    // nothing to refresh
+    generate_maintainer_scripts(&control_dir, &data_dir, Path::new("/usr")).unwrap();
+    assert!(!control_dir.join("postinst").exists());
+
+    fs::create_dir_all(data_dir.join("opt/app/share/applications")).unwrap();
+    fs::write(data_dir.join("opt/app/share/applications/app.desktop"), "").unwrap();
+    fs::create_dir_all(data_dir.join("opt/app/share/icons/hicolor/32x32/apps")).unwrap();
+    generate_maintainer_scripts(&control_dir, &data_dir, Path::new("/opt/app")).unwrap();
+
+    let postinst = fs::read_to_string(control_dir.join("postinst")).unwrap();
+    assert!(postinst.starts_with("#!/bin/sh\nset -e\n"));
+    assert!(postinst.contains("  update-desktop-database -q /opt/app/share/applications || true\n"));
+    assert!(postinst.contains("gtk-update-icon-cache -q -t -f /opt/app/share/icons/hicolor"));
+    assert!(!postinst.contains("update-mime-database"));
+    assert_eq!(
+      fs::read_to_string(control_dir.join("postrm")).unwrap(),
+      postinst
+    );
+  }
+
+  #[test]
+  fn splits_debug_symbols() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let data_dir = tmp.path().join("data");
+    let bin_dir = data_dir.join("usr/bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let source = tmp.path().join("main.c");
+    fs::write(&source, "int main() { return 0; }").unwrap();
+    let status = Command::new("cc")
+      .arg("-g")
+      .arg("-o")
+      .arg(bin_dir.join("app"))
+      .arg(&source)
+      .status()
+      .expect("failed to run cc");
+    assert!(status.success());
+
+    let settings = test_settings(tmp.path(), Default::default(), "x86_64-unknown-linux-gnu");
+
+    let dbgsym_data_dir = tmp.path().join("dbgsym");
+    split_debug_symbols(&settings, &data_dir, Path::new("/usr"), &dbgsym_data_dir)
+      .expect("Failed to split debug symbols");
+
+    let debug_files = WalkDir::new(dbgsym_data_dir.join("usr/lib/debug"))
+      .into_iter()
+      .filter_map(Result::ok)
+      .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "debug"))
+      .count();
+    assert_eq!(debug_files, 1);
+    assert!(Command::new(bin_dir.join("app"))
+      .status()
+      .expect("failed to run the stripped binary")
+      .success());
+  }
+}
@@ -0,0 +1,116 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::super::{
+  audit,
+  common::{self, CommandExt},
+  paths,
+};
+use crate::{GpgSettings, Settings};
+use anyhow::Context;
+use log::info;
+
+use std::{
+  env,
+  io::Write,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+/// The environment variable holding the passphrase of the signing key.
+const PASSPHRASE_ENV: &str = "TAURI_GPG_PASSPHRASE";
+
+/// Signs the bundles with [`crate::BundleSettings::gpg`], if it is set,
+/// and returns the bundle paths followed by the paths of their signatures.
+pub fn sign_all(settings: &Settings, mut paths: Vec<PathBuf>) -> crate::Result<Vec<PathBuf>> {
+  let gpg = match settings.gpg() {
+    Some(gpg) => gpg,
+    None => return Ok(paths),
+  };
+
+  // a temporary home isolates an imported key file from the user's keyring
+  let home = tempfile::tempdir()?;
+  let key_path = Path::new(&gpg.key);
+  let imported = key_path.is_file();
+  if imported {
+    gpg_command(home.path(), true)
+      .arg("--import")
+      .arg(key_path)
+      .output_ok()
+      .context("failed to import the GPG key file")?;
+  }
+
+  // the passphrase is passed in a file, so it does not show up in the process list
+  let passphrase_file = match env::var_os(PASSPHRASE_ENV) {
+    Some(passphrase) => {
+      let path = home.path().join("passphrase");
+      let mut file = common::create_file(&path)?;
+      file.write_all(passphrase.to_string_lossy().as_bytes())?;
+      file.flush()?;
+      Some(path)
+    }
+    None => None,
+  };
+
+  let mut signatures = Vec::new();
+  for path in &paths {
+    let result = sign(gpg, home.path(), imported, passphrase_file.as_deref(), path);
+    signatures.push(audit::record(
+      settings, "gpg", &gpg.key, path, None, result,
+    )?);
+  }
+  paths.extend(signatures);
+  Ok(paths)
+}
+
+// Creates the detached signature of a bundle next to it and verifies it.
+fn sign(
+  gpg: &GpgSettings,
+  home: &Path,
+  imported: bool,
+  passphrase_file: Option<&Path>,
+  path: &Path,
+) -> crate::Result<PathBuf> {
+  let signature_path = paths::gpg_signature_path(path, gpg.armor);
+
+  info!(action = "Signing"; "{} with GPG", path.display());
+
+  let mut command = gpg_command(home, imported);
+  command.args(["--yes", "--detach-sign"]);
+  if gpg.armor {
+    command.arg("--armor");
+  }
+  if !imported {
+    command.arg("--local-user").arg(&gpg.key);
+  }
+  if let Some(passphrase_file) = passphrase_file {
+    command
+      .args(["--pinentry-mode", "loopback", "--passphrase-file"])
+      .arg(passphrase_file);
+  }
+  command
+    .arg("--output")
+    .arg(&signature_path)
+    .arg(path)
+    .output_ok()
+    .with_context(|| format!("failed to sign {} with GPG", path.display()))?;
+
+  gpg_command(home, imported)
+    .arg("--verify")
+    .arg(&signature_path)
+    .arg(path)
+    .output_ok()
+    .with_context(|| format!("the GPG signature of {} is invalid", path.display()))?;
+
+  Ok(signature_path)
+}
+
+fn gpg_command(home: &Path, imported: bool) -> Command {
+  let mut command = Command::new("gpg");
+  command.arg("--batch");
+  if imported {
+    command.arg("--homedir").arg(home);
+  }
+  command
+}
@@ -4,5 +4,8 @@
 // SPDX-License-Identifier: MIT
 
 pub mod appimage;
+mod copyright;
 pub mod debian;
+pub mod gpg;
+pub mod payload;
 pub mod rpm;
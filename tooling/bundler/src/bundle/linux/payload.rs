@@ -0,0 +1,238 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::debian::{self, DebIcon};
+use crate::Settings;
+use anyhow::Context;
+use log::debug;
+
+use std::{
+  collections::BTreeSet,
+  env, fs,
+  path::{Path, PathBuf},
+};
+
+/// The `/usr` file tree shared by the deb and AppImage bundles.
+///
+/// It is staged by the first of those bundlers to run and reused by the other one,
+/// so the binaries, resources and icons are only copied once per invocation.
+/// The staged tree is removed when the payload is dropped, after the last of those bundlers ran.
+pub struct Payload {
+  /// The directory holding the `usr` tree.
+  pub data_dir: PathBuf,
+  /// The icons installed in the tree.
+  pub icons: BTreeSet<DebIcon>,
+  /// The `bundle/linux_payload` directory the tree was staged in, `None` for a prebuilt tree.
+  staging_dir: Option<PathBuf>,
+}
+
+impl Drop for Payload {
+  fn drop(&mut self) {
+    if let Some(staging_dir) = &self.staging_dir {
+      if let Err(e) = fs::remove_dir_all(staging_dir) {
+        debug!(
+          "failed to remove the staged payload {}: {}",
+          staging_dir.display(),
+          e
+        );
+      }
+    }
+  }
+}
+
+/// Returns the staged payload, staging it first if no previous bundler did.
+///
+/// A [`crate::BundleSettings::prebuilt_bundle`] is used as the payload without staging it.
+pub fn stage<'a>(
+  settings: &Settings,
+  payload: &'a mut Option<Payload>,
+) -> crate::Result<&'a Payload> {
+  if let (None, Some(prebuilt_bundle)) = (&payload, settings.prebuilt_bundle()) {
+    let data_dir = env::current_dir()?.join(prebuilt_bundle);
+    if !data_dir.is_dir() {
+      return Err(crate::Error::GenericError(format!(
+        "the prebuilt bundle {} is not a directory",
+        data_dir.display()
+      )));
+    }
+    let icons = prebuilt_icons(&data_dir)?;
+    *payload = Some(Payload {
+      data_dir,
+      icons,
+      staging_dir: None,
+    });
+  }
+  if payload.is_none() {
+    let package_dir = settings
+      .project_out_directory()
+      .join("bundle/linux_payload");
+    if package_dir.exists() {
+      fs::remove_dir_all(&package_dir).context("Failed to remove old staged payload")?;
+    }
+    let (data_dir, icons) = debian::generate_data(
+      settings,
+      &package_dir,
+      Path::new(debian::DEFAULT_INSTALL_PREFIX),
+    )
+    .context("Failed to stage the package payload")?;
+    *payload = Some(Payload {
+      data_dir,
+      icons,
+      staging_dir: Some(package_dir),
+    });
+  }
+  Ok(payload.as_ref().unwrap())
+}
+
+// The PNG icons of the hicolor theme in a prebuilt payload, in size folders like `32x32` or `128x128@2x`.
+fn prebuilt_icons(data_dir: &Path) -> crate::Result<BTreeSet<DebIcon>> {
+  let mut icons = BTreeSet::new();
+  let hicolor_dir = data_dir.join("usr/share/icons/hicolor");
+  if !hicolor_dir.is_dir() {
+    return Ok(icons);
+  }
+  for entry in fs::read_dir(hicolor_dir)? {
+    let entry = entry?;
+    let size = entry.file_name().to_string_lossy().into_owned();
+    let (size, is_high_density) = match size.strip_suffix("@2x") {
+      Some(size) => (size, true),
+      None => (size.as_str(), false),
+    };
+    let dimensions = size
+      .split_once('x')
+      .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+    let apps_dir = entry.path().join("apps");
+    let (width, height) = match dimensions {
+      Some(dimensions) if apps_dir.is_dir() => dimensions,
+      _ => continue,
+    };
+    for icon in fs::read_dir(apps_dir)? {
+      let path = icon?.path();
+      if path.extension().map_or(false, |ext| ext == "png") {
+        icons.insert(DebIcon {
+          width,
+          height,
+          is_high_density,
+          path,
+        });
+      }
+    }
+  }
+  Ok(icons)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{stage, Payload};
+  use crate::{
+    bundle::linux::{appimage, debian},
+    bundle::settings::test_settings,
+    BundleSettings, DebianSettings,
+  };
+  use std::{fs, path::Path};
+  use walkdir::WalkDir;
+
+  fn file_tree(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    WalkDir::new(dir)
+      .sort_by_file_name()
+      .into_iter()
+      .map(|entry| entry.expect("Failed to read dir entry"))
+      .filter(|entry| entry.file_type().is_file())
+      .map(|entry| {
+        (
+          entry
+            .path()
+            .strip_prefix(dir)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned(),
+          fs::read(entry.path()).expect("Failed to read file"),
+        )
+      })
+      .collect()
+  }
+
+  #[test]
+  fn deb_reuses_staged_payload() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    fs::write(tmp.path().join("app"), b"binary").expect("Failed to write binary");
+    let settings = test_settings(tmp.path(), Default::default(), "x86_64-unknown-linux-gnu");
+
+    let mut payload = None;
+    let deb = debian::bundle_project(&settings, &mut payload).expect("Failed to bundle deb");
+    assert!(deb[0].is_file());
+
+    let Payload { data_dir, .. } = payload.as_ref().expect("payload was not staged");
+    assert!(data_dir.join("usr/bin/app").is_file());
+    let staged = file_tree(data_dir);
+    let deb_data_dir = tmp.path().join("bundle/deb/app_1.0.0_amd64/data");
+    assert_eq!(staged, file_tree(&deb_data_dir));
+
+    // a second bundler gets the same tree without staging it again
+    fs::write(data_dir.join("marker"), b"").expect("Failed to write marker");
+    let reused = stage(&settings, &mut payload).expect("Failed to stage payload");
+    assert!(reused.data_dir.join("marker").exists());
+
+    // the AppDir gets the same tree
+    fs::remove_file(reused.data_dir.join("marker")).unwrap();
+    let app_dir = tmp.path().join("bundle/appimage/app.AppDir");
+    fs::create_dir_all(&app_dir).unwrap();
+    appimage::create_app_dir(reused, &app_dir).expect("Failed to create the AppDir");
+    assert_eq!(file_tree(&app_dir), staged);
+
+    // the staged tree is removed once the bundlers are done, the deb keeps its linked copy
+    drop(payload);
+    assert!(!tmp.path().join("bundle/linux_payload").exists());
+    assert_eq!(file_tree(&deb_data_dir), staged);
+  }
+
+  #[test]
+  fn deb_packages_prebuilt_bundle() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let prebuilt = tmp.path().join("prebuilt");
+    for path in ["usr/bin/app", "usr/share/icons/hicolor/32x32/apps/app.png"] {
+      fs::create_dir_all(prebuilt.join(path).parent().unwrap()).unwrap();
+      fs::write(prebuilt.join(path), b"file").unwrap();
+    }
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        prebuilt_bundle: Some(prebuilt.clone()),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+
+    let mut payload = None;
+    let deb = debian::bundle_project(&settings, &mut payload).expect("Failed to bundle deb");
+    assert!(deb[0].is_file());
+    let payload = payload.expect("payload was not set");
+    assert_eq!(payload.data_dir, prebuilt);
+    assert_eq!(
+      payload
+        .icons
+        .iter()
+        .map(|icon| icon.width)
+        .collect::<Vec<_>>(),
+      vec![32]
+    );
+    let deb_data_dir = tmp.path().join("bundle/deb/app_1.0.0_amd64/data");
+    assert_eq!(file_tree(&deb_data_dir), file_tree(&prebuilt));
+
+    // the prebuilt tree is installed at `/`, so a custom prefix is rejected
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        prebuilt_bundle: Some(prebuilt),
+        deb: DebianSettings {
+          install_prefix: Some("/opt/app".into()),
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+    assert!(debian::bundle_project(&settings, &mut None).is_err());
+  }
+}
@@ -23,31 +23,45 @@
 // files into the `Contents` directory of the bundle.
 
 use super::{
-  super::common,
+  super::{
+    common::{self, CommandExt},
+    paths,
+  },
   icon::create_icns_file,
-  sign::{notarize, notarize_auth_args, sign},
+  sign::{notarize, notarize_auth_args, sign, sign_helper},
 };
-use crate::Settings;
+use crate::{MultiArchStrategy, Settings};
 
 use anyhow::Context;
 use log::{info, warn};
+use regex::Regex;
 
 use std::{
   fs,
   path::{Path, PathBuf},
+  process::Command,
 };
 
 /// Bundles the project.
 /// Returns a vector of PathBuf that shows where the .app was created.
 pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
-  // we should use the bundle name (App name) as a MacOS standard.
-  // version or platform shouldn't be included in the App name.
-  let app_product_name = format!("{}.app", settings.product_name());
+  let extension = paths::app_bundle_extension(settings);
+  if extension.is_empty()
+    || extension.starts_with('.')
+    || extension.contains(|c: char| c == '/' || c == '\\' || c.is_whitespace())
+  {
+    return Err(crate::Error::GenericError(format!(
+      "invalid app bundle extension `{}`, expected a file extension like `app` without the dot",
+      extension
+    )));
+  }
 
-  let app_bundle_path = settings
-    .project_out_directory()
-    .join("bundle/macos")
-    .join(&app_product_name);
+  let app_bundle_path = paths::app_bundle_path(settings);
+  let app_product_name = app_bundle_path
+    .file_name()
+    .expect("failed to get bundle filename")
+    .to_string_lossy()
+    .into_owned();
 
   info!(action = "Bundling"; "{} ({})", app_product_name, app_bundle_path.display());
 
@@ -56,7 +70,77 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
       .with_context(|| format!("Failed to remove old {}", app_product_name))?;
   }
   let bundle_directory = app_bundle_path.join("Contents");
-  fs::create_dir_all(&bundle_directory).with_context(|| {
+  if let Some(prebuilt_bundle) = settings.prebuilt_bundle() {
+    copy_prebuilt_bundle(prebuilt_bundle, &app_bundle_path)?;
+  } else {
+    stage_app_bundle(&bundle_directory, settings)?;
+  }
+
+  if settings.macos().strip_extended_attributes {
+    remove_extended_attributes(&app_bundle_path)?;
+  }
+
+  if let Some(identity) = settings
+    .macos()
+    .signing_identity
+    .as_ref()
+    .filter(|_| settings.macos().sign_app)
+  {
+    settings.check_cancelled()?;
+    // the helper is signed on its own first, the `--deep` signature of the app below then
+    // re-signs it along with the rest of the nested code
+    if let Some(crash_reporter) = settings
+      .macos()
+      .crash_reporter
+      .as_ref()
+      .filter(|_| settings.prebuilt_bundle().is_none())
+    {
+      sign_helper(
+        &bundle_directory.join(crash_reporter.bundle_path()),
+        identity,
+        settings,
+        crash_reporter.entitlements.as_deref(),
+      )?;
+    }
+    // sign application
+    let manifest_path = sign(app_bundle_path.clone(), identity, settings, true)?;
+    // notarization is required for distribution
+    if settings.macos().notarize_app {
+      settings.check_cancelled()?;
+      match notarize_auth_args() {
+        Ok(args) => {
+          notarize(app_bundle_path.clone(), args, settings)?;
+        }
+        Err(e) => {
+          warn!("skipping app notarization, {}", e.to_string());
+        }
+      }
+    }
+    super::gatekeeper::assess(&app_bundle_path, settings, true)?;
+    if let Some(manifest_path) = manifest_path {
+      return Ok(vec![app_bundle_path, manifest_path]);
+    }
+  }
+
+  Ok(vec![app_bundle_path])
+}
+
+// Copies the `prebuilt_bundle` to the app bundle path, keeping its symlinks.
+fn copy_prebuilt_bundle(prebuilt_bundle: &Path, app_bundle_path: &Path) -> crate::Result<()> {
+  if !prebuilt_bundle.join("Contents/Info.plist").is_file() {
+    return Err(crate::Error::GenericError(format!(
+      "the prebuilt bundle {} is not an app bundle, it has no Contents/Info.plist",
+      prebuilt_bundle.display()
+    )));
+  }
+  common::copy_dir(prebuilt_bundle, app_bundle_path)
+    .with_context(|| format!("Failed to copy the prebuilt bundle {:?}", prebuilt_bundle))?;
+  Ok(())
+}
+
+// Creates the contents of the app bundle from the binaries, resources and frameworks.
+fn stage_app_bundle(bundle_directory: &Path, settings: &Settings) -> crate::Result<()> {
+  fs::create_dir_all(bundle_directory).with_context(|| {
     format!(
       "Failed to create bundle directory at {:?}",
       bundle_directory
@@ -69,45 +153,207 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
   let bundle_icon_file: Option<PathBuf> =
     { create_icns_file(&resources_dir, settings).with_context(|| "Failed to create app icon")? };
 
-  create_info_plist(&bundle_directory, bundle_icon_file, settings)
+  create_info_plist(bundle_directory, bundle_icon_file, settings)
     .with_context(|| "Failed to create Info.plist")?;
 
-  copy_frameworks_to_bundle(&bundle_directory, settings)
+  copy_frameworks_to_bundle(bundle_directory, settings)
     .with_context(|| "Failed to bundle frameworks")?;
 
   settings.copy_resources(&resources_dir)?;
+  // an app bundle has no installer to put the shared resources outside of it
+  settings.copy_shared_resources(&bundle_directory.join("SharedSupport"))?;
+  settings.write_build_info(&resources_dir)?;
+  settings.write_release_notes(&resources_dir)?;
+  write_display_names(&resources_dir, settings)
+    .with_context(|| "Failed to write the localized app names")?;
 
   settings
     .copy_binaries(&bin_dir)
     .with_context(|| "Failed to copy external binaries")?;
 
-  copy_binaries_to_bundle(&bundle_directory, settings)?;
+  copy_binaries_to_bundle(bundle_directory, settings)?;
+  add_rpaths(&bin_dir, settings)?;
 
-  if let Some(identity) = &settings.macos().signing_identity {
-    // sign application
-    sign(app_bundle_path.clone(), identity, settings, true)?;
-    // notarization is required for distribution
-    match notarize_auth_args() {
-      Ok(args) => {
-        notarize(app_bundle_path.clone(), args, settings)?;
-      }
-      Err(e) => {
-        warn!("skipping app notarization, {}", e.to_string());
-      }
-    }
+  if let Some(crash_reporter) = &settings.macos().crash_reporter {
+    copy_crash_reporter(bundle_directory, crash_reporter)
+      .with_context(|| "Failed to bundle the crash reporter")?;
   }
+  Ok(())
+}
 
-  Ok(vec![app_bundle_path])
+// Recursively removes the extended attributes (e.g. `com.apple.quarantine`) of the bundle contents.
+fn remove_extended_attributes(path: &Path) -> crate::Result<()> {
+  Command::new("xattr")
+    .arg("-cr")
+    .arg(path)
+    .output_ok()
+    .context("failed to remove extended attributes")?;
+  Ok(())
+}
+
+// Copies the crash reporter helper to its location in the bundle, keeping its symlinks and permissions.
+fn copy_crash_reporter(
+  bundle_directory: &Path,
+  crash_reporter: &crate::CrashReporter,
+) -> crate::Result<()> {
+  let bundle_path = crash_reporter.bundle_path();
+  if bundle_path.is_absolute()
+    || bundle_path
+      .components()
+      .any(|c| matches!(c, std::path::Component::ParentDir))
+  {
+    return Err(crate::Error::GenericError(format!(
+      "the crash reporter location {} must be relative to the `Contents` folder of the app bundle",
+      bundle_path.display()
+    )));
+  }
+  let dest_path = bundle_directory.join(&bundle_path);
+  if crash_reporter.path.is_dir() {
+    common::copy_dir(&crash_reporter.path, &dest_path)
+  } else {
+    common::copy_file(&crash_reporter.path, &dest_path)
+  }
+  .with_context(|| {
+    format!(
+      "Failed to copy crash reporter from {:?}",
+      crash_reporter.path
+    )
+  })?;
+  Ok(())
 }
 
 // Copies the app's binaries to the bundle.
 fn copy_binaries_to_bundle(bundle_directory: &Path, settings: &Settings) -> crate::Result<()> {
   let dest_dir = bundle_directory.join("MacOS");
+  let macos = settings.macos();
   for bin in settings.binaries() {
     let bin_path = settings.binary_path(bin);
-    common::copy_file(&bin_path, &dest_dir.join(bin.name()))
-      .with_context(|| format!("Failed to copy binary from {:?}", bin_path))?;
+    let dest_path = dest_dir.join(bin.name());
+    if bin.main()
+      && !macos.arch_binaries.is_empty()
+      && macos.multi_arch_strategy == MultiArchStrategy::Lipo
+    {
+      create_universal_binary(&bin_path, macos.arch_binaries.values(), &dest_path)?;
+    } else {
+      common::copy_file(&bin_path, &dest_path)
+        .with_context(|| format!("Failed to copy binary from {:?}", bin_path))?;
+    }
+  }
+  Ok(())
+}
+
+// Adds the `rpaths` to the main and external binaries, which must happen before they are signed.
+fn add_rpaths(bin_dir: &Path, settings: &Settings) -> crate::Result<()> {
+  let rpaths = &settings.macos().rpaths;
+  if rpaths.is_empty() {
+    return Ok(());
+  }
+  for rpath in rpaths {
+    validate_rpath(rpath)?;
+  }
+  // the app signature replaces the linker's ad-hoc signature the rpaths invalidate
+  let signed = settings.macos().signing_identity.is_some() && settings.macos().sign_app;
+
+  let mut binaries = settings
+    .binaries()
+    .iter()
+    .map(|bin| bin_dir.join(bin.name()))
+    .collect::<Vec<_>>();
+  for src in settings.external_binaries() {
+    binaries.push(
+      bin_dir.join(
+        src?
+          .file_name()
+          .expect("failed to extract external binary filename")
+          .to_string_lossy()
+          .replace(&format!("-{}", settings.target()), ""),
+      ),
+    );
+  }
+
+  for binary in binaries {
+    let output = common::tool_command("otool")
+      .arg("-l")
+      .arg(&binary)
+      .output_ok()
+      .with_context(|| format!("failed to read the load commands of {}", binary.display()))?;
+    let existing = parse_rpaths(&String::from_utf8_lossy(&output.stdout));
+    for rpath in rpaths.iter().filter(|rpath| !existing.contains(rpath)) {
+      info!(action = "Adding"; "rpath {} to {}", rpath, binary.display());
+      common::tool_command("install_name_tool")
+        .arg("-add_rpath")
+        .arg(rpath)
+        .arg(&binary)
+        .output_ok()
+        .with_context(|| format!("failed to add the rpath {} to {}", rpath, binary.display()))?;
+    }
+    // arm64 Macs kill binaries with an invalid signature, so an unsigned app gets an ad-hoc one again
+    if !signed && rpaths.iter().any(|rpath| !existing.contains(rpath)) {
+      common::tool_command("codesign")
+        .args(["--force", "-s", "-"])
+        .arg(&binary)
+        .output_ok()
+        .with_context(|| format!("failed to ad-hoc sign {}", binary.display()))?;
+    }
+  }
+  Ok(())
+}
+
+// Checks that an rpath is absolute or relative to the binary, the only paths dyld resolves.
+fn validate_rpath(rpath: &str) -> crate::Result<()> {
+  let valid = rpath.starts_with('/')
+    || ["@executable_path", "@loader_path"].iter().any(|prefix| {
+      rpath
+        .strip_prefix(prefix)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+    });
+  let valid = valid && !rpath.contains(|c: char| c.is_control());
+  if valid {
+    Ok(())
+  } else {
+    Err(crate::Error::GenericError(format!(
+      "invalid rpath `{}`, expected an absolute path or a path starting with `@executable_path` or `@loader_path`",
+      rpath
+    )))
+  }
+}
+
+// The paths of the `LC_RPATH` load commands listed by `otool -l`.
+fn parse_rpaths(otool_output: &str) -> Vec<String> {
+  let mut rpaths = Vec::new();
+  let mut lines = otool_output.lines().map(str::trim);
+  while let Some(line) = lines.next() {
+    if line == "cmd LC_RPATH" {
+      // the command size comes first, then `path <path> (offset <n>)`
+      if let Some(path) = lines
+        .by_ref()
+        .take(2)
+        .find_map(|line| line.strip_prefix("path "))
+      {
+        let path = path.rsplit_once(" (offset").map_or(path, |(path, _)| path);
+        rpaths.push(path.to_string());
+      }
+    }
   }
+  rpaths
+}
+
+// Merges the binary and its builds for other architectures into a universal binary.
+fn create_universal_binary<'a>(
+  bin_path: &Path,
+  arch_binaries: impl Iterator<Item = &'a PathBuf>,
+  dest_path: &Path,
+) -> crate::Result<()> {
+  info!(action = "Merging"; "{} into a universal binary", bin_path.display());
+  fs::create_dir_all(dest_path.parent().expect("No data in parent"))?;
+  Command::new("lipo")
+    .arg("-create")
+    .arg(bin_path)
+    .args(arch_binaries)
+    .arg("-output")
+    .arg(dest_path)
+    .output_ok()
+    .context("failed to create universal binary with lipo")?;
   Ok(())
 }
 
@@ -130,6 +376,9 @@ fn create_info_plist(
     "CFBundleExecutable".into(),
     settings.main_binary_name().into(),
   );
+  if let Some(info) = &settings.macos().get_info_string {
+    plist.insert("CFBundleGetInfoString".into(), info.clone().into());
+  }
   if let Some(path) = bundle_icon_file {
     plist.insert(
       "CFBundleIconFile".into(),
@@ -143,17 +392,39 @@ fn create_info_plist(
   }
   plist.insert(
     "CFBundleIdentifier".into(),
-    settings.bundle_identifier().into(),
+    settings.macos_bundle_identifier().into(),
   );
   plist.insert("CFBundleInfoDictionaryVersion".into(), "6.0".into());
   plist.insert("CFBundleName".into(), settings.product_name().into());
   plist.insert("CFBundlePackageType".into(), "APPL".into());
   plist.insert(
     "CFBundleShortVersionString".into(),
-    settings.version_string().into(),
+    settings.short_version_string().into(),
+  );
+  plist.insert(
+    "CFBundleVersion".into(),
+    settings
+      .build_number()
+      .map(ToString::to_string)
+      .unwrap_or(build_number)
+      .into(),
   );
-  plist.insert("CFBundleVersion".into(), build_number.into());
   plist.insert("CSResourcesFileMapped".into(), true.into());
+  if !settings.macos().display_names.is_empty() {
+    plist.insert("LSHasLocalizedDisplayName".into(), true.into());
+  }
+  if let Some(crash_reporter) = &settings.macos().crash_reporter {
+    if let Some(key) = &crash_reporter.info_plist_key {
+      plist.insert(
+        key.clone(),
+        crash_reporter
+          .bundle_path()
+          .to_string_lossy()
+          .into_owned()
+          .into(),
+      );
+    }
+  }
   if let Some(category) = settings.app_category() {
     plist.insert(
       "LSApplicationCategoryType".into(),
@@ -164,11 +435,42 @@ fn create_info_plist(
     plist.insert("LSMinimumSystemVersion".into(), version.into());
   }
   plist.insert("LSRequiresCarbon".into(), true.into());
+  if !settings.macos().spotlight_keywords.is_empty() {
+    plist.insert(
+      "MDItemKeywords".into(),
+      settings.macos().spotlight_keywords.join(", ").into(),
+    );
+  }
   plist.insert("NSHighResolutionCapable".into(), true.into());
   if let Some(copyright) = settings.copyright_string() {
     plist.insert("NSHumanReadableCopyright".into(), copyright.into());
   }
 
+  if !settings.macos().deep_link_protocols.is_empty() {
+    for scheme in &settings.macos().deep_link_protocols {
+      validate_url_scheme(scheme)?;
+    }
+    let mut url_type = plist::Dictionary::new();
+    url_type.insert(
+      "CFBundleURLName".into(),
+      settings.macos_bundle_identifier().into(),
+    );
+    url_type.insert(
+      "CFBundleURLSchemes".into(),
+      settings
+        .macos()
+        .deep_link_protocols
+        .iter()
+        .map(|scheme| plist::Value::from(scheme.as_str()))
+        .collect::<Vec<_>>()
+        .into(),
+    );
+    plist.insert(
+      "CFBundleURLTypes".into(),
+      vec![plist::Value::from(url_type)].into(),
+    );
+  }
+
   if let Some(exception_domain) = settings.macos().exception_domain.clone() {
     let mut security = plist::Dictionary::new();
     let mut domain = plist::Dictionary::new();
@@ -190,11 +492,125 @@ fn create_info_plist(
     }
   }
 
-  plist::Value::Dictionary(plist).to_file_xml(bundle_dir.join("Info.plist"))?;
+  let mut plist = plist::Value::Dictionary(plist);
+  if let Some(transform) = &settings.macos().info_plist_transform {
+    transform.apply(&mut plist);
+  }
+  plist.to_file_xml(bundle_dir.join("Info.plist"))?;
 
   Ok(())
 }
 
+// Writes the localized app names to the `InfoPlist.strings` file of each locale,
+// merging them into the strings the resources already provide.
+fn write_display_names(resources_dir: &Path, settings: &Settings) -> crate::Result<()> {
+  let locale_regex =
+    Regex::new(r"^(Base|[a-z]{2,3}([_-][A-Z][a-z]{3})?([_-]([A-Z]{2}|[0-9]{3}))?)$")?;
+  for (locale, name) in &settings.macos().display_names {
+    if !locale_regex.is_match(locale) {
+      return Err(crate::Error::GenericError(format!(
+        "invalid display name locale `{}`, expected a language code like `de`, `pt-BR` or `zh-Hans`",
+        locale
+      )));
+    }
+    let strings_path = resources_dir
+      .join(format!("{}.lproj", locale))
+      .join("InfoPlist.strings");
+    fs::create_dir_all(strings_path.parent().expect("No data in parent"))?;
+    let contents = if strings_path.exists() {
+      fs::read(&strings_path)?
+    } else {
+      Vec::new()
+    };
+    let merged = merge_info_plist_strings(&contents, name).map_err(|message| {
+      crate::Error::GenericError(format!("{} {}", strings_path.display(), message))
+    })?;
+    fs::write(&strings_path, merged)?;
+  }
+  Ok(())
+}
+
+// Renders the localized Info.plist keys of the app name.
+fn info_plist_strings(name: &str) -> String {
+  let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+  format!(
+    "CFBundleDisplayName = \"{0}\";\nCFBundleName = \"{0}\";\n",
+    name
+  )
+}
+
+// Adds the localized app name keys to the contents of an `InfoPlist.strings` file, keeping its
+// UTF-8 or UTF-16 encoding. Fails if the file already defines the keys.
+fn merge_info_plist_strings(contents: &[u8], name: &str) -> Result<Vec<u8>, String> {
+  type ToBytes = fn(u16) -> [u8; 2];
+  let (text, utf16): (String, Option<ToBytes>) = if contents.starts_with(b"bplist") {
+    return Err("is a binary property list, convert it to a text strings file".into());
+  } else if let Some(rest) = contents.strip_prefix(&[0xff, 0xfe][..]) {
+    (
+      decode_utf16(rest, u16::from_le_bytes)?,
+      Some(u16::to_le_bytes),
+    )
+  } else if let Some(rest) = contents.strip_prefix(&[0xfe, 0xff][..]) {
+    (
+      decode_utf16(rest, u16::from_be_bytes)?,
+      Some(u16::to_be_bytes),
+    )
+  } else {
+    let text = String::from_utf8(contents.to_vec())
+      .map_err(|_| "is neither UTF-8 nor UTF-16 with a byte order mark".to_string())?;
+    (text, None)
+  };
+
+  let keys = Regex::new(r#"(?m)(^|;)\s*"?(CFBundleDisplayName|CFBundleName)"?\s*="#)
+    .expect("invalid strings key regex");
+  if let Some(captures) = keys.captures(text.trim_start_matches('\u{feff}')) {
+    return Err(format!(
+      "already defines `{}`, remove it or the display name of the locale",
+      &captures[2]
+    ));
+  }
+
+  let separator = if text.is_empty() || text.ends_with('\n') {
+    ""
+  } else {
+    "\n"
+  };
+  let strings = format!("{}{}", separator, info_plist_strings(name));
+  let mut merged = contents.to_vec();
+  match utf16 {
+    Some(to_bytes) => merged.extend(strings.encode_utf16().flat_map(to_bytes)),
+    None => merged.extend(strings.as_bytes()),
+  }
+  Ok(merged)
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<String, String> {
+  if bytes.len() % 2 != 0 {
+    return Err("is not valid UTF-16".into());
+  }
+  let units: Vec<u16> = bytes
+    .chunks_exact(2)
+    .map(|unit| from_bytes([unit[0], unit[1]]))
+    .collect();
+  String::from_utf16(&units).map_err(|_| "is not valid UTF-16".to_string())
+}
+
+// Checks that a deep link protocol is a URL scheme as defined by RFC 3986, in lowercase.
+fn validate_url_scheme(scheme: &str) -> crate::Result<()> {
+  let valid = scheme.starts_with(|c: char| c.is_ascii_lowercase())
+    && scheme
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '+' || c == '-' || c == '.');
+  if valid {
+    Ok(())
+  } else {
+    Err(crate::Error::GenericError(format!(
+      "invalid deep link protocol `{}`, expected a lowercase URL scheme like `myapp` without `://`",
+      scheme
+    )))
+  }
+}
+
 // Copies the framework under `{src_dir}/{framework}.framework` to `{dest_dir}/{framework}.framework`.
 fn copy_framework_from(dest_dir: &Path, framework: &str, src_dir: &Path) -> crate::Result<bool> {
   let src_name = format!("{}.framework", framework);
@@ -256,3 +672,100 @@ fn copy_frameworks_to_bundle(bundle_directory: &Path, settings: &Settings) -> cr
   }
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    info_plist_strings, merge_info_plist_strings, parse_rpaths, remove_extended_attributes,
+    validate_rpath, validate_url_scheme,
+  };
+  use std::process::Command;
+
+  #[test]
+  fn adds_valid_rpaths() {
+    assert!(validate_rpath("@executable_path/../Frameworks").is_ok());
+    assert!(validate_rpath("@loader_path/lib").is_ok());
+    assert!(validate_rpath("/usr/local/lib").is_ok());
+    assert!(validate_rpath("@executable_path").is_ok());
+    assert!(validate_rpath("@executable_pathfoo").is_err());
+    assert!(validate_rpath("@rpath/lib").is_err());
+    assert!(validate_rpath("../Frameworks").is_err());
+    assert!(validate_rpath("").is_err());
+
+    let otool_output = "Load command 12\n          cmd LC_RPATH\n      cmdsize 48\n         path @executable_path/../Frameworks (offset 12)\nLoad command 13\n      cmd LC_FUNCTION_STARTS\n  cmdsize 16\n";
+    assert_eq!(
+      parse_rpaths(otool_output),
+      vec!["@executable_path/../Frameworks".to_string()]
+    );
+  }
+
+  #[test]
+  fn renders_localized_names() {
+    assert_eq!(
+      info_plist_strings("Die \"App\""),
+      "CFBundleDisplayName = \"Die \\\"App\\\"\";\nCFBundleName = \"Die \\\"App\\\"\";\n"
+    );
+  }
+
+  #[test]
+  fn merges_localized_names() {
+    let strings = info_plist_strings("App");
+    assert_eq!(
+      merge_info_plist_strings(b"", "App").unwrap(),
+      strings.as_bytes()
+    );
+    assert_eq!(
+      merge_info_plist_strings(b"NSCameraUsageDescription = \"x\";", "App").unwrap(),
+      format!("NSCameraUsageDescription = \"x\";\n{}", strings).into_bytes()
+    );
+
+    // UTF-16 files stay UTF-16
+    let utf16 = |text: &str| -> Vec<u8> {
+      let mut bytes = vec![0xff, 0xfe];
+      bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+      bytes
+    };
+    let existing = "NSCameraUsageDescription = \"Kamera\";\n";
+    assert_eq!(
+      merge_info_plist_strings(&utf16(existing), "App").unwrap(),
+      utf16(&format!("{}{}", existing, strings))
+    );
+
+    assert!(merge_info_plist_strings(b"\"CFBundleName\" = \"Other\";\n", "App").is_err());
+    assert!(merge_info_plist_strings(&utf16("CFBundleDisplayName = \"Other\";"), "App").is_err());
+    assert!(merge_info_plist_strings(b"bplist00", "App").is_err());
+  }
+
+  #[test]
+  fn validates_url_schemes() {
+    assert!(validate_url_scheme("myapp").is_ok());
+    assert!(validate_url_scheme("my-app+beta.2").is_ok());
+    assert!(validate_url_scheme("myapp://").is_err());
+    assert!(validate_url_scheme("MyApp").is_err());
+    assert!(validate_url_scheme("2app").is_err());
+    assert!(validate_url_scheme("").is_err());
+  }
+
+  #[test]
+  fn strips_extended_attributes() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let file = tmp.path().join("Contents/MacOS/app");
+    std::fs::create_dir_all(file.parent().unwrap()).expect("Failed to create dir");
+    std::fs::write(&file, b"binary").expect("Failed to write file");
+
+    let status = Command::new("xattr")
+      .args(["-w", "com.apple.quarantine", "0081;00000000;Safari;"])
+      .arg(&file)
+      .status()
+      .expect("Failed to run xattr");
+    assert!(status.success());
+
+    remove_extended_attributes(tmp.path()).expect("Failed to remove extended attributes");
+
+    let output = Command::new("xattr")
+      .arg(&file)
+      .output()
+      .expect("Failed to run xattr");
+    assert!(output.stdout.is_empty());
+  }
+}
@@ -3,53 +3,214 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use super::{app, icon::create_icns_file};
+use super::{
+  app,
+  icon::create_icns_file,
+  sign::{notarize, notarize_auth_args, sign},
+};
 use crate::{
-  bundle::{common::CommandExt, Bundle},
+  bundle::{
+    common::{self, CommandExt},
+    paths, Bundle,
+  },
+  DmgBackend, DmgFormat, DmgLabelPosition, DmgLicenseLabels, DmgLicenseLanguage, HdiutilVerbosity,
+  MultiArchStrategy,
   PackageType::MacOsBundle,
   Settings,
 };
 
 use anyhow::Context;
-use log::info;
+use log::{debug, info, warn};
 
 use std::{
   env,
   fs::{self, write},
-  path::PathBuf,
+  io::Read,
+  path::{Path, PathBuf},
   process::{Command, Stdio},
 };
 
+/// Re-signs an existing read-only DMG in place with the identity, e.g. after rotating the signing certificate,
+/// without bundling the app again.
+///
+/// The new signature replaces the previous one and is checked with `codesign --verify --strict`,
+/// and the image checksums with `hdiutil verify`. The DMG is notarized again when [`crate::MacOsSettings::notarize_app`]
+/// is set and the notarization credentials are available.
+pub fn resign(dmg_path: &Path, identity: &str, settings: &Settings) -> crate::Result<()> {
+  if !dmg_path.is_file() || dmg_path.extension().map_or(true, |ext| ext != "dmg") {
+    return Err(crate::Error::GenericError(format!(
+      "{} is not a DMG file",
+      dmg_path.display()
+    )));
+  }
+  let mut settings = settings.clone();
+  settings.set_signing_identity(identity);
+  let settings = &settings;
+  let _tool_context = common::enter(settings)?;
+
+  info!(action = "Re-signing"; "{} with identity \"{}\"", dmg_path.display(), identity);
+  sign(dmg_path.to_path_buf(), identity, settings, false)?;
+  common::tool_command("codesign")
+    .args(["--verify", "--strict"])
+    .arg(dmg_path)
+    .output_ok()
+    .with_context(|| format!("the new signature of {} is invalid", dmg_path.display()))?;
+  verify_dmg(dmg_path, settings.dmg().hdiutil_verbosity)?;
+
+  if settings.macos().notarize_app && !super::sign::is_ad_hoc(identity) {
+    settings.check_cancelled()?;
+    match notarize_auth_args() {
+      Ok(args) => notarize(dmg_path.to_path_buf(), args, settings)?,
+      Err(e) => warn!("skipping DMG notarization, {}", e),
+    }
+  }
+  super::gatekeeper::assess(dmg_path, settings, false)
+}
+
 /// Bundles the project.
 /// Returns a vector of PathBuf that shows where the DMG was created.
 pub fn bundle_project(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<PathBuf>> {
+  let source_dir = match &settings.dmg().source_dir {
+    Some(source_dir) => {
+      let source_dir = env::current_dir()?.join(source_dir);
+      if !source_dir.is_dir() {
+        return Err(crate::Error::GenericError(format!(
+          "DMG source directory {} does not exist",
+          source_dir.display()
+        )));
+      }
+      Some(source_dir)
+    }
+    None => None,
+  };
+  let prebuilt_app = match &settings.dmg().app_path {
+    Some(app_path) => {
+      if source_dir.is_some() {
+        return Err(crate::Error::GenericError(
+          "the DMG `source_dir` and `app_path` cannot be used together".into(),
+        ));
+      }
+      let app_path = env::current_dir()?.join(app_path);
+      if !app_path.is_dir() {
+        return Err(crate::Error::GenericError(format!(
+          "DMG app {} does not exist",
+          app_path.display()
+        )));
+      }
+      if !settings.macos().arch_binaries.is_empty() {
+        warn!("the DMG `app_path` is imaged as is, ignoring the architecture binaries");
+      }
+      Some(app_path)
+    }
+    None => None,
+  };
+
   // generate the .app bundle if needed
-  if bundles
-    .iter()
-    .filter(|bundle| bundle.package_type == MacOsBundle)
-    .count()
-    == 0
+  if source_dir.is_none()
+    && prebuilt_app.is_none()
+    && bundles
+      .iter()
+      .filter(|bundle| bundle.package_type == MacOsBundle)
+      .count()
+      == 0
   {
     app::bundle_project(settings)?;
   }
 
-  // get the target path
-  let output_path = settings.project_out_directory().join("bundle/dmg");
-  let package_base_name = format!(
-    "{}_{}_{}",
-    settings.main_binary_name(),
-    settings.version_string(),
-    match settings.binary_arch() {
-      "x86_64" => "x64",
-      other => other,
+  let format = settings
+    .dmg()
+    .format
+    .unwrap_or(if settings.dmg().max_compression {
+      DmgFormat::Udbz
+    } else {
+      DmgFormat::Udzo
+    });
+  if let Some(size) = settings.dmg().text_size {
+    if !(10..=16).contains(&size) {
+      return Err(crate::Error::GenericError(format!(
+        "invalid DMG text size {}, Finder supports sizes between 10 and 16",
+        size
+      )));
     }
-  );
-  let dmg_name = format!("{}.dmg", &package_base_name);
-  let dmg_path = output_path.join(&dmg_name);
+  }
+
+  if let Some(background) = &settings.dmg().background {
+    if !background.is_file() {
+      return Err(crate::Error::GenericError(format!(
+        "DMG background image {} does not exist",
+        background.display()
+      )));
+    }
+  }
+  if let Some(ds_store) = &settings.dmg().ds_store {
+    validate_ds_store(ds_store)?;
+  }
 
-  let product_name = settings.main_binary_name();
-  let bundle_file_name = format!("{}.app", product_name);
-  let bundle_dir = settings.project_out_directory().join("bundle/macos");
+  let use_create_dmg = settings.dmg().backend == DmgBackend::CreateDmg;
+  if use_create_dmg {
+    if settings.dmg().ds_store.is_some() {
+      return Err(crate::Error::GenericError(
+        "the create-dmg DMG backend does not support a custom .DS_Store, use the hdiutil backend"
+          .into(),
+      ));
+    }
+    if settings.dmg().case_sensitive {
+      return Err(crate::Error::GenericError(
+        "the create-dmg DMG backend does not support case-sensitive volumes, use the hdiutil backend"
+          .into(),
+      ));
+    }
+    Command::new("create-dmg")
+      .arg("--version")
+      .output_ok()
+      .context("failed to run create-dmg, install it with `brew install create-dmg` or use the hdiutil DMG backend")?;
+  }
+
+  let sign_dmg = settings.macos().signing_identity.is_some() && settings.dmg().sign_dmg;
+  if format == DmgFormat::Udrw && sign_dmg {
+    return Err(crate::Error::GenericError(
+      "a read-write (UDRW) DMG cannot be signed, use a read-only format or disable `sign_dmg`"
+        .into(),
+    ));
+  }
+
+  // get the target path
+  let dmg_path = paths::dmg_path(settings);
+  let output_path = dmg_path.parent().expect("No data in parent").to_path_buf();
+  let dmg_name = format!("{}.dmg", settings.dmg_file_stem());
+
+  // user-facing labels use the product name, the binary keeps its own name inside the bundle
+  let product_name = settings.product_name();
+  let app_bundle_path = prebuilt_app
+    .clone()
+    .unwrap_or_else(|| paths::app_bundle_path(settings));
+  let extension = match &prebuilt_app {
+    Some(app_path) => app_path
+      .extension()
+      .map(|extension| extension.to_string_lossy().into_owned())
+      .unwrap_or_default(),
+    None => paths::app_bundle_extension(settings).to_string(),
+  };
+  let extension = extension.as_str();
+  let app_name = match &prebuilt_app {
+    Some(app_path) => app_path
+      .file_stem()
+      .expect("failed to get app name")
+      .to_string_lossy()
+      .into_owned(),
+    None => product_name.to_string(),
+  };
+  let bundle_file_name = format!("{}.{}", app_name, extension);
+  let bundle_dir = app_bundle_path
+    .parent()
+    .expect("No data in parent")
+    .to_path_buf();
+  if source_dir.is_none() && !app_bundle_path.is_dir() {
+    return Err(crate::Error::GenericError(format!(
+      "the app bundle {} does not exist, run the bundler with `--bundles app` to create it before the DMG",
+      app_bundle_path.display()
+    )));
+  }
 
   let support_directory_path = output_path.join("support");
   if output_path.exists() {
@@ -79,7 +240,7 @@ pub fn bundle_project(settings: &Settings, bundles: &[Bundle]) -> crate::Result<
   )?;
   write(
     support_directory_path.join("eula-resources-template.xml"),
-    include_str!("templates/dmg/eula-resources-template.xml"),
+    eula_resources_template(&settings.dmg().license_labels)?,
   )?;
 
   // chmod script for execution
@@ -92,28 +253,202 @@ pub fn bundle_project(settings: &Settings, bundles: &[Bundle]) -> crate::Result<
     .output()
     .expect("Failed to chmod script");
 
-  let mut args = vec![
-    "--volname",
-    product_name,
-    "--icon",
-    product_name,
-    "180",
-    "170",
-    "--app-drop-link",
-    "480",
-    "170",
-    "--window-size",
-    "660",
-    "400",
-    "--hide-extension",
-    &bundle_file_name,
-  ];
+  let mut args = vec!["--volname", product_name];
+
+  // the images are created in the temporary directory when one is set, and moved to the output directory
+  let temp_dir = match &settings.dmg().temp_dir {
+    Some(dir) => {
+      fs::create_dir_all(dir)?;
+      Some(
+        tempfile::Builder::new()
+          .prefix("tauri-dmg-")
+          .tempdir_in(dir)
+          .with_context(|| format!("Failed to create a temporary directory in {:?}", dir))?,
+      )
+    }
+    None => None,
+  };
+  let image_path = match &temp_dir {
+    Some(temp_dir) => temp_dir.path().join(&dmg_name),
+    None => PathBuf::from(&dmg_name),
+  };
+  let image_arg = image_path.to_string_lossy().into_owned();
+
+  // the image source is either the pre-staged directory, the per-architecture apps or the .app bundle
+  let macos = settings.macos();
+  let mut staging_dir = None;
+  let mut entitlements_manifests = Vec::new();
+  let (work_dir, source, app_names) = if let Some(source_dir) = &source_dir {
+    (
+      output_path.clone(),
+      source_dir.to_string_lossy().into_owned(),
+      Vec::new(),
+    )
+  } else if macos.multi_arch_strategy == MultiArchStrategy::SideBySide
+    && !macos.arch_binaries.is_empty()
+    && prebuilt_app.is_none()
+  {
+    let dir = temp_dir
+      .as_ref()
+      .map(|dir| dir.path().to_path_buf())
+      .unwrap_or_else(|| output_path.clone())
+      .join("staging");
+    let (app_names, manifests) = stage_arch_apps(settings, &app_bundle_path, &dir, extension)?;
+    entitlements_manifests = manifests;
+    let source = dir.to_string_lossy().into_owned();
+    staging_dir = Some(dir);
+    (output_path.clone(), source, app_names)
+  } else {
+    (bundle_dir, bundle_file_name.clone(), vec![app_name.clone()])
+  };
+  let layout_args = layout_args(
+    &app_names,
+    extension,
+    settings.dmg().create_application_folder_link,
+    settings.dmg().window_size,
+  );
+  let comment_args = comment_args(settings, &app_names, extension, use_create_dmg);
+
+  let window_position = settings
+    .dmg()
+    .window_position
+    .map(|(x, y)| (x.to_string(), y.to_string()));
+  if let Some((x, y)) = &window_position {
+    args.push("--window-pos");
+    args.push(x);
+    args.push(y);
+  }
+
+  let size_mb = settings.dmg().size_mb.map(|size| size.to_string());
+  if let Some(size) = settings.dmg().size_mb {
+    let mut payload_size = content_size(&work_dir.join(&source))?;
+    // the background and .DS_Store are copied to the volume after it is created
+    for file in [&settings.dmg().background, &settings.dmg().ds_store]
+      .into_iter()
+      .flatten()
+    {
+      payload_size += fs::metadata(file)?.len();
+    }
+    validate_image_size(size, payload_size, use_create_dmg)?;
+  }
+  if let Some(size_mb) = &size_mb {
+    args.push("--disk-image-size");
+    args.push(size_mb);
+  }
+
+  // the label position and settle delay are additions of the bundled script
+  if !use_create_dmg {
+    args.push("--label-position");
+    args.push(match settings.dmg().label_position {
+      DmgLabelPosition::Bottom => "bottom",
+      DmgLabelPosition::Right => "right",
+    });
+  } else if settings.dmg().label_position != DmgLabelPosition::Bottom {
+    warn!("the create-dmg DMG backend does not support the label position, ignoring it");
+  }
+
+  let text_size = settings.dmg().text_size.map(|size| size.to_string());
+  if let Some(text_size) = &text_size {
+    args.push("--text-size");
+    args.push(text_size);
+  }
 
-  let icns_icon_path =
-    create_icns_file(&output_path, settings)?.map(|path| path.to_string_lossy().to_string());
+  // the script runs in its work directory, so relative paths are resolved first
+  let background = match &settings.dmg().background {
+    Some(path) => Some(
+      env::current_dir()?
+        .join(path)
+        .to_string_lossy()
+        .into_owned(),
+    ),
+    None => None,
+  };
+  if let Some(background) = &background {
+    args.push("--background");
+    args.push(background);
+  }
+
+  let ds_store = match &settings.dmg().ds_store {
+    Some(path) => Some(
+      env::current_dir()?
+        .join(path)
+        .to_string_lossy()
+        .into_owned(),
+    ),
+    None => None,
+  };
+  if let Some(ds_store) = &ds_store {
+    args.push("--ds-store");
+    args.push(ds_store);
+  }
+
+  let volume_date = volume_date(settings)?;
+  if let Some(volume_date) = &volume_date {
+    if !use_create_dmg {
+      args.push("--volume-date");
+      args.push(volume_date);
+    } else {
+      warn!("the create-dmg DMG backend does not support the volume date, ignoring it");
+    }
+  }
+
+  if settings.dmg().case_sensitive {
+    args.push("--case-sensitive");
+  }
+
+  // both scripts pass the flag to every hdiutil command creating the image
+  match settings.dmg().hdiutil_verbosity {
+    HdiutilVerbosity::Normal => (),
+    HdiutilVerbosity::Quiet => args.push("--hdiutil-quiet"),
+    HdiutilVerbosity::Verbose => args.push("--hdiutil-verbose"),
+  }
+
+  let settle_delay = settings.dmg().settle_delay_ms.to_string();
+  if !use_create_dmg {
+    args.push("--settle-delay");
+    args.push(&settle_delay);
+  }
+
+  // the script converts its intermediate read-write image to this format and removes it afterwards
+  args.push("--format");
+  args.push(match format {
+    DmgFormat::Udzo => "UDZO",
+    DmgFormat::Udbz => "UDBZ",
+    DmgFormat::Ulfo => "ULFO",
+    DmgFormat::Ulmo => "ULMO",
+    DmgFormat::Udro => "UDRO",
+    DmgFormat::Udrw => "UDRW",
+  });
+
+  // most of the contents would not get smaller, so the slow highest level is not worth it
+  if use_create_dmg && !settings.dmg().fast_compression_extensions.is_empty() {
+    warn!("the create-dmg DMG backend does not support the compression level, ignoring `fast_compression_extensions`");
+  } else if matches!(format, DmgFormat::Udzo | DmgFormat::Udbz)
+    && !settings.dmg().fast_compression_extensions.is_empty()
+  {
+    let (compressed, total) = compressed_size(
+      &work_dir.join(&source),
+      &settings.dmg().fast_compression_extensions,
+    )?;
+    if compressed * 2 > total {
+      info!(
+        "{}% of the DMG contents are already compressed, compressing the image at the fastest level",
+        compressed * 100 / total
+      );
+      args.push("--compression-level");
+      args.push("1");
+    }
+  }
+
+  // without configured icons the volume has the default icon, but configured icons must not be dropped silently
+  let icns_icon_path = create_icns_file(&output_path, settings)
+    .context("failed to create the DMG volume icon")?
+    .map(|path| path.to_string_lossy().to_string());
   if let Some(icon) = &icns_icon_path {
     args.push("--volicon");
     args.push(icon);
+  } else {
+    debug!("no icon is configured, the DMG has the default volume icon");
   }
 
   #[allow(unused_assignments)]
@@ -135,21 +470,759 @@ pub fn bundle_project(settings: &Settings, bundles: &[Bundle]) -> crate::Result<
     }
   }
 
-  info!(action = "Running"; "bundle_dmg.sh");
+  let program = if use_create_dmg {
+    PathBuf::from("create-dmg")
+  } else {
+    bundle_script_path
+  };
+  let program_name = program
+    .file_name()
+    .expect("failed to get program name")
+    .to_string_lossy()
+    .into_owned();
+
+  settings.check_cancelled()?;
+  info!(action = "Running"; "{}", program_name);
 
   // execute the bundle script
-  Command::new(&bundle_script_path)
-    .current_dir(bundle_dir.clone())
+  let result = Command::new(&program)
+    .current_dir(&work_dir)
+    .env("HDIUTIL", common::tool_path("hdiutil"))
     .args(args)
-    .args(vec![dmg_name.as_str(), bundle_file_name.as_str()])
-    .output_ok()
-    .context("error running bundle_dmg.sh")?;
+    .args(layout_args)
+    .args(comment_args)
+    .args(vec![image_arg.as_str(), source.as_str()])
+    .output_ok();
+  if matches!(result, Err(crate::Error::Cancelled)) {
+    clean_up_cancelled(&work_dir.join(&image_path), staging_dir.as_deref());
+  }
+  result.with_context(|| format!("error running {}", program_name))?;
 
-  fs::rename(bundle_dir.join(dmg_name), dmg_path.clone())?;
+  move_file(&work_dir.join(image_path), &dmg_path)?;
 
-  // Sign DMG if needed
-  if let Some(identity) = &settings.macos().signing_identity {
+  // Sign DMG if needed, once the script is done converting and writing the license resources
+  // to the image, so the signature covers the image as it is distributed
+  if let Some(identity) = settings
+    .macos()
+    .signing_identity
+    .as_ref()
+    .filter(|_| sign_dmg)
+  {
+    settings.check_cancelled()?;
     super::sign::sign(dmg_path.clone(), identity, settings, false)?;
+    super::gatekeeper::assess(&dmg_path, settings, false)?;
+  }
+
+  if settings.dmg().verify && format != DmgFormat::Udrw {
+    verify_dmg(&dmg_path, settings.dmg().hdiutil_verbosity)?;
+  }
+
+  let mut paths = vec![dmg_path.clone()];
+  if let Some(split_size_mb) = settings.dmg().split_size_mb {
+    let split_size = split_size_mb.checked_mul(1024 * 1024).ok_or_else(|| {
+      crate::Error::GenericError(format!(
+        "the DMG split size of {}MB is too large",
+        split_size_mb
+      ))
+    })?;
+    if fs::metadata(&dmg_path)?.len() > split_size {
+      if sign_dmg {
+        warn!("the DMG signature is not kept by the segmented DMG set, only the app inside it is signed");
+      }
+      paths = split_dmg(settings, &dmg_path, split_size_mb, format)?;
+    } else {
+      // a single part still gets its manifest, so the outputs do not depend on the image size
+      paths.push(write_parts_manifest(
+        &dmg_path,
+        std::slice::from_ref(&dmg_path),
+      )?);
+    }
+  }
+  paths.extend(entitlements_manifests);
+  Ok(paths)
+}
+
+// Detaches and removes the read-write image a cancelled DMG script leaves mounted, along with
+// the partially written image and the staging directory.
+fn clean_up_cancelled(image_path: &Path, staging_dir: Option<&Path>) {
+  let image_name = image_path
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let dir = image_path.parent().unwrap_or_else(|| Path::new("."));
+  // the scripts name it `rw.<name>` or `rw.<pid>.<name>`
+  for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    if name.starts_with("rw.") && name.ends_with(&image_name) {
+      detach_image(&entry.path());
+      let _ = fs::remove_file(entry.path());
+    }
+  }
+  let _ = fs::remove_file(image_path);
+  if let Some(staging_dir) = staging_dir {
+    let _ = fs::remove_dir_all(staging_dir);
+  }
+}
+
+// Force-detaches the image if it is attached. hdiutil is run directly since the tools
+// run through `CommandExt` fail right away once cancelled.
+fn detach_image(image: &Path) {
+  let info = match common::tool_command("hdiutil")
+    .args(["info", "-plist"])
+    .output()
+  {
+    Ok(output) if output.status.success() => output.stdout,
+    _ => return,
+  };
+  for device in attached_devices(&info, image) {
+    debug!("detaching {} of the cancelled image {:?}", device, image);
+    let detached = common::tool_command("hdiutil")
+      .args(["detach", "-force", &device])
+      .output()
+      .map_or(false, |output| output.status.success());
+    if !detached {
+      warn!(
+        "failed to detach {} of the cancelled image {:?}",
+        device, image
+      );
+    }
+  }
+}
+
+// Returns the devices the image is attached as in the `hdiutil info -plist` output.
+fn attached_devices(info: &[u8], image: &Path) -> Vec<String> {
+  let image = fs::canonicalize(image).unwrap_or_else(|_| image.to_path_buf());
+  let info: plist::Value = match plist::from_bytes(info) {
+    Ok(info) => info,
+    Err(_) => return Vec::new(),
+  };
+  info
+    .as_dictionary()
+    .and_then(|info| info.get("images"))
+    .and_then(|images| images.as_array())
+    .into_iter()
+    .flatten()
+    .filter_map(|image| image.as_dictionary())
+    .filter(|attached| {
+      attached
+        .get("image-path")
+        .and_then(|path| path.as_string())
+        .map_or(false, |path| Path::new(path) == image)
+    })
+    // the first entity is the whole disk, detaching it detaches its volumes
+    .filter_map(|attached| {
+      attached
+        .get("system-entities")
+        .and_then(|entities| entities.as_array())
+        .and_then(|entities| entities.first())
+        .and_then(|entity| entity.as_dictionary())
+        .and_then(|entity| entity.get("dev-entry"))
+        .and_then(|device| device.as_string())
+        .map(ToString::to_string)
+    })
+    .collect()
+}
+
+// Segments the DMG into parts of at most `split_size_mb`, which hdiutil mounts as one image,
+// and returns the parts in mount order followed by their manifest.
+fn split_dmg(
+  settings: &Settings,
+  dmg_path: &Path,
+  split_size_mb: u64,
+  format: DmgFormat,
+) -> crate::Result<Vec<PathBuf>> {
+  info!(action = "Splitting"; "{} into {}MB parts", dmg_path.display(), split_size_mb);
+  let output_path = dmg_path.parent().expect("No data in parent");
+  let file_name = dmg_path.file_name().expect("failed to get DMG filename");
+
+  // hdiutil cannot segment an image in place, so the parts are created next to it first
+  let segment_dir = tempfile::Builder::new()
+    .prefix(".dmg-segments")
+    .tempdir_in(output_path)?;
+  let mut command = common::tool_command("hdiutil");
+  command.arg("segment");
+  if let Some(flag) = hdiutil_verbosity_flag(settings.dmg().hdiutil_verbosity) {
+    command.arg(flag);
+  }
+  command
+    .arg("-segmentSize")
+    .arg(format!("{}m", split_size_mb))
+    .arg("-o")
+    .arg(segment_dir.path().join(file_name))
+    .arg(dmg_path)
+    .output_ok()
+    .context("failed to segment the DMG")?;
+  fs::remove_file(dmg_path)?;
+
+  let mut parts = Vec::new();
+  for entry in fs::read_dir(segment_dir.path())? {
+    let part = entry?.path();
+    let target = output_path.join(part.file_name().expect("failed to get part filename"));
+    fs::rename(&part, &target)?;
+    parts.push(target);
+  }
+  // the first segment keeps the `.dmg` extension and is followed by the numbered `.dmgpart` files
+  parts.sort_by_key(|part| (part.as_path() != dmg_path, part.clone()));
+
+  if settings.dmg().verify && format != DmgFormat::Udrw {
+    verify_dmg(dmg_path, settings.dmg().hdiutil_verbosity)?;
+  }
+  let manifest_path = write_parts_manifest(dmg_path, &parts)?;
+  verify_parts(&manifest_path)?;
+  parts.push(manifest_path);
+  Ok(parts)
+}
+
+// Writes the `<dmg>.parts.json` manifest listing the parts in mount order with their size and SHA-256 hash.
+//
+// Every segment carries its own UDIF trailer, so the parts do not concatenate back into the unsegmented
+// image and the manifest has no hash of it; `hdiutil verify` checks the set as one image instead.
+fn write_parts_manifest(dmg_path: &Path, parts: &[PathBuf]) -> crate::Result<PathBuf> {
+  let mut entries = Vec::new();
+  for part in parts {
+    entries.push(serde_json::json!({
+      "file": part.file_name().expect("failed to get part filename").to_string_lossy(),
+      "size": fs::metadata(part)?.len(),
+      "sha256": common::hash_file(part)?,
+    }));
+  }
+  let manifest_path = paths::dmg_parts_manifest_path(dmg_path);
+  let manifest = serde_json::json!({
+    "image": dmg_path.file_name().expect("failed to get DMG filename").to_string_lossy(),
+    "parts": entries,
+  });
+  fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+  Ok(manifest_path)
+}
+
+// Checks that every part listed in the manifest is next to it and unmodified, so the set can be mounted.
+fn verify_parts(manifest_path: &Path) -> crate::Result<()> {
+  let manifest: serde_json::Value = serde_json::from_slice(&fs::read(manifest_path)?)?;
+  let dir = manifest_path.parent().expect("No data in parent");
+  let parts = manifest["parts"].as_array().ok_or_else(|| {
+    crate::Error::GenericError(format!(
+      "the DMG parts manifest {} has no parts",
+      manifest_path.display()
+    ))
+  })?;
+  for part in parts {
+    let file = part["file"].as_str().unwrap_or_default();
+    let path = dir.join(file);
+    if !path.is_file() {
+      return Err(crate::Error::GenericError(format!(
+        "the DMG part {} is missing",
+        path.display()
+      )));
+    }
+    if Some(common::hash_file(&path)?.as_str()) != part["sha256"].as_str() {
+      return Err(crate::Error::GenericError(format!(
+        "the DMG part {} does not match its manifest",
+        path.display()
+      )));
+    }
+  }
+  Ok(())
+}
+
+// Moves a file, copying it when the destination is on another disk.
+fn move_file(from: &Path, to: &Path) -> crate::Result<()> {
+  if fs::rename(from, to).is_err() {
+    fs::copy(from, to).with_context(|| format!("Failed to move {:?} to {:?}", from, to))?;
+    fs::remove_file(from)?;
+  }
+  Ok(())
+}
+
+// Checks the image checksums, so a corrupt DMG fails the build instead of failing to mount for users.
+fn verify_dmg(dmg_path: &Path, verbosity: HdiutilVerbosity) -> crate::Result<()> {
+  info!(action = "Verifying"; "{}", dmg_path.display());
+  let mut command = common::tool_command("hdiutil");
+  command.arg("verify");
+  if let Some(flag) = hdiutil_verbosity_flag(verbosity) {
+    command.arg(flag);
+  }
+  let output = command.arg(dmg_path).output_unchecked()?;
+  if output.status.success() {
+    Ok(())
+  } else {
+    Err(crate::Error::GenericError(format!(
+      "the DMG {} is corrupt, `hdiutil verify` failed:\n{}{}",
+      dmg_path.display(),
+      String::from_utf8_lossy(&output.stdout),
+      String::from_utf8_lossy(&output.stderr)
+    )))
+  }
+}
+
+// The hdiutil flag of the verbosity, `None` for its default output.
+fn hdiutil_verbosity_flag(verbosity: HdiutilVerbosity) -> Option<&'static str> {
+  match verbosity {
+    HdiutilVerbosity::Normal => None,
+    HdiutilVerbosity::Quiet => Some("-quiet"),
+    HdiutilVerbosity::Verbose => Some("-verbose"),
+  }
+}
+
+// The volume date in the `touch -t` format, from the settings or `SOURCE_DATE_EPOCH`.
+fn volume_date(settings: &Settings) -> crate::Result<Option<String>> {
+  let timestamp = match settings.dmg().volume_date {
+    Some(timestamp) => timestamp,
+    None => match env::var("SOURCE_DATE_EPOCH") {
+      Ok(epoch) => epoch.trim().parse().map_err(|_| {
+        crate::Error::GenericError(format!(
+          "invalid SOURCE_DATE_EPOCH `{}`, expected a Unix timestamp",
+          epoch
+        ))
+      })?,
+      Err(_) => return Ok(None),
+    },
+  };
+  let date = i64::try_from(timestamp)
+    .ok()
+    .and_then(|timestamp| time::OffsetDateTime::from_unix_timestamp(timestamp).ok())
+    .ok_or_else(|| crate::Error::GenericError(format!("invalid DMG volume date {}", timestamp)))?;
+  Ok(Some(format!(
+    "{:04}{:02}{:02}{:02}{:02}.{:02}",
+    date.year(),
+    u8::from(date.month()),
+    date.day(),
+    date.hour(),
+    date.minute(),
+    date.second()
+  )))
+}
+
+// Checks that the file starts with the header of a `.DS_Store` file, the Finder's "Bud1" buddy allocator.
+fn validate_ds_store(path: &Path) -> crate::Result<()> {
+  const DS_STORE_HEADER: &[u8] = b"\0\0\0\x01Bud1";
+  let mut header = [0; 8];
+  let valid = fs::File::open(path)
+    .and_then(|mut file| file.read_exact(&mut header))
+    .map(|_| header == DS_STORE_HEADER);
+  match valid {
+    Ok(true) => Ok(()),
+    Ok(false) => Err(crate::Error::GenericError(format!(
+      "{} is not a .DS_Store file",
+      path.display()
+    ))),
+    Err(e) => Err(crate::Error::GenericError(format!(
+      "failed to read the .DS_Store file {}: {}",
+      path.display(),
+      e
+    ))),
+  }
+}
+
+// Checks that the payload fits the requested volume size, which the bundled script keeps as the
+// final size while create-dmg only uses it for the initial image before resizing it to fit.
+fn validate_image_size(size_mb: u64, payload_size: u64, use_create_dmg: bool) -> crate::Result<()> {
+  let size = size_mb.checked_mul(1024 * 1024).ok_or_else(|| {
+    crate::Error::GenericError(format!("the DMG size of {}MB is too large", size_mb))
+  })?;
+  if size < payload_size {
+    return Err(crate::Error::GenericError(format!(
+      "the DMG size of {}MB is too small for its contents, which need at least {}MB",
+      size_mb,
+      (payload_size + 1024 * 1024 - 1) / (1024 * 1024)
+    )));
+  }
+  if use_create_dmg {
+    warn!("create-dmg resizes the volume to fit its contents, the DMG size only sets the initial image size");
+  }
+  Ok(())
+}
+
+// The size in bytes of the files in the DMG source, without following symlinks.
+fn content_size(path: &Path) -> crate::Result<u64> {
+  let mut size = 0;
+  for entry in walkdir::WalkDir::new(path) {
+    let entry = entry?;
+    if entry.file_type().is_file() {
+      size += entry.metadata()?.len();
+    }
+  }
+  Ok(size)
+}
+
+// The size in bytes of the files with one of the extensions and of all files in the DMG source.
+fn compressed_size(path: &Path, extensions: &[String]) -> crate::Result<(u64, u64)> {
+  let extensions = extensions
+    .iter()
+    .map(|extension| extension.trim_start_matches('.').to_lowercase())
+    .collect::<Vec<_>>();
+  let (mut compressed, mut total) = (0, 0);
+  for entry in walkdir::WalkDir::new(path) {
+    let entry = entry?;
+    if !entry.file_type().is_file() {
+      continue;
+    }
+    let size = entry.metadata()?.len();
+    total += size;
+    let extension = entry
+      .path()
+      .extension()
+      .map(|extension| extension.to_string_lossy().to_lowercase());
+    if extension.map_or(false, |extension| extensions.contains(&extension)) {
+      compressed += size;
+    }
+  }
+  Ok((compressed, total))
+}
+
+// Fills the license resources template with the labels of the license dialog.
+// The script then adds the license text to it.
+fn eula_resources_template(labels: &DmgLicenseLabels) -> crate::Result<String> {
+  let (language, region) = license_language(labels.language);
+  let agree = labels.agree.as_deref().unwrap_or("Agree");
+  let disagree = labels.disagree.as_deref().unwrap_or("Disagree");
+  let message = labels.message.clone().unwrap_or_else(|| {
+    format!(
+      "If you agree with the terms of this license, press \"{}\" to install the software.  If you do not agree, press \"{}\".",
+      agree, disagree
+    )
+  });
+  let strings = [
+    language,
+    agree,
+    disagree,
+    labels.print.as_deref().unwrap_or("Print"),
+    labels.save.as_deref().unwrap_or("Save..."),
+    &message,
+  ];
+
+  // a `STR#` resource is the number of strings followed by the strings prefixed with their length
+  let mut str_data = (strings.len() as u16).to_be_bytes().to_vec();
+  for string in strings {
+    let encoded = mac_roman(string)?;
+    if encoded.len() > u8::MAX as usize {
+      return Err(crate::Error::GenericError(format!(
+        "the DMG license label `{}` is longer than 255 bytes",
+        string
+      )));
+    }
+    str_data.push(encoded.len() as u8);
+    str_data.extend(encoded);
+  }
+  // the `LPic` resource lists the default language and, for each language, its region code,
+  // the offset of its resource IDs from 5000 and whether its text uses two-byte characters
+  let lpic_data = [region, 1, region, 0, 0]
+    .iter()
+    .flat_map(|value| value.to_be_bytes())
+    .collect::<Vec<u8>>();
+
+  Ok(
+    include_str!("templates/dmg/eula-resources-template.xml")
+      .replace("@STR_DATA@", &plist_data(&str_data))
+      .replace("@LPIC_DATA@", &plist_data(&lpic_data))
+      .replace("@LANGUAGE@", language),
+  )
+}
+
+// The name of the language in itself and its classic Mac OS region code.
+fn license_language(language: DmgLicenseLanguage) -> (&'static str, u16) {
+  match language {
+    DmgLicenseLanguage::English => ("English", 0),
+    DmgLicenseLanguage::French => ("Français", 1),
+    DmgLicenseLanguage::German => ("Deutsch", 3),
+    DmgLicenseLanguage::Italian => ("Italiano", 4),
+    DmgLicenseLanguage::Dutch => ("Nederlands", 5),
+    DmgLicenseLanguage::Swedish => ("Svenska", 7),
+    DmgLicenseLanguage::Spanish => ("Español", 8),
+    DmgLicenseLanguage::Danish => ("Dansk", 9),
+    DmgLicenseLanguage::Portuguese => ("Português", 10),
+    DmgLicenseLanguage::Norwegian => ("Norsk", 12),
+    DmgLicenseLanguage::Finnish => ("Suomi", 17),
+    DmgLicenseLanguage::BrazilianPortuguese => ("Português", 71),
+  }
+}
+
+// Encodes the text in Mac OS Roman, the encoding of the license dialog resources.
+fn mac_roman(text: &str) -> crate::Result<Vec<u8>> {
+  // the characters of the bytes 0x80 to 0xFF, the lower half being ASCII
+  const UPPER_HALF: &str = "ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü†°¢£§•¶ß®©™´¨≠ÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø\
+    ¿¡¬√ƒ≈∆«»…\u{a0}ÀÃÕŒœ–—“”‘’÷◊ÿŸ⁄€‹›ﬁﬂ‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ\u{f8ff}ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ";
+  text
+    .chars()
+    .map(|c| {
+      if c.is_ascii() {
+        Ok(c as u8)
+      } else {
+        UPPER_HALF
+          .chars()
+          .position(|upper| upper == c)
+          .map(|i| 0x80 + i as u8)
+          .ok_or_else(|| {
+            crate::Error::GenericError(format!(
+              "the DMG license label `{}` contains `{}`, which Mac OS Roman cannot encode",
+              text, c
+            ))
+          })
+      }
+    })
+    .collect()
+}
+
+// Encodes the bytes as the base64 contents of a plist `<data>` element, in indented lines of 52 characters.
+fn plist_data(bytes: &[u8]) -> String {
+  const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut encoded = String::new();
+  for chunk in bytes.chunks(3) {
+    let n = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |n, (i, byte)| n | u32::from(*byte) << (16 - 8 * i));
+    for i in 0..4 {
+      if i <= chunk.len() {
+        encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+  encoded
+    .as_bytes()
+    .chunks(52)
+    .map(|line| format!("\t\t\t{}", String::from_utf8_lossy(line)))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+// Sets the Finder comment of the apps, which Finder stores in the `.DS_Store` of the volume,
+// so it is skipped when the layout AppleScript does not run.
+fn comment_args(
+  settings: &Settings,
+  app_names: &[String],
+  extension: &str,
+  use_create_dmg: bool,
+) -> Vec<String> {
+  let comment = match &settings.dmg().comment {
+    Some(comment) => comment,
+    None => return Vec::new(),
+  };
+  if use_create_dmg {
+    warn!("the create-dmg DMG backend does not support Finder comments, ignoring the comment");
+    return Vec::new();
+  }
+  if settings.dmg().ds_store.is_some() || app_names.is_empty() {
+    warn!("the Finder comment is only set with the AppleScript layout of the app, ignoring it");
+    return Vec::new();
+  }
+  // the comment is an AppleScript string literal
+  let comment = comment.replace('\\', "\\\\").replace('"', "\\\"");
+  app_names
+    .iter()
+    .flat_map(|name| {
+      [
+        "--comment".to_string(),
+        format!("{}.{}", name, extension),
+        comment.clone(),
+      ]
+    })
+    .collect()
+}
+
+// Places the apps in a row, followed by the link to the Applications folder if it is enabled.
+// The window is sized to fit them unless a window size is set.
+fn layout_args(
+  app_names: &[String],
+  extension: &str,
+  application_folder_link: bool,
+  window_size: Option<(u32, u32)>,
+) -> Vec<String> {
+  let mut args = Vec::new();
+  for (i, name) in app_names.iter().enumerate() {
+    args.extend([
+      "--icon".to_string(),
+      name.clone(),
+      (180 + 220 * i).to_string(),
+      "170".to_string(),
+      "--hide-extension".to_string(),
+      format!("{}.{}", name, extension),
+    ]);
+  }
+  let last_x = 180 + 220 * (app_names.len().max(1) - 1);
+  let window_width = if application_folder_link {
+    let app_drop_link_x = last_x + 300;
+    args.extend([
+      "--app-drop-link".to_string(),
+      app_drop_link_x.to_string(),
+      "170".to_string(),
+    ]);
+    app_drop_link_x + 180
+  } else {
+    last_x + 180
+  };
+  let (window_width, window_height) = window_size.unwrap_or((window_width as u32, 400));
+  args.extend([
+    "--window-size".to_string(),
+    window_width.to_string(),
+    window_height.to_string(),
+  ]);
+  args
+}
+
+// Copies the app once per architecture into the staging directory, replacing the main binary
+// with the build for that architecture. Returns the names of the staged apps and the entitlements
+// manifests of the apps that were signed again.
+fn stage_arch_apps(
+  settings: &Settings,
+  app_bundle_path: &Path,
+  staging_dir: &Path,
+  extension: &str,
+) -> crate::Result<(Vec<String>, Vec<PathBuf>)> {
+  let target_arch = settings.binary_arch();
+  let mut archs: Vec<(&str, Option<&PathBuf>)> = vec![(target_arch, None)];
+  archs.extend(
+    settings
+      .macos()
+      .arch_binaries
+      .iter()
+      .filter(|(arch, _)| arch.as_str() != target_arch)
+      .map(|(arch, binary)| (arch.as_str(), Some(binary))),
+  );
+
+  let mut app_names = Vec::new();
+  let mut manifests = Vec::new();
+  for (arch, binary) in archs {
+    let app_name = format!("{} ({})", settings.product_name(), arch);
+    let app_path = staging_dir.join(format!("{}.{}", app_name, extension));
+    common::copy_dir(app_bundle_path, &app_path)
+      .with_context(|| format!("Failed to stage {}", app_name))?;
+
+    if let Some(binary) = binary {
+      common::copy_file(
+        binary,
+        app_path
+          .join("Contents/MacOS")
+          .join(settings.main_binary_name()),
+      )
+      .with_context(|| format!("Failed to copy binary from {:?}", binary))?;
+
+      // the binary changed, so the copy needs a signature of its own
+      if let Some(identity) = settings
+        .macos()
+        .signing_identity
+        .as_ref()
+        .filter(|_| settings.macos().sign_app)
+      {
+        manifests.extend(sign(app_path.clone(), identity, settings, true)?);
+        if settings.macos().notarize_app {
+          if let Ok(args) = notarize_auth_args() {
+            notarize(app_path.clone(), args, settings)?;
+          }
+        }
+      }
+    }
+
+    app_names.push(app_name);
+  }
+  Ok((app_names, manifests))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    attached_devices, eula_resources_template, mac_roman, plist_data, validate_image_size,
+    verify_parts, write_parts_manifest,
+  };
+  use crate::{DmgLicenseLabels, DmgLicenseLanguage};
+  use std::fs;
+
+  #[test]
+  fn encodes_license_labels() {
+    assert_eq!(plist_data(b"\0\0\0\x02"), "\t\t\tAAAAAg==");
+    assert_eq!(mac_roman("Désaccord").unwrap(), b"D\x8esaccord");
+    assert!(mac_roman("同意").is_err());
+
+    // the default labels are the English resources of the create-dmg template
+    let template = eula_resources_template(&DmgLicenseLabels::default()).unwrap();
+    assert!(template.contains("\t\t\tAAYHRW5nbGlzaAVBZ3JlZQhEaXNhZ3JlZQVQcmludAdTYXZlLi4u\n"));
+    assert!(template.contains("\t\t\tAAAAAQAAAAAAAA==\n"));
+
+    let template = eula_resources_template(&DmgLicenseLabels {
+      language: DmgLicenseLanguage::German,
+      agree: Some("Akzeptieren".into()),
+      ..Default::default()
+    })
+    .unwrap();
+    assert!(template.contains("<string>Deutsch</string>"));
+    // German has the region code 3
+    assert!(template.contains("\t\t\tAAMAAQADAAAAAA==\n"));
+    assert!(!template.contains('@'));
+  }
+
+  #[test]
+  fn validates_the_image_size() {
+    assert!(validate_image_size(10, 10 * 1024 * 1024, false).is_ok());
+    assert!(validate_image_size(10, 10 * 1024 * 1024 + 1, false).is_err());
+    assert!(validate_image_size(u64::MAX, 0, false).is_err());
+  }
+
+  #[test]
+  fn finds_the_attached_devices() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let image = tmp.path().join("rw.App.dmg");
+    fs::write(&image, b"").unwrap();
+    let info = format!(
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>images</key>
+  <array>
+    <dict>
+      <key>image-path</key>
+      <string>/Users/me/Other.dmg</string>
+      <key>system-entities</key>
+      <array><dict><key>dev-entry</key><string>/dev/disk3</string></dict></array>
+    </dict>
+    <dict>
+      <key>image-path</key>
+      <string>{}</string>
+      <key>system-entities</key>
+      <array>
+        <dict><key>dev-entry</key><string>/dev/disk4</string></dict>
+        <dict><key>dev-entry</key><string>/dev/disk4s1</string></dict>
+      </array>
+    </dict>
+  </array>
+</dict>
+</plist>"#,
+      fs::canonicalize(&image).unwrap().display()
+    );
+    assert_eq!(
+      attached_devices(info.as_bytes(), &image),
+      vec!["/dev/disk4"]
+    );
+    assert!(attached_devices(b"not a plist", &image).is_empty());
+  }
+
+  #[test]
+  fn verify_segmented_dmg_parts() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let dmg_path = tmp.path().join("App.dmg");
+    let parts = vec![dmg_path.clone(), tmp.path().join("App.002.dmgpart")];
+    fs::write(&parts[0], b"first segment").unwrap();
+    fs::write(&parts[1], b"second segment").unwrap();
+
+    let manifest_path = write_parts_manifest(&dmg_path, &parts).expect("Failed to write manifest");
+    assert_eq!(manifest_path, tmp.path().join("App.parts.json"));
+    verify_parts(&manifest_path).expect("the parts do not match");
+
+    // the parts are listed in mount order
+    let manifest: serde_json::Value =
+      serde_json::from_slice(&fs::read(&manifest_path).unwrap()).unwrap();
+    let files: Vec<_> = manifest["parts"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|part| part["file"].as_str().unwrap())
+      .collect();
+    assert_eq!(files, vec!["App.dmg", "App.002.dmgpart"]);
+    assert_eq!(manifest["parts"][1]["size"], 14);
+
+    fs::write(&parts[1], b"modified").unwrap();
+    assert!(verify_parts(&manifest_path).is_err());
+    fs::remove_file(&parts[1]).unwrap();
+    assert!(verify_parts(&manifest_path).is_err());
   }
-  Ok(vec![dmg_path])
 }
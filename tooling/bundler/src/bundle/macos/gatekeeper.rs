@@ -0,0 +1,102 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+use crate::{
+  bundle::common::{self, CommandExt},
+  Settings,
+};
+use anyhow::Context;
+use log::{info, warn};
+
+const CACHE_FILE_NAME: &str = ".gatekeeper-cache.json";
+
+/// Successful assessments, mapping the artifact path to the hash it had when it was assessed.
+type AssessmentCache = BTreeMap<String, String>;
+
+/// Runs a Gatekeeper assessment on the given app bundle or DMG if `verify_gatekeeper` is enabled.
+///
+/// With `gatekeeper_cache` enabled, artifacts whose hash matches their last successful assessment are skipped.
+pub fn assess(path: &Path, settings: &Settings, is_an_app: bool) -> crate::Result<()> {
+  if !settings.macos().verify_gatekeeper {
+    return Ok(());
+  }
+
+  // Gatekeeper always rejects ad-hoc signatures
+  if let Some(identity) = settings
+    .macos()
+    .signing_identity
+    .as_deref()
+    .filter(|identity| super::sign::is_ad_hoc(identity))
+  {
+    warn!(
+      "skipping the Gatekeeper assessment of {}, it is signed with the ad-hoc identity \"{}\"",
+      path.display(),
+      identity
+    );
+    return Ok(());
+  }
+
+  if !settings.macos().gatekeeper_cache {
+    return run_spctl(path, is_an_app);
+  }
+
+  let cache_path = cache_path(settings);
+  let mut cache = read_cache(&cache_path);
+  let key = path.to_string_lossy().into_owned();
+  let hash = common::hash_path(path)?;
+
+  if cache.get(&key) == Some(&hash) {
+    info!(action = "Skipping"; "Gatekeeper assessment of {}, unchanged since it was last verified", path.display());
+    return Ok(());
+  }
+
+  run_spctl(path, is_an_app)?;
+
+  cache.insert(key, hash);
+  let cache_file = common::create_file(&cache_path)?;
+  serde_json::to_writer_pretty(cache_file, &cache)?;
+
+  Ok(())
+}
+
+fn run_spctl(path: &Path, is_an_app: bool) -> crate::Result<()> {
+  info!(action = "Verifying"; "{} with Gatekeeper", path.display());
+
+  let mut args = vec!["--assess", "-vv"];
+  if is_an_app {
+    args.extend(["--type", "execute"]);
+  } else {
+    args.extend(["--type", "open", "--context", "context:primary-signature"]);
+  }
+
+  Command::new("spctl")
+    .args(args)
+    .arg(path)
+    .output_ok()
+    .with_context(|| format!("Gatekeeper rejected {}", path.display()))?;
+
+  Ok(())
+}
+
+fn cache_path(settings: &Settings) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle")
+    .join(CACHE_FILE_NAME)
+}
+
+// A missing or unreadable cache just means every artifact gets assessed again.
+fn read_cache(cache_path: &Path) -> AssessmentCache {
+  fs::read(cache_path)
+    .ok()
+    .and_then(|contents| serde_json::from_slice(&contents).ok())
+    .unwrap_or_default()
+}
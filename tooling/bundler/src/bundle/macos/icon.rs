@@ -4,20 +4,29 @@
 // SPDX-License-Identifier: MIT
 
 use crate::bundle::{common, Settings};
+use anyhow::Context;
 use std::{
   cmp::min,
   ffi::OsStr,
   fs::{self, File},
-  io::{self, BufWriter},
+  io::{self, BufWriter, Read},
   path::{Path, PathBuf},
 };
 
 use image::GenericImageView;
 
+// Every icns file starts with these magic bytes.
+const ICNS_MAGIC: &[u8; 4] = b"icns";
+
 // Given a list of icon files, try to produce an ICNS file in the out_dir
-// nd return the path to it.  Returns `Ok(None)` if no usable icons
-// were provided.
+// and return the path to it. Returns `Ok(None)` if no icons are configured,
+// and an error naming the icon if a configured icon cannot be used.
 pub fn create_icns_file(out_dir: &Path, settings: &Settings) -> crate::Result<Option<PathBuf>> {
+  // If the user provided their own ICNS file, use it verbatim.
+  if let Some(icns_path) = &settings.macos().icns_path {
+    return copy_custom_icns_file(out_dir, icns_path).map(Some);
+  }
+
   if settings.icon_files().count() == 0 {
     return Ok(None);
   }
@@ -59,28 +68,43 @@ pub fn create_icns_file(out_dir: &Path, settings: &Settings) -> crate::Result<Op
     }
   }
 
-  let mut images_to_resize: Vec<(image::DynamicImage, u32, u32)> = vec![];
+  let mut images_to_resize: Vec<(PathBuf, image::DynamicImage, u32, u32)> = vec![];
   for icon_path in settings.icon_files() {
     let icon_path = icon_path?;
-    let icon = image::open(&icon_path)?;
+    let icon = image::open(&icon_path)
+      .with_context(|| format!("failed to read the icon {}", icon_path.display()))?;
     let density = if common::is_retina(&icon_path) { 2 } else { 1 };
     let (w, h) = icon.dimensions();
     let orig_size = min(w, h);
     let next_size_down = 2f32.powf((orig_size as f32).log2().floor()) as u32;
     if orig_size > next_size_down {
-      images_to_resize.push((icon, next_size_down, density));
+      images_to_resize.push((icon_path, icon, next_size_down, density));
     } else {
-      add_icon_to_family(icon, density, &mut family)?;
+      add_icon_to_family(icon, density, &mut family).with_context(|| {
+        format!(
+          "the {}x{} icon {} cannot be used in an icns file",
+          w,
+          h,
+          icon_path.display()
+        )
+      })?;
     }
   }
 
-  for (icon, next_size_down, density) in images_to_resize {
+  for (icon_path, icon, next_size_down, density) in images_to_resize {
     let icon = icon.resize_exact(
       next_size_down,
       next_size_down,
       image::imageops::FilterType::Lanczos3,
     );
-    add_icon_to_family(icon, density, &mut family)?;
+    add_icon_to_family(icon, density, &mut family).with_context(|| {
+      format!(
+        "the icon {} resized to {}x{} cannot be used in an icns file",
+        icon_path.display(),
+        next_size_down,
+        next_size_down
+      )
+    })?;
   }
 
   if !family.is_empty() {
@@ -98,6 +122,30 @@ pub fn create_icns_file(out_dir: &Path, settings: &Settings) -> crate::Result<Op
   }
 }
 
+// Validates the user-provided ICNS file and copies it to the out_dir.
+fn copy_custom_icns_file(out_dir: &Path, icns_path: &Path) -> crate::Result<PathBuf> {
+  if !is_icns_file(icns_path)? {
+    return Err(crate::Error::GenericError(format!(
+      "{} is not a valid icns file",
+      icns_path.display()
+    )));
+  }
+  let dest_path = out_dir.join(icns_path.file_name().expect("Could not get icns filename"));
+  common::copy_file(icns_path, &dest_path)?;
+  Ok(dest_path)
+}
+
+// Checks the magic bytes of the file to see if it is an ICNS file.
+fn is_icns_file(path: &Path) -> crate::Result<bool> {
+  let mut magic = [0; 4];
+  let mut file = File::open(path)?;
+  match file.read_exact(&mut magic) {
+    Ok(()) => Ok(&magic == ICNS_MAGIC),
+    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+    Err(e) => Err(e.into()),
+  }
+}
+
 // Converts an image::DynamicImage into an icns::Image.
 fn make_icns_image(img: image::DynamicImage) -> io::Result<icns::Image> {
   let pixel_format = match img.color() {
@@ -112,3 +160,54 @@ fn make_icns_image(img: image::DynamicImage) -> io::Result<icns::Image> {
   };
   icns::Image::from_data(pixel_format, img.width(), img.height(), img.into_bytes())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{create_icns_file, is_icns_file};
+  use crate::{bundle::settings::test_settings, BundleSettings};
+
+  #[test]
+  fn icns_magic_bytes() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let icns = tmp.path().join("icon.icns");
+    std::fs::write(&icns, b"icns\x00\x00\x00\x08").unwrap();
+    assert!(is_icns_file(&icns).unwrap());
+
+    let png = tmp.path().join("icon.png");
+    std::fs::write(&png, b"\x89PNG\r\n\x1a\n").unwrap();
+    assert!(!is_icns_file(&png).unwrap());
+
+    let empty = tmp.path().join("empty.icns");
+    std::fs::write(&empty, b"").unwrap();
+    assert!(!is_icns_file(&empty).unwrap());
+  }
+
+  #[test]
+  fn configured_icons_must_be_usable() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let icon = tmp.path().join("icon.png");
+    std::fs::write(&icon, b"not a png").unwrap();
+    let settings = |icon: Option<Vec<String>>| {
+      test_settings(
+        tmp.path(),
+        BundleSettings {
+          icon,
+          ..Default::default()
+        },
+        "x86_64-apple-darwin",
+      )
+    };
+
+    // no icon is configured
+    assert!(create_icns_file(tmp.path(), &settings(None))
+      .unwrap()
+      .is_none());
+
+    let error = create_icns_file(
+      tmp.path(),
+      &settings(Some(vec![icon.to_string_lossy().into_owned()])),
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("icon.png"));
+  }
+}
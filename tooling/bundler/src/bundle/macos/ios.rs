@@ -13,7 +13,10 @@
 // See https://developer.apple.com/go/?id=bundle-structure for a full
 // explanation.
 
-use crate::{bundle::common, Settings};
+use crate::{
+  bundle::{common, paths},
+  Settings,
+};
 
 use anyhow::Context;
 use image::{self, codecs::png::PngDecoder, GenericImageView, ImageDecoder};
@@ -31,13 +34,18 @@ use std::{
 /// Returns a vector of PathBuf that shows where the .app was created.
 pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
   warn!("iOS bundle support is still experimental.");
+  if settings.prebuilt_bundle().is_some() {
+    return Err(crate::Error::GenericError(
+      "the iOS bundle does not support a prebuilt bundle".into(),
+    ));
+  }
 
-  let app_product_name = format!("{}.app", settings.product_name());
-
-  let app_bundle_path = settings
-    .project_out_directory()
-    .join("bundle/ios")
-    .join(&app_product_name);
+  let app_bundle_path = paths::ios_bundle_path(settings);
+  let app_product_name = app_bundle_path
+    .file_name()
+    .expect("failed to get bundle filename")
+    .to_string_lossy()
+    .into_owned();
 
   info!(action = "Bundling"; "{} ({})", app_product_name, app_bundle_path.display());
 
@@ -54,6 +62,10 @@ pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
     common::copy_file(&src, &dest)
       .with_context(|| format!("Failed to copy resource file {:?}", src))?;
   }
+  for (src, target) in settings.remote_resource_files() {
+    common::copy_file(src, app_bundle_path.join(target))
+      .with_context(|| format!("Failed to copy resource file {:?}", src))?;
+  }
 
   let icon_filenames = generate_icon_files(&app_bundle_path, settings)
     .with_context(|| "Failed to create app icons")?;
@@ -181,7 +193,7 @@ fn generate_info_plist(
   writeln!(
     file,
     "  <key>CFBundleShortVersionString</key>\n  <string>{}</string>",
-    settings.version_string()
+    settings.short_version_string()
   )?;
   writeln!(
     file,
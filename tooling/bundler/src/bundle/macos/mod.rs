@@ -5,6 +5,8 @@
 
 pub mod app;
 pub mod dmg;
+pub mod gatekeeper;
 pub mod icon;
 pub mod ios;
 pub mod sign;
+pub mod zip;
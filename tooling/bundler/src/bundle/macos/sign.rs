@@ -4,15 +4,91 @@
 // SPDX-License-Identifier: MIT
 
 use std::ffi::OsString;
-use std::{fs::File, io::prelude::*, path::PathBuf, process::Command};
-
-use crate::{bundle::common::CommandExt, Settings};
+use std::{
+  fs::File,
+  io::prelude::*,
+  path::{Path, PathBuf},
+  process::Command,
+  sync::atomic::{AtomicBool, Ordering},
+  time::{Duration, Instant},
+};
+
+use crate::{
+  bundle::{
+    audit,
+    common::{self, CommandExt},
+    paths,
+  },
+  Settings,
+};
 use anyhow::Context;
-use log::info;
+use log::{info, warn};
 use regex::Regex;
 
+/// The signing identity that creates an ad-hoc signature (`codesign -s -`).
+///
+/// Ad-hoc signed apps run on the machine that built them, but cannot be notarized or distributed.
+pub const AD_HOC_IDENTITY: &str = "-";
+
 const KEYCHAIN_ID: &str = "tauri-build.keychain";
 const KEYCHAIN_PWD: &str = "tauri-build";
+// Magic numbers of universal Mach-O binaries, stored big-endian.
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+// Bundle extensions that are signed as their own bundle when nested in the app.
+const NESTED_BUNDLE_EXTENSIONS: &[&str] = &["app", "appex", "xpc"];
+// The hardened runtime exceptions, see https://developer.apple.com/documentation/security/hardened_runtime
+const RUNTIME_EXCEPTION_PREFIX: &str = "com.apple.security.cs.";
+const RUNTIME_EXCEPTIONS: &[&str] = &[
+  "allow-jit",
+  "allow-unsigned-executable-memory",
+  "allow-dyld-environment-variables",
+  "disable-library-validation",
+  "disable-executable-page-protection",
+  "debugger",
+];
+
+// Whether our keychain is currently set up.
+static KEYCHAIN_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// A temporary keychain holding the certificate imported from the environment.
+///
+/// The keychain is deleted when this value is dropped, so it is cleaned up even if signing fails midway.
+pub struct Keychain(());
+
+impl Keychain {
+  fn new() -> Self {
+    KEYCHAIN_ACTIVE.store(true, Ordering::SeqCst);
+    Self(())
+  }
+}
+
+impl Drop for Keychain {
+  fn drop(&mut self) {
+    delete_keychain();
+    KEYCHAIN_ACTIVE.store(false, Ordering::SeqCst);
+  }
+}
+
+/// Sets up a temporary keychain if the `APPLE_CERTIFICATE` and `APPLE_CERTIFICATE_PASSWORD`
+/// environment variables are set and the keychain is not already set up.
+///
+/// Keep the returned value alive while signing to reuse the same keychain for multiple artifacts.
+pub fn setup_keychain_from_env() -> crate::Result<Option<Keychain>> {
+  if KEYCHAIN_ACTIVE.load(Ordering::SeqCst) {
+    return Ok(None);
+  }
+  if let (Some(certificate_encoded), Some(certificate_password)) = (
+    std::env::var_os("APPLE_CERTIFICATE"),
+    std::env::var_os("APPLE_CERTIFICATE_PASSWORD"),
+  ) {
+    // setup keychain allow you to import your certificate
+    // for CI build
+    setup_keychain(certificate_encoded, certificate_password).map(Some)
+  } else {
+    Ok(None)
+  }
+}
 
 // Import certificate from ENV variables.
 // APPLE_CERTIFICATE is the p12 certificate base64 encoded.
@@ -23,7 +99,7 @@ const KEYCHAIN_PWD: &str = "tauri-build";
 pub fn setup_keychain(
   certificate_encoded: OsString,
   certificate_password: OsString,
-) -> crate::Result<()> {
+) -> crate::Result<Keychain> {
   // we delete any previous version of our keychain if present
   delete_keychain();
   info!("setup keychain from environment variables...");
@@ -69,6 +145,9 @@ pub fn setup_keychain(
     .output_ok()
     .context("failed to create keychain")?;
 
+  // from now on the keychain is deleted if any of the next steps fail
+  let keychain = Keychain::new();
+
   Command::new("security")
     .args(["unlock-keychain", "-p", KEYCHAIN_PWD, KEYCHAIN_ID])
     .output_ok()
@@ -127,7 +206,7 @@ pub fn setup_keychain(
     .output_ok()
     .context("failed to list keychain")?;
 
-  Ok(())
+  Ok(keychain)
 }
 
 pub fn delete_keychain() {
@@ -138,60 +217,338 @@ pub fn delete_keychain() {
     .output_ok();
 }
 
+/// Whether the identity creates an ad-hoc signature, see [`AD_HOC_IDENTITY`].
+pub fn is_ad_hoc(identity: &str) -> bool {
+  identity == AD_HOC_IDENTITY
+}
+
+/// Signs the path, returning the [`paths::entitlements_manifest_path`] when it is signed with entitlements.
 pub fn sign(
   path_to_sign: PathBuf,
   identity: &str,
   settings: &Settings,
   is_an_executable: bool,
-) -> crate::Result<()> {
-  info!(action = "Signing"; "{} with identity \"{}\"", path_to_sign.display(), identity);
-
-  let setup_keychain = if let (Some(certificate_encoded), Some(certificate_password)) = (
-    std::env::var_os("APPLE_CERTIFICATE"),
-    std::env::var_os("APPLE_CERTIFICATE_PASSWORD"),
-  ) {
-    // setup keychain allow you to import your certificate
-    // for CI build
-    setup_keychain(certificate_encoded, certificate_password)?;
-    true
+) -> crate::Result<Option<PathBuf>> {
+  // an ad-hoc signature needs no certificate, so the keychain is left alone
+  let (_keychain, tauri_keychain) = if is_ad_hoc(identity) {
+    warn!(
+      "signing {} with an ad-hoc signature, it is only suitable for local testing and cannot be distributed",
+      path_to_sign.display()
+    );
+    (None, false)
   } else {
-    false
+    info!(action = "Signing"; "{} with identity \"{}\"", path_to_sign.display(), identity);
+    // the keychain is deleted again after signing when it goes out of scope
+    let keychain = setup_keychain_from_env()?;
+    (keychain, KEYCHAIN_ACTIVE.load(Ordering::SeqCst))
   };
 
-  let res = try_sign(
-    path_to_sign,
+  // the generated entitlements file is removed when it goes out of scope
+  let entitlements_dir = tempfile::tempdir()?;
+  let entitlements = resolve_entitlements(settings, entitlements_dir.path())?;
+  let entitlements_arg = entitlements
+    .as_ref()
+    .map(|path| path.to_string_lossy().into_owned());
+
+  // helper bundles must be signed as bundles before the bundle containing them is sealed
+  if path_to_sign.is_dir() {
+    for nested_bundle in nested_bundles(&path_to_sign) {
+      info!(action = "Signing"; "nested bundle {}", nested_bundle.display());
+      let result = try_sign(
+        nested_bundle.clone(),
+        identity,
+        None,
+        true,
+        is_an_executable,
+        settings.macos().secure_timestamp,
+        tauri_keychain,
+      );
+      record_codesign(settings, identity, &nested_bundle, result)?;
+    }
+  }
+
+  // a universal binary is signed once, which covers all of its slices
+  let is_universal = path_to_sign.is_file() && is_universal_binary(&path_to_sign)?;
+  if is_universal {
+    info!(
+      "signing all architectures of universal binary {}",
+      path_to_sign.display()
+    );
+  }
+
+  let result = try_sign(
+    path_to_sign.clone(),
     identity,
-    settings,
+    entitlements_arg.as_deref(),
+    false,
     is_an_executable,
-    setup_keychain,
+    settings.macos().secure_timestamp,
+    tauri_keychain,
+  );
+  record_codesign(settings, identity, &path_to_sign, result)?;
+
+  let mut manifest_path = None;
+  if let Some(entitlements_path) = &entitlements {
+    if path_to_sign.is_dir() || is_an_executable {
+      manifest_path = Some(write_entitlements_manifest(
+        settings,
+        identity,
+        &path_to_sign,
+        entitlements_path,
+      )?);
+    }
+  }
+
+  if is_universal {
+    verify_universal_binary(&path_to_sign)?;
+  }
+
+  Ok(manifest_path)
+}
+
+// Returns the entitlements file to sign with: the `entitlements` file as is, or a copy of it
+// with the `runtime_exceptions` added, written to the directory.
+fn resolve_entitlements(settings: &Settings, dir: &Path) -> crate::Result<Option<PathBuf>> {
+  let macos = settings.macos();
+  if macos.runtime_exceptions.is_empty() {
+    return Ok(macos.entitlements.as_ref().map(PathBuf::from));
+  }
+  let mut entitlements = match &macos.entitlements {
+    Some(path) => plist::Value::from_file(path)
+      .with_context(|| format!("failed to read the entitlements file {}", path))?
+      .into_dictionary()
+      .ok_or_else(|| {
+        crate::Error::GenericError(format!(
+          "the entitlements file {} is not a dictionary",
+          path
+        ))
+      })?,
+    None => plist::Dictionary::new(),
+  };
+  for key in runtime_exception_keys(&macos.runtime_exceptions)? {
+    entitlements.insert(key, plist::Value::Boolean(true));
+  }
+  let path = dir.join("entitlements.plist");
+  plist::Value::Dictionary(entitlements).to_file_xml(&path)?;
+  Ok(Some(path))
+}
+
+// The entitlement keys of the hardened runtime exceptions, failing on unknown exceptions.
+fn runtime_exception_keys(exceptions: &[String]) -> crate::Result<Vec<String>> {
+  exceptions
+    .iter()
+    .map(|exception| {
+      let name = exception
+        .strip_prefix(RUNTIME_EXCEPTION_PREFIX)
+        .unwrap_or(exception);
+      if RUNTIME_EXCEPTIONS.contains(&name) {
+        Ok(format!("{}{}", RUNTIME_EXCEPTION_PREFIX, name))
+      } else {
+        Err(crate::Error::GenericError(format!(
+          "unknown hardened runtime exception `{}`, expected one of {}",
+          exception,
+          RUNTIME_EXCEPTIONS.join(", ")
+        )))
+      }
+    })
+    .collect()
+}
+
+// Writes the entitlements the signed code actually carries to its `paths::entitlements_manifest_path`
+// and the audit log, warning about the entitlements that were not requested.
+fn write_entitlements_manifest(
+  settings: &Settings,
+  identity: &str,
+  path: &Path,
+  entitlements_path: &Path,
+) -> crate::Result<PathBuf> {
+  // the leading colon strips the blob header, leaving the plain XML plist
+  let output = common::tool_command("codesign")
+    .args(["-d", "--entitlements", ":-"])
+    .arg(path)
+    .output_ok()
+    .with_context(|| format!("failed to read the entitlements of {}", path.display()))?;
+  let applied = entitlement_keys(&output.stdout)?;
+  let requested = entitlement_keys(&std::fs::read(entitlements_path)?)?;
+  for key in applied.iter().filter(|key| !requested.contains(key)) {
+    match &settings.macos().entitlements {
+      Some(file) => warn!(
+        "{} carries the entitlement `{}`, which is not in {} or the runtime exceptions",
+        path.display(),
+        key,
+        file
+      ),
+      None => warn!(
+        "{} carries the entitlement `{}`, which is not a runtime exception",
+        path.display(),
+        key
+      ),
+    }
+  }
+
+  let manifest_path = paths::entitlements_manifest_path(settings, path);
+  std::fs::create_dir_all(manifest_path.parent().expect("No data in parent"))?;
+  let manifest = serde_json::json!({
+    "artifact": path,
+    "entitlementsFile": settings.macos().entitlements,
+    "runtimeExceptions": settings.macos().runtime_exceptions,
+    "entitlements": applied,
+  });
+  std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+  audit::record(
+    settings,
+    "entitlements",
+    identity,
+    path,
+    Some(&applied.join(", ")),
+    Ok(()),
+  )?;
+  Ok(manifest_path)
+}
+
+// The sorted keys of an entitlements plist, empty for code signed without entitlements.
+fn entitlement_keys(plist: &[u8]) -> crate::Result<Vec<String>> {
+  if plist.iter().all(u8::is_ascii_whitespace) {
+    return Ok(Vec::new());
+  }
+  let value = plist::Value::from_reader(std::io::Cursor::new(plist))?;
+  let mut keys = value
+    .as_dictionary()
+    .map(|entitlements| entitlements.keys().cloned().collect::<Vec<_>>())
+    .unwrap_or_default();
+  keys.sort();
+  Ok(keys)
+}
+
+/// Signs a helper nested in the app bundle with its own entitlements and the hardened runtime,
+/// before the app bundle containing it is signed.
+pub fn sign_helper(
+  path: &Path,
+  identity: &str,
+  settings: &Settings,
+  entitlements: Option<&Path>,
+) -> crate::Result<()> {
+  info!(action = "Signing"; "helper {}", path.display());
+  let (_keychain, tauri_keychain) = if is_ad_hoc(identity) {
+    (None, false)
+  } else {
+    let keychain = setup_keychain_from_env()?;
+    (keychain, KEYCHAIN_ACTIVE.load(Ordering::SeqCst))
+  };
+  let entitlements = entitlements.map(|path| path.to_string_lossy().into_owned());
+  // without an entitlements file, the helper keeps the entitlements it was built with
+  let result = try_sign(
+    path.to_path_buf(),
+    identity,
+    entitlements.as_deref(),
+    true,
+    true,
+    settings.macos().secure_timestamp,
+    tauri_keychain,
   );
+  record_codesign(settings, identity, path, result)
+}
 
-  if setup_keychain {
-    // delete the keychain again after signing
-    delete_keychain();
+// Records the codesign run with its output in the signing audit log.
+fn record_codesign(
+  settings: &Settings,
+  identity: &str,
+  path: &Path,
+  result: crate::Result<String>,
+) -> crate::Result<()> {
+  let output = result.as_ref().ok().cloned();
+  audit::record(
+    settings,
+    "codesign",
+    identity,
+    path,
+    output.as_deref(),
+    result.map(|_| ()),
+  )
+}
+
+/// Whether the file is a universal binary, i.e. a fat Mach-O file holding one slice per architecture.
+pub fn is_universal_binary(path: &Path) -> crate::Result<bool> {
+  let mut header = [0; 8];
+  match File::open(path)?.read_exact(&mut header) {
+    Ok(()) => {}
+    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+    Err(e) => return Err(e.into()),
   }
+  let magic = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+  let arch_count = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+  // Java class files share the magic number, but their version field is never this small
+  Ok((magic == FAT_MAGIC || magic == FAT_MAGIC_64) && arch_count > 0 && arch_count < 45)
+}
 
-  res
+// Checks that the signature of the universal binary is valid for every slice.
+fn verify_universal_binary(path: &Path) -> crate::Result<()> {
+  let output = Command::new("lipo")
+    .arg("-archs")
+    .arg(path)
+    .output_ok()
+    .context("failed to list the architectures of the universal binary")?;
+  for arch in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+    common::tool_command("codesign")
+      .args(["--verify", "--arch", arch])
+      .arg(path)
+      .output_ok()
+      .with_context(|| format!("the {} slice of {} is not signed", arch, path.display()))?;
+  }
+  Ok(())
+}
+
+// Finds the helper bundles (XPC services, app extensions and helper apps) nested in a bundle,
+// ordered so nested bundles come before the bundles containing them.
+fn nested_bundles(bundle_path: &Path) -> Vec<PathBuf> {
+  let mut bundles: Vec<PathBuf> = walkdir::WalkDir::new(bundle_path)
+    .min_depth(1)
+    .into_iter()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| {
+      entry.file_type().is_dir()
+        && entry
+          .path()
+          .extension()
+          .and_then(|ext| ext.to_str())
+          .map_or(false, |ext| NESTED_BUNDLE_EXTENSIONS.contains(&ext))
+    })
+    .map(|entry| entry.into_path())
+    .collect();
+  bundles.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+  bundles
 }
 
+// Signs with the entitlements file, or with the entitlements the code was already signed with
+// when `preserve_entitlements` is set, which is only meant for nested code built on its own.
+// Returns the output of codesign.
 fn try_sign(
   path_to_sign: PathBuf,
   identity: &str,
-  settings: &Settings,
+  entitlements: Option<&str>,
+  preserve_entitlements: bool,
   is_an_executable: bool,
+  secure_timestamp: bool,
   tauri_keychain: bool,
-) -> crate::Result<()> {
+) -> crate::Result<String> {
   let mut args = vec!["--force", "-s", identity];
 
+  if !secure_timestamp {
+    args.push("--timestamp=none");
+  }
+
   if tauri_keychain {
     args.push("--keychain");
     args.push(KEYCHAIN_ID);
   }
 
-  if let Some(entitlements_path) = &settings.macos().entitlements {
+  if let Some(entitlements_path) = entitlements {
     info!("using entitlements file at {}", entitlements_path);
     args.push("--entitlements");
     args.push(entitlements_path);
+  } else if preserve_entitlements {
+    // keep the entitlements the nested bundle build signed it with
+    args.push("--preserve-metadata=entitlements");
   }
 
   if is_an_executable {
@@ -203,13 +560,17 @@ fn try_sign(
     args.push("--deep");
   }
 
-  Command::new("codesign")
+  let output = common::tool_command("codesign")
     .args(args)
     .arg(path_to_sign.to_string_lossy().to_string())
     .output_ok()
     .context("failed to sign app")?;
 
-  Ok(())
+  Ok(format!(
+    "{}{}",
+    String::from_utf8_lossy(&output.stdout),
+    String::from_utf8_lossy(&output.stderr)
+  ))
 }
 
 pub fn notarize(
@@ -217,7 +578,46 @@ pub fn notarize(
   auth_args: Vec<String>,
   settings: &Settings,
 ) -> crate::Result<()> {
-  let identifier = settings.bundle_identifier();
+  if let Some(identity) = settings
+    .macos()
+    .signing_identity
+    .as_deref()
+    .filter(|identity| is_ad_hoc(identity))
+  {
+    warn!(
+      "skipping notarization, apps signed with the ad-hoc identity \"{}\" cannot be notarized",
+      identity
+    );
+    return Ok(());
+  }
+
+  if !settings.macos().secure_timestamp {
+    warn!("skipping notarization, signatures without a secure timestamp cannot be notarized");
+    return Ok(());
+  }
+
+  // the Apple ID or API key, never the password
+  let identity = auth_args.get(1).cloned().unwrap_or_default();
+  let result = notarize_bundle(app_bundle_path.clone(), auth_args, settings);
+  let output = result.as_ref().ok().cloned();
+  audit::record(
+    settings,
+    "notarize",
+    &identity,
+    &app_bundle_path,
+    output.as_deref(),
+    result.map(|_| ()),
+  )
+}
+
+// Uploads the bundle, waits for Apple to accept it and staples the ticket. Returns the upload response.
+fn notarize_bundle(
+  app_bundle_path: PathBuf,
+  auth_args: Vec<String>,
+  settings: &Settings,
+) -> crate::Result<String> {
+  settings.check_cancelled()?;
+  let identifier = settings.macos_bundle_identifier();
 
   let bundle_stem = app_bundle_path
     .file_stem()
@@ -260,7 +660,7 @@ pub fn notarize(
       .to_str()
       .expect("failed to convert zip_path to string"),
     "--primary-bundle-id",
-    identifier,
+    &identifier,
   ];
 
   if let Some(provider_short_name) = &settings.macos().provider_short_name {
@@ -270,17 +670,7 @@ pub fn notarize(
 
   info!(action = "Notarizing"; "{}", app_bundle_path.display());
 
-  let output = Command::new("xcrun")
-    .args(notarize_args)
-    .args(auth_args.clone())
-    .output_ok()
-    .context("failed to upload app to Apple's notarization servers.")?;
-
-  // combine both stdout and stderr to support macOS below 10.15
-  let mut notarize_response = std::str::from_utf8(&output.stdout)?.to_string();
-  notarize_response.push('\n');
-  notarize_response.push_str(std::str::from_utf8(&output.stderr)?);
-  notarize_response.push('\n');
+  let notarize_response = upload_for_notarization(&notarize_args, &auth_args, settings)?;
   if let Some(uuid) = Regex::new(r"\nRequestUUID = (.+?)\n")?
     .captures_iter(&notarize_response)
     .next()
@@ -300,7 +690,66 @@ pub fn notarize(
     );
   }
 
-  Ok(())
+  Ok(notarize_response)
+}
+
+// Uploads the app for notarization, retrying transient failures with an exponential backoff.
+fn upload_for_notarization(
+  notarize_args: &[&str],
+  auth_args: &[String],
+  settings: &Settings,
+) -> crate::Result<String> {
+  let mut delay = settings.macos().notarization_retry_delay;
+  let mut attempt = 0;
+  loop {
+    let output = common::tool_command("xcrun")
+      .args(notarize_args)
+      .args(auth_args)
+      .output_unchecked()
+      .context("failed to upload app to Apple's notarization servers.")?;
+
+    // combine both stdout and stderr to support macOS below 10.15
+    let mut notarize_response = String::from_utf8_lossy(&output.stdout).into_owned();
+    notarize_response.push('\n');
+    notarize_response.push_str(&String::from_utf8_lossy(&output.stderr));
+    notarize_response.push('\n');
+
+    if output.status.success() {
+      return Ok(notarize_response);
+    }
+    if attempt >= settings.macos().notarization_retries || !is_transient_failure(&notarize_response)
+    {
+      return Err(
+        anyhow::anyhow!(
+          "failed to upload app to Apple's notarization servers. {}",
+          notarize_response
+        )
+        .into(),
+      );
+    }
+
+    attempt += 1;
+    warn!(
+      "notarization upload failed with a transient error, retrying in {} seconds ({}/{})",
+      delay.as_secs(),
+      attempt,
+      settings.macos().notarization_retries
+    );
+    std::thread::sleep(delay);
+    settings.check_cancelled()?;
+    delay *= 2;
+  }
+}
+
+// Whether a failed notarization upload was caused by the network or Apple's servers and is worth retrying,
+// rather than by the submission or the credentials.
+fn is_transient_failure(response: &str) -> bool {
+  // NSURLError codes for timeouts and connection failures, and HTTP 5xx responses
+  Regex::new(
+    r"(?i)timed out|network connection was lost|not connected to the internet|could not connect to the server|-100[1-9]\b|HTTP status code:? 5\d\d|service unavailable|bad gateway",
+  )
+  .map(|re| re.is_match(response))
+  .unwrap_or(false)
 }
 
 fn staple_app(mut app_bundle_path: PathBuf) -> crate::Result<()> {
@@ -313,7 +762,7 @@ fn staple_app(mut app_bundle_path: PathBuf) -> crate::Result<()> {
 
   app_bundle_path.pop();
 
-  Command::new("xcrun")
+  common::tool_command("xcrun")
     .args(vec!["stapler", "staple", "-v", filename])
     .current_dir(app_bundle_path)
     .output_ok()
@@ -327,49 +776,85 @@ fn get_notarization_status(
   auth_args: Vec<String>,
   settings: &Settings,
 ) -> crate::Result<()> {
-  std::thread::sleep(std::time::Duration::from_secs(10));
-  let result = Command::new("xcrun")
-    .args(vec!["altool", "--notarization-info", &uuid])
-    .args(auth_args.clone())
-    .output_ok();
+  let status_regex = Regex::new(r"\n *Status: (.+?)\n")?;
+  let started = Instant::now();
+  loop {
+    std::thread::sleep(Duration::from_secs(10));
+    settings.check_cancelled()?;
+
+    if let Some(timeout) = settings.macos().notarization_timeout {
+      if started.elapsed() > timeout {
+        return Err(crate::Error::GenericError(format!(
+          "notarization request {} did not finish within {} seconds",
+          uuid,
+          timeout.as_secs()
+        )));
+      }
+    }
+
+    let result = common::tool_command("xcrun")
+      .args(vec!["altool", "--notarization-info", &uuid])
+      .args(auth_args.clone())
+      .output_ok();
+
+    let output = match result {
+      Ok(output) => output,
+      Err(_) => continue,
+    };
 
-  if let Ok(output) = result {
     // combine both stdout and stderr to support macOS below 10.15
     let mut notarize_status = std::str::from_utf8(&output.stdout)?.to_string();
     notarize_status.push('\n');
     notarize_status.push_str(std::str::from_utf8(&output.stderr)?);
     notarize_status.push('\n');
-    if let Some(status) = Regex::new(r"\n *Status: (.+?)\n")?
-      .captures_iter(&notarize_status)
-      .next()
-    {
-      let status = status[1].to_string();
-      if status == "in progress" {
-        get_notarization_status(uuid, auth_args, settings)
-      } else if status == "invalid" {
-        Err(
-          anyhow::anyhow!(format!(
-            "Apple failed to notarize your app. {}",
-            notarize_status
-          ))
-          .into(),
-        )
-      } else if status != "success" {
-        Err(
-          anyhow::anyhow!(format!(
-            "Unknown notarize status {}. {}",
-            status, notarize_status
-          ))
-          .into(),
-        )
-      } else {
-        Ok(())
-      }
+    let status = match status_regex.captures_iter(&notarize_status).next() {
+      Some(status) => status[1].to_string(),
+      None => continue,
+    };
+
+    info!(action = "Notarizing"; "status: {} ({}s elapsed)", status, started.elapsed().as_secs());
+
+    if status == "in progress" {
+      continue;
+    } else if status == "invalid" {
+      return Err(
+        anyhow::anyhow!(format!(
+          "Apple failed to notarize your app. {}{}",
+          notarize_status,
+          notarization_log(&notarize_status)
+        ))
+        .into(),
+      );
+    } else if status != "success" {
+      return Err(
+        anyhow::anyhow!(format!(
+          "Unknown notarize status {}. {}",
+          status, notarize_status
+        ))
+        .into(),
+      );
     } else {
-      get_notarization_status(uuid, auth_args, settings)
+      return Ok(());
     }
-  } else {
-    get_notarization_status(uuid, auth_args, settings)
+  }
+}
+
+// Fetches the notarization log referenced by the status output, so rejections can be acted upon.
+fn notarization_log(notarize_status: &str) -> String {
+  let url = match Regex::new(r"\n *LogFileURL: (.+?)\n")
+    .ok()
+    .and_then(|re| re.captures(notarize_status))
+  {
+    Some(url) => url[1].to_string(),
+    None => return String::new(),
+  };
+  match Command::new("curl").args(["-fsSL", &url]).output_ok() {
+    Ok(output) => format!(
+      "\nNotarization log ({}):\n{}",
+      url,
+      String::from_utf8_lossy(&output.stdout)
+    ),
+    Err(_) => format!("\nNotarization log: {}", url),
   }
 }
 
@@ -406,3 +891,109 @@ pub fn notarize_auth_args() -> crate::Result<Vec<String>> {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    entitlement_keys, is_transient_failure, is_universal_binary, runtime_exception_keys, try_sign,
+    verify_universal_binary,
+  };
+  use std::process::Command;
+
+  #[test]
+  fn universal_binary_header() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+
+    let fat = tmp.path().join("fat");
+    std::fs::write(&fat, b"\xca\xfe\xba\xbe\x00\x00\x00\x02").unwrap();
+    assert!(is_universal_binary(&fat).unwrap());
+
+    // a Java class file, version 52.0
+    let class = tmp.path().join("Main.class");
+    std::fs::write(&class, b"\xca\xfe\xba\xbe\x00\x00\x00\x34").unwrap();
+    assert!(!is_universal_binary(&class).unwrap());
+
+    let thin = tmp.path().join("thin");
+    std::fs::write(&thin, b"\xcf\xfa\xed\xfe\x07\x00\x00\x01").unwrap();
+    assert!(!is_universal_binary(&thin).unwrap());
+
+    let empty = tmp.path().join("empty");
+    std::fs::write(&empty, b"").unwrap();
+    assert!(!is_universal_binary(&empty).unwrap());
+  }
+
+  #[test]
+  fn validates_runtime_exceptions() {
+    assert_eq!(
+      runtime_exception_keys(&[
+        "allow-jit".into(),
+        "com.apple.security.cs.disable-library-validation".into()
+      ])
+      .unwrap(),
+      vec![
+        "com.apple.security.cs.allow-jit",
+        "com.apple.security.cs.disable-library-validation"
+      ]
+    );
+    assert!(runtime_exception_keys(&["allow-everything".into()]).is_err());
+  }
+
+  #[test]
+  fn parse_entitlement_keys() {
+    let plist = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>com.apple.security.network.client</key>
+  <true/>
+  <key>com.apple.security.app-sandbox</key>
+  <true/>
+</dict>
+</plist>"#;
+    assert_eq!(
+      entitlement_keys(plist).unwrap(),
+      vec![
+        "com.apple.security.app-sandbox".to_string(),
+        "com.apple.security.network.client".to_string()
+      ]
+    );
+    assert!(entitlement_keys(b"\n").unwrap().is_empty());
+  }
+
+  #[test]
+  fn transient_notarization_failures() {
+    assert!(is_transient_failure(
+      "Error: The request timed out. (-1001)\n"
+    ));
+    assert!(is_transient_failure(
+      "*** Error: Unable to upload your app for notarization. HTTP status code: 503\n"
+    ));
+    assert!(!is_transient_failure(
+      "*** Error: Unable to validate your application. The username or password was incorrect.\n"
+    ));
+    assert!(!is_transient_failure(
+      "*** Error: ERROR ITMS-90732: The software asset has already been uploaded.\n"
+    ));
+  }
+
+  #[test]
+  fn sign_universal_binary() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let source = tmp.path().join("main.c");
+    std::fs::write(&source, "int main() { return 0; }").unwrap();
+    let binary = tmp.path().join("main");
+    let status = Command::new("cc")
+      .args(["-arch", "x86_64", "-arch", "arm64", "-o"])
+      .arg(&binary)
+      .arg(&source)
+      .status()
+      .expect("failed to run cc");
+    assert!(status.success());
+    assert!(is_universal_binary(&binary).unwrap());
+
+    // ad-hoc signature
+    try_sign(binary.clone(), "-", None, false, true, true, false)
+      .expect("failed to sign universal binary");
+    verify_universal_binary(&binary).expect("slices are not signed");
+  }
+}
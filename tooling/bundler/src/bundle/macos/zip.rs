@@ -0,0 +1,59 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::app;
+use crate::{
+  bundle::{common::CommandExt, paths, Bundle},
+  PackageType::MacOsBundle,
+  Settings,
+};
+
+use anyhow::Context;
+use log::info;
+
+use std::{ffi::OsStr, fs, path::PathBuf, process::Command};
+
+/// Bundles the project.
+/// Returns a vector of PathBuf that shows where the zipped .app was created.
+pub fn bundle_project(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<PathBuf>> {
+  // find our .app or rebuild our bundle
+  let app_bundle_path = match bundles
+    .iter()
+    .filter(|bundle| bundle.package_type == MacOsBundle)
+    .find_map(|bundle| {
+      bundle
+        .bundle_paths
+        .iter()
+        .find(|path| path.extension() == Some(OsStr::new(paths::app_bundle_extension(settings))))
+    }) {
+    Some(path) => path.clone(),
+    None => app::bundle_project(settings)?
+      .into_iter()
+      .next()
+      .ok_or(crate::Error::UnableToFindProject)?,
+  };
+
+  let zip_path = paths::macos_zip_path(settings, &app_bundle_path);
+  let zip_name = zip_path
+    .file_name()
+    .expect("failed to get zip filename")
+    .to_string_lossy()
+    .into_owned();
+
+  info!(action = "Bundling"; "{} ({})", zip_name, zip_path.display());
+
+  if zip_path.exists() {
+    fs::remove_file(&zip_path).with_context(|| format!("Failed to remove old {}", zip_name))?;
+  }
+
+  // use ditto so the code signature and the extended attributes survive the archive
+  Command::new("ditto")
+    .args(["-c", "-k", "--keepParent"])
+    .arg(&app_bundle_path)
+    .arg(&zip_path)
+    .output_ok()
+    .context("failed to zip app with ditto")?;
+
+  Ok(vec![zip_path])
+}
@@ -0,0 +1,624 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! The output paths of the bundles, shared by the bundlers and [`predict_output_paths`].
+
+use crate::{ChecksumFormat, MultiArchStrategy, PackageType, Settings};
+
+use std::{
+  ffi::OsString,
+  path::{Component, Path, PathBuf},
+};
+
+/// The folder name of the MSI installers.
+pub const MSI_FOLDER_NAME: &str = "msi";
+/// The folder name of the MSI installers built for the updater.
+pub const MSI_UPDATER_FOLDER_NAME: &str = "msi-updater";
+
+/// Returns the paths of the bundles [`crate::bundle_project`] creates for the settings,
+/// without building anything.
+///
+/// The checksum manifests are listed last as [`PackageType::Checksums`], like [`crate::bundle_project`] returns them.
+pub fn predict_output_paths(settings: &Settings) -> crate::Result<Vec<(PackageType, PathBuf)>> {
+  let mut paths = Vec::new();
+  for package_type in settings.package_types()? {
+    let package_paths = match package_type {
+      PackageType::MacOsBundle if signs_with_entitlements(settings) => {
+        let app_path = app_bundle_path(settings);
+        let manifest_path = entitlements_manifest_path(settings, &app_path);
+        vec![app_path, manifest_path]
+      }
+      PackageType::MacOsBundle => vec![app_bundle_path(settings)],
+      PackageType::IosBundle => vec![ios_bundle_path(settings)],
+      PackageType::WindowsMsi => msi_paths(settings)?,
+      PackageType::Deb if settings.deb().dbgsym => vec![
+        deb_package_path(settings),
+        deb_dbgsym_package_path(settings),
+      ],
+      PackageType::Deb => vec![deb_package_path(settings)],
+      // not implemented yet
+      PackageType::Rpm => Vec::new(),
+      PackageType::AppImage => vec![appimage_path(settings)],
+      PackageType::Dmg if settings.dmg().split_size_mb.is_some() => {
+        // the number of `.dmgpart` files depends on the image size, the manifest lists them
+        let dmg_path = dmg_path(settings);
+        let manifest_path = dmg_parts_manifest_path(&dmg_path);
+        vec![dmg_path, manifest_path]
+      }
+      PackageType::Dmg => vec![dmg_path(settings)],
+      PackageType::Zip if cfg!(target_os = "macos") => {
+        vec![macos_zip_path(settings, &app_bundle_path(settings))]
+      }
+      PackageType::Zip => vec![portable_zip_path(settings)],
+      PackageType::Updater => {
+        let sources = if cfg!(target_os = "macos") {
+          vec![app_bundle_path(settings)]
+        } else if cfg!(target_os = "windows") {
+          msi_paths(settings)?
+        } else {
+          vec![appimage_path(settings)]
+        };
+        sources
+          .iter()
+          .map(|source| updater_archive_path(source))
+          .collect()
+      }
+      // listed after the other bundles below
+      PackageType::Checksums => Vec::new(),
+    };
+    let mut package_paths = package_paths;
+    if package_type == PackageType::Dmg {
+      package_paths.extend(
+        dmg_arch_app_names(settings)
+          .into_iter()
+          .filter(|_| signs_with_entitlements(settings))
+          .map(|name| entitlements_manifest_path(settings, Path::new(&name))),
+      );
+    }
+    if let Some(previous) = settings.previous_artifact(package_type) {
+      if let Some(artifact) = package_paths.first() {
+        let delta_path = delta_path(artifact, &previous.version);
+        let metadata_path = delta_metadata_path(&delta_path);
+        package_paths.push(delta_path);
+        package_paths.push(metadata_path);
+      }
+    }
+    if let Some(gpg) = settings.gpg() {
+      let signed = cfg!(target_os = "linux")
+        && matches!(package_type, PackageType::Deb | PackageType::AppImage);
+      if signed {
+        let signatures: Vec<PathBuf> = package_paths
+          .iter()
+          .map(|path| gpg_signature_path(path, gpg.armor))
+          .collect();
+        package_paths.extend(signatures);
+      }
+    }
+    paths.extend(package_paths.into_iter().map(|path| (package_type, path)));
+  }
+
+  paths.extend(settings.checksum_formats().iter().map(|format| {
+    (
+      PackageType::Checksums,
+      checksums_manifest_path(settings, *format),
+    )
+  }));
+  Ok(paths)
+}
+
+/// The macOS application bundle: `bundle/macos/<product_name>.<bundle_extension>`.
+pub fn app_bundle_path(settings: &Settings) -> PathBuf {
+  // we should use the bundle name (App name) as a MacOS standard.
+  // version or platform shouldn't be included in the App name.
+  settings
+    .project_out_directory()
+    .join("bundle/macos")
+    .join(format!(
+      "{}.{}",
+      settings.product_name(),
+      app_bundle_extension(settings)
+    ))
+}
+
+/// The extension of the macOS application bundle, see [`crate::MacOsSettings::bundle_extension`].
+pub fn app_bundle_extension(settings: &Settings) -> &str {
+  settings
+    .macos()
+    .bundle_extension
+    .as_deref()
+    .unwrap_or("app")
+}
+
+/// The iOS application bundle: `bundle/ios/<product_name>.app`.
+pub fn ios_bundle_path(settings: &Settings) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle/ios")
+    .join(format!("{}.app", settings.product_name()))
+}
+
+/// The architecture name of the deb package, see [`crate::DebianSettings::arch`].
+pub fn deb_arch(settings: &Settings) -> &str {
+  match &settings.deb().arch {
+    Some(arch) => arch,
+    None => debian_arch(settings.target()),
+  }
+}
+
+/// Maps a Rust target triple to the Debian architecture name.
+///
+/// Only the architectures accepted by the settings are mapped, the other ones are returned as is.
+fn debian_arch(target: &str) -> &str {
+  let arch = target.split('-').next().unwrap_or(target);
+  match arch {
+    "x86_64" => "amd64",
+    "i386" | "i586" | "i686" => "i386",
+    "aarch64" => "arm64",
+    // the hard-float ABI is the `eabihf` environment, e.g. `armv7-unknown-linux-gnueabihf`
+    _ if arch.starts_with("arm") => {
+      if target.ends_with("hf") {
+        "armhf"
+      } else {
+        "armel"
+      }
+    }
+    other => other,
+  }
+}
+
+/// The deb package name without extension: `<binary_name>_<version>_<arch>`.
+pub fn deb_package_base_name(settings: &Settings) -> String {
+  settings.sanitize_file_name(&format!(
+    "{}_{}_{}",
+    settings.main_binary_name(),
+    settings.version_string(),
+    deb_arch(settings)
+  ))
+}
+
+/// The deb package: `bundle/deb/<binary_name>_<version>_<arch>.deb`.
+pub fn deb_package_path(settings: &Settings) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle/deb")
+    .join(format!("{}.deb", deb_package_base_name(settings)))
+}
+
+/// The delta package of a bundle against a previous version: `<bundle>.from-<previous_version>.delta`.
+pub fn delta_path(artifact: &Path, previous_version: &str) -> PathBuf {
+  let mut file_name = artifact
+    .file_name()
+    .expect("failed to get bundle filename")
+    .to_os_string();
+  file_name.push(format!(".from-{}.delta", previous_version));
+  artifact.with_file_name(file_name)
+}
+
+/// The metadata file of a delta package: `<delta>.json`.
+pub fn delta_metadata_path(delta_path: &Path) -> PathBuf {
+  let mut file_name = delta_path
+    .file_name()
+    .expect("failed to get delta filename")
+    .to_os_string();
+  file_name.push(".json");
+  delta_path.with_file_name(file_name)
+}
+
+/// The debug symbols package: `bundle/deb/<binary_name>-dbgsym_<version>_<arch>.deb`.
+pub fn deb_dbgsym_package_path(settings: &Settings) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle/deb")
+    .join(format!(
+      "{}.deb",
+      settings.sanitize_file_name(&format!(
+        "{}-dbgsym_{}_{}",
+        settings.main_binary_name(),
+        settings.version_string(),
+        deb_arch(settings)
+      ))
+    ))
+}
+
+/// The AppImage: `bundle/appimage/<binary_name>_<version>_<arch>.AppImage`.
+pub fn appimage_path(settings: &Settings) -> PathBuf {
+  let arch = match settings.binary_arch() {
+    "x86" => "i386",
+    "x86_64" => "amd64",
+    other => other,
+  };
+  settings
+    .project_out_directory()
+    .join("bundle/appimage")
+    .join(format!(
+      "{}.AppImage",
+      settings.sanitize_file_name(&format!(
+        "{}_{}_{}",
+        settings.main_binary_name(),
+        settings.version_string(),
+        arch
+      ))
+    ))
+}
+
+/// The DMG: `bundle/dmg/<file stem>.dmg`, see [`Settings::dmg_file_stem`].
+pub fn dmg_path(settings: &Settings) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle/dmg")
+    .join(format!("{}.dmg", settings.dmg_file_stem()))
+}
+
+/// The manifest of the DMG parts, see [`crate::DmgSettings::split_size_mb`]: `<dmg name>.parts.json`.
+pub fn dmg_parts_manifest_path(dmg_path: &Path) -> PathBuf {
+  dmg_path.with_extension("parts.json")
+}
+
+/// The file names of the apps built for the other [`crate::MacOsSettings::arch_binaries`] the DMG stages
+/// next to the app with the [`MultiArchStrategy::SideBySide`] strategy: `<product_name> (<arch>).<bundle_extension>`.
+pub fn dmg_arch_app_names(settings: &Settings) -> Vec<String> {
+  let macos = settings.macos();
+  let staged = macos.multi_arch_strategy == MultiArchStrategy::SideBySide
+    && settings.dmg().source_dir.is_none()
+    && settings.dmg().app_path.is_none();
+  macos
+    .arch_binaries
+    .keys()
+    .filter(|arch| staged && arch.as_str() != settings.binary_arch())
+    .map(|arch| dmg_arch_app_name(settings, arch))
+    .collect()
+}
+
+/// The file name of the app staged into the DMG for the architecture, see [`dmg_arch_app_names`].
+pub fn dmg_arch_app_name(settings: &Settings, arch: &str) -> String {
+  format!(
+    "{} ({}).{}",
+    settings.product_name(),
+    arch,
+    app_bundle_extension(settings)
+  )
+}
+
+/// The manifest of the entitlements a signed app carries, see [`crate::MacOsSettings::entitlements`]:
+/// `bundle/macos/<app>.entitlements.json` for the app bundle and `bundle/dmg/<app>.entitlements.json`
+/// for the apps staged into the DMG, outside of the folders that are imaged.
+pub fn entitlements_manifest_path(settings: &Settings, signed_path: &Path) -> PathBuf {
+  let folder = if signed_path == app_bundle_path(settings) {
+    "bundle/macos"
+  } else {
+    "bundle/dmg"
+  };
+  let mut name = signed_path
+    .file_name()
+    .expect("failed to get signed path filename")
+    .to_os_string();
+  name.push(".entitlements.json");
+  settings.project_out_directory().join(folder).join(name)
+}
+
+/// Whether the app is signed with entitlements, so an [`entitlements_manifest_path`] is written.
+pub fn signs_with_entitlements(settings: &Settings) -> bool {
+  let macos = settings.macos();
+  macos.signing_identity.is_some()
+    && macos.sign_app
+    && (macos.entitlements.is_some() || !macos.runtime_exceptions.is_empty())
+}
+
+/// A checksum manifest, see [`crate::BundleSettings::checksum_formats`]: `bundle/<manifest name>`.
+pub fn checksums_manifest_path(settings: &Settings, format: ChecksumFormat) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle")
+    .join(format.file_name())
+}
+
+/// The detached GPG signature of a bundle, see [`crate::BundleSettings::gpg`]: `<bundle>.asc` or `<bundle>.sig`.
+pub fn gpg_signature_path(path: &Path, armor: bool) -> PathBuf {
+  let mut signature_path = OsString::from(path);
+  signature_path.push(if armor { ".asc" } else { ".sig" });
+  PathBuf::from(signature_path)
+}
+
+/// The zipped macOS application bundle, next to the app bundle.
+pub fn macos_zip_path(settings: &Settings, app_bundle_path: &Path) -> PathBuf {
+  let zip_name = match &settings.zip().file_name_template {
+    Some(template) => format!(
+      "{}.zip",
+      settings.sanitize_file_name(&settings.expand_file_name_template(template))
+    ),
+    None => format!(
+      "{}.zip",
+      app_bundle_path
+        .file_name()
+        .expect("failed to get bundle filename")
+        .to_string_lossy()
+    ),
+  };
+  app_bundle_path.with_file_name(zip_name)
+}
+
+/// The portable zip on Windows and Linux: `bundle/zip/<file stem>.zip`.
+pub fn portable_zip_path(settings: &Settings) -> PathBuf {
+  settings
+    .project_out_directory()
+    .join("bundle/zip")
+    .join(format!("{}.zip", portable_zip_file_stem(settings)))
+}
+
+/// The portable zip file name without extension, see [`crate::ZipSettings::file_name_template`].
+pub fn portable_zip_file_stem(settings: &Settings) -> String {
+  settings.sanitize_file_name(
+    &settings.expand_file_name_template(
+      settings
+        .zip()
+        .file_name_template
+        .as_deref()
+        .unwrap_or("{binary_name}_{version}_{arch}"),
+    ),
+  )
+}
+
+/// The MSI installers, one per configured language.
+fn msi_paths(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
+  settings
+    .windows()
+    .wix
+    .as_ref()
+    .map(|wix| wix.language.clone())
+    .unwrap_or_default()
+    .0
+    .iter()
+    .map(|(language, _)| msi_path(settings, language, false))
+    .collect()
+}
+
+/// The MSI installer: `bundle/msi/<binary_name>_<version>_<arch>_<language>.msi`,
+/// in the `msi-updater` folder for the installers built for the updater.
+pub fn msi_path(settings: &Settings, language: &str, updater: bool) -> crate::Result<PathBuf> {
+  let arch = match settings.binary_arch() {
+    "x86" => "x86",
+    "x86_64" => "x64",
+    target => {
+      return Err(crate::Error::ArchError(format!(
+        "Unsupported architecture: {}",
+        target
+      )))
+    }
+  };
+
+  let package_base_name = settings.sanitize_file_name(&format!(
+    "{}_{}_{}_{}",
+    settings.main_binary_name().replace(".exe", ""),
+    settings.version_string(),
+    arch,
+    language,
+  ));
+
+  Ok(settings.project_out_directory().to_path_buf().join(format!(
+    "bundle/{}/{}.msi",
+    if updater {
+      MSI_UPDATER_FOLDER_NAME
+    } else {
+      MSI_FOLDER_NAME
+    },
+    package_base_name
+  )))
+}
+
+/// The updater archive of a bundle: `<bundle>.tar.gz`, or `<installer>.msi.zip` in the `msi` folder for MSI installers.
+pub fn updater_archive_path(source_path: &Path) -> PathBuf {
+  if source_path.extension().map_or(false, |ext| ext == "msi") {
+    source_path
+      .components()
+      .fold(PathBuf::new(), |mut p, c| {
+        if let Component::Normal(name) = c {
+          if name == MSI_UPDATER_FOLDER_NAME {
+            p.push(MSI_FOLDER_NAME);
+            return p;
+          }
+        }
+        p.push(c);
+        p
+      })
+      .with_extension("msi.zip")
+  } else {
+    PathBuf::from(format!("{}.tar.gz", source_path.display()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::debian_arch;
+
+  #[test]
+  fn maps_targets_to_debian_architectures() {
+    for (target, arch) in [
+      ("x86_64-unknown-linux-gnu", "amd64"),
+      ("i686-unknown-linux-gnu", "i386"),
+      ("aarch64-unknown-linux-gnu", "arm64"),
+      ("armv7-unknown-linux-gnueabihf", "armhf"),
+      ("arm-unknown-linux-gnueabihf", "armhf"),
+      ("arm-unknown-linux-gnueabi", "armel"),
+    ] {
+      assert_eq!(debian_arch(target), arch, "{}", target);
+    }
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod linux_tests {
+  use super::predict_output_paths;
+  use crate::{
+    bundle::settings::test_settings_builder, BundleSettings, ChecksumFormat, GpgSettings,
+    PackageType,
+  };
+
+  #[test]
+  fn predicts_linux_bundle_paths() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings =
+      test_settings_builder(tmp.path(), Default::default(), "x86_64-unknown-linux-gnu")
+        .package_types(vec![PackageType::Deb, PackageType::AppImage])
+        .build()
+        .expect("Failed to build settings");
+
+    assert_eq!(
+      predict_output_paths(&settings).expect("Failed to predict paths"),
+      vec![
+        (
+          PackageType::Deb,
+          tmp.path().join("bundle/deb/app_1.0.0_amd64.deb")
+        ),
+        (
+          PackageType::AppImage,
+          tmp.path().join("bundle/appimage/app_1.0.0_amd64.AppImage")
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn predicts_gpg_signatures() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings = test_settings_builder(
+      tmp.path(),
+      BundleSettings {
+        gpg: Some(GpgSettings {
+          key: "ABCDEF0123456789".into(),
+          armor: true,
+        }),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    )
+    .package_types(vec![PackageType::Deb])
+    .build()
+    .expect("Failed to build settings");
+
+    assert_eq!(
+      predict_output_paths(&settings).expect("Failed to predict paths"),
+      vec![
+        (
+          PackageType::Deb,
+          tmp.path().join("bundle/deb/app_1.0.0_amd64.deb")
+        ),
+        (
+          PackageType::Deb,
+          tmp.path().join("bundle/deb/app_1.0.0_amd64.deb.asc")
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn predicts_checksum_manifests() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings = test_settings_builder(
+      tmp.path(),
+      BundleSettings {
+        checksum_formats: vec![ChecksumFormat::Sha256sum, ChecksumFormat::Json],
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    )
+    .package_types(vec![PackageType::Deb, PackageType::AppImage])
+    .build()
+    .expect("Failed to build settings");
+
+    assert_eq!(
+      predict_output_paths(&settings).expect("Failed to predict paths"),
+      vec![
+        (
+          PackageType::Deb,
+          tmp.path().join("bundle/deb/app_1.0.0_amd64.deb")
+        ),
+        (
+          PackageType::AppImage,
+          tmp.path().join("bundle/appimage/app_1.0.0_amd64.AppImage")
+        ),
+        (PackageType::Checksums, tmp.path().join("bundle/SHA256SUMS")),
+        (
+          PackageType::Checksums,
+          tmp.path().join("bundle/checksums.json")
+        ),
+      ]
+    );
+  }
+}
+
+#[cfg(all(test, target_os = "macos"))]
+mod macos_tests {
+  use super::predict_output_paths;
+  use crate::{
+    bundle::settings::test_settings_builder, BundleSettings, DmgSettings, MacOsSettings,
+    PackageType,
+  };
+
+  #[test]
+  fn predicts_the_entitlements_manifest() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings = test_settings_builder(
+      tmp.path(),
+      BundleSettings {
+        macos: MacOsSettings {
+          signing_identity: Some("-".into()),
+          entitlements: Some("entitlements.plist".into()),
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      "x86_64-apple-darwin",
+    )
+    .package_types(vec![PackageType::MacOsBundle])
+    .build()
+    .expect("Failed to build settings");
+
+    assert_eq!(
+      predict_output_paths(&settings).expect("Failed to predict paths"),
+      vec![
+        (
+          PackageType::MacOsBundle,
+          tmp.path().join("bundle/macos/app.app")
+        ),
+        (
+          PackageType::MacOsBundle,
+          tmp.path().join("bundle/macos/app.app.entitlements.json")
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn predicts_the_dmg_parts_manifest() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings = test_settings_builder(
+      tmp.path(),
+      BundleSettings {
+        dmg: DmgSettings {
+          split_size_mb: Some(100),
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      "x86_64-apple-darwin",
+    )
+    .package_types(vec![PackageType::Dmg])
+    .build()
+    .expect("Failed to build settings");
+
+    assert_eq!(
+      predict_output_paths(&settings).expect("Failed to predict paths"),
+      vec![
+        (
+          PackageType::Dmg,
+          tmp.path().join("bundle/dmg/app_1.0.0_x64.dmg")
+        ),
+        (
+          PackageType::Dmg,
+          tmp.path().join("bundle/dmg/app_1.0.0_x64.parts.json")
+        ),
+      ]
+    );
+  }
+}
@@ -0,0 +1,212 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::{common, paths};
+use crate::Settings;
+use anyhow::Context;
+use log::{info, warn};
+use zip::write::FileOptions;
+
+use std::{
+  collections::BTreeMap,
+  fs::{self, File},
+  io::{self, Seek, Write},
+  path::{Path, PathBuf},
+};
+
+/// A file of the portable zip.
+enum Entry {
+  /// A file copied from disk.
+  File(PathBuf),
+  /// A generated file.
+  Data(Vec<u8>),
+}
+
+/// Bundles the project.
+/// Returns a vector of PathBuf that shows where the portable zip was created.
+///
+/// The binaries, external binaries and resources are laid out like the installed Windows app
+/// in a folder named after the zip.
+pub fn bundle_project(settings: &Settings) -> crate::Result<Vec<PathBuf>> {
+  let zip_path = paths::portable_zip_path(settings);
+  let zip_name = zip_path
+    .file_name()
+    .expect("failed to get zip filename")
+    .to_string_lossy()
+    .into_owned();
+
+  info!(action = "Bundling"; "{} ({})", zip_name, zip_path.display());
+
+  if zip_path.exists() {
+    fs::remove_file(&zip_path).with_context(|| format!("Failed to remove old {}", zip_name))?;
+  }
+  let file = common::create_file(&zip_path)?;
+  write_portable_zip(settings, file)
+    .with_context(|| "Failed to zip the app")?
+    .flush()?;
+
+  Ok(vec![zip_path])
+}
+
+/// Writes the portable zip bundle to the `writer` instead of the project output directory,
+/// e.g. to upload it without writing it to disk, and returns the writer.
+///
+/// Only the binaries and resources are read from disk.
+/// Use an [`std::io::Cursor`] over a `Vec<u8>` to get the zip in memory.
+pub fn write_portable_zip<W: Write + Seek>(settings: &Settings, writer: W) -> crate::Result<W> {
+  if settings.prebuilt_bundle().is_some() {
+    return Err(crate::Error::GenericError(
+      "the portable zip does not support a prebuilt bundle".into(),
+    ));
+  }
+  let root = paths::portable_zip_file_stem(settings);
+  let entries = entries(settings)?;
+
+  let mut zip = zip::ZipWriter::new(writer);
+  let dir_options = FileOptions::default().unix_permissions(0o755);
+  zip.add_directory(root.as_str(), dir_options)?;
+  let mut directories = Vec::new();
+  for (path, entry) in entries {
+    let path = format!("{}/{}", root, path);
+    // every parent folder is added before the files it contains
+    for (i, _) in path.match_indices('/').skip(1) {
+      let directory = &path[..i];
+      if !directories.iter().any(|d| d == directory) {
+        zip.add_directory(directory, dir_options)?;
+        directories.push(directory.to_string());
+      }
+    }
+    match entry {
+      Entry::File(src) => {
+        let options = FileOptions::default().unix_permissions(file_mode(&src)?);
+        zip.start_file(path, options)?;
+        io::copy(&mut File::open(&src)?, &mut zip)?;
+      }
+      Entry::Data(data) => {
+        zip.start_file(path, FileOptions::default().unix_permissions(0o644))?;
+        zip.write_all(&data)?;
+      }
+    }
+  }
+
+  Ok(zip.finish()?)
+}
+
+// The files of the zip keyed by their path in the app folder, later files replacing earlier ones.
+fn entries(settings: &Settings) -> crate::Result<BTreeMap<String, Entry>> {
+  let mut entries = BTreeMap::new();
+  if settings.shared_resource_files().next().is_some() {
+    warn!(
+      "the portable zip has no install location for the shared resources, they are not included"
+    );
+  }
+  for bin in settings.binaries() {
+    let bin_path = settings.binary_path(bin);
+    if !bin_path.is_file() {
+      return Err(crate::Error::GenericError(format!(
+        "Failed to find binary {:?}",
+        bin_path
+      )));
+    }
+    entries.insert(bin.name().to_string(), Entry::File(bin_path));
+  }
+  for src in settings.external_binaries() {
+    let src = src?;
+    let name = src
+      .file_name()
+      .expect("failed to extract external binary filename")
+      .to_string_lossy()
+      .replace(&format!("-{}", settings.target()), "");
+    entries.insert(name, Entry::File(src));
+  }
+  for src in settings.resource_files() {
+    let src = src?;
+    let path = archive_path(&tauri_utils::resources::resource_relpath(&src));
+    entries.insert(path, Entry::File(src));
+  }
+  for (src, target) in settings.remote_resource_files() {
+    entries.insert(archive_path(target), Entry::File(src.clone()));
+  }
+  if let Some((file_name, contents)) = settings.build_info()? {
+    entries.insert(file_name.to_string(), Entry::Data(contents));
+  }
+  if let Some((file_name, notes)) = settings.release_notes_file()? {
+    entries.insert(file_name, Entry::Data(notes.into_bytes()));
+  }
+  Ok(entries)
+}
+
+// Joins the components of a relative path with forward slashes, as zip entries require.
+fn archive_path(path: &Path) -> String {
+  path
+    .components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> crate::Result<u32> {
+  use std::os::unix::fs::PermissionsExt;
+  Ok(fs::metadata(path)?.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(path: &Path) -> crate::Result<u32> {
+  Ok(if path.is_dir() { 0o755 } else { 0o644 })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{bundle_project, write_portable_zip};
+  use crate::{bundle::settings::test_settings, BundleSettings, ReleaseNotes, ZipSettings};
+  use std::{
+    fs::{self, File},
+    io::{Cursor, Read},
+  };
+
+  #[test]
+  fn portable_zip_contains_binaries() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    fs::write(tmp.path().join("app"), b"binary").expect("Failed to write binary");
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        zip: ZipSettings {
+          file_name_template: Some("{product_name}-{version}-portable".into()),
+        },
+        release_notes: Some(ReleaseNotes::Text("- Fixed the crash on startup".into())),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+
+    let paths = bundle_project(&settings).expect("Failed to bundle zip");
+    assert_eq!(
+      paths,
+      vec![tmp.path().join("bundle/zip/App-1.0.0-portable.zip")]
+    );
+
+    let mut archive = zip::ZipArchive::new(File::open(&paths[0]).expect("Failed to open zip"))
+      .expect("invalid zip");
+    assert!(archive.by_name("App-1.0.0-portable/app").is_ok());
+    assert!(archive
+      .by_name("App-1.0.0-portable/RELEASE_NOTES.md")
+      .is_ok());
+    let mut build_info = String::new();
+    archive
+      .by_name("App-1.0.0-portable/.build-info.json")
+      .expect("missing build info")
+      .read_to_string(&mut build_info)
+      .unwrap();
+    assert!(build_info.contains(r#""releaseNotes": "- Fixed the crash on startup""#));
+
+    // the same zip can be written to memory
+    let buffer = write_portable_zip(&settings, Cursor::new(Vec::new()))
+      .expect("Failed to write zip")
+      .into_inner();
+    let mut archive = zip::ZipArchive::new(Cursor::new(buffer)).expect("invalid zip");
+    assert!(archive.by_name("App-1.0.0-portable/app").is_ok());
+  }
+}
@@ -0,0 +1,190 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use crate::Settings;
+
+use std::path::{Path, PathBuf};
+
+/// Checks that every file the settings reference exists before any bundle is created,
+/// and reports all missing files in a single [`crate::Error::MissingFiles`].
+///
+/// Only the files of the current platform are checked, since the other bundlers do not run.
+pub fn check(settings: &Settings) -> crate::Result<()> {
+  let mut missing = Vec::new();
+
+  match settings.prebuilt_bundle() {
+    Some(prebuilt_bundle) => check_path(&mut missing, "prebuilt bundle", prebuilt_bundle),
+    None => {
+      for bin in settings.binaries() {
+        check_path(&mut missing, "binary", &settings.binary_path(bin));
+      }
+    }
+  }
+  for (kind, paths) in [
+    ("resource", settings.resource_files()),
+    ("shared resource", settings.shared_resource_files()),
+  ] {
+    for path in paths {
+      match path {
+        Ok(_) => (),
+        Err(crate::Error::Resource(e)) => missing.push(format!("{} {}", kind, e)),
+        // a pattern matching more files than the copy limits allow, checked before anything is copied
+        Err(e) => return Err(e),
+      }
+    }
+  }
+  for (kind, paths) in [
+    ("icon", settings.icon_files()),
+    ("external binary", settings.external_binaries()),
+  ] {
+    for path in paths {
+      if let Err(e) = path {
+        missing.push(format!("{} {}", kind, e));
+      }
+    }
+  }
+
+  if let Some(path) = settings.release_notes_path() {
+    check_path(&mut missing, "release notes", path);
+  }
+  for package_type in settings.package_types()? {
+    if let Some(previous) = settings.previous_artifact(package_type) {
+      check_path(&mut missing, "previous artifact", &previous.path);
+    }
+  }
+
+  if let Some(appcast) = settings.appcast() {
+    for (kind, path) in [
+      ("sign_update tool", appcast.sign_update_path.as_ref()),
+      ("EdDSA key file", appcast.ed_key_file.as_ref()),
+    ] {
+      if let Some(path) = path {
+        check_path(&mut missing, kind, path);
+      }
+    }
+  }
+
+  if cfg!(target_os = "macos") {
+    let macos = settings.macos();
+    for framework in macos.frameworks.iter().flatten() {
+      // plain names are looked up in the framework folders of the system
+      if framework.ends_with(".framework") {
+        check_path(&mut missing, "framework", Path::new(framework));
+      }
+    }
+    for (kind, path) in [
+      ("license", macos.license.as_ref().map(PathBuf::from)),
+      (
+        "entitlements",
+        macos.entitlements.as_ref().map(PathBuf::from),
+      ),
+      ("Info.plist", macos.info_plist_path.clone()),
+      ("icns icon", macos.icns_path.clone()),
+      ("DMG background", settings.dmg().background.clone()),
+      ("DMG .DS_Store", settings.dmg().ds_store.clone()),
+    ] {
+      if let Some(path) = path {
+        check_path(&mut missing, kind, &path);
+      }
+    }
+    for path in macos.arch_binaries.values() {
+      check_path(&mut missing, "architecture binary", path);
+    }
+    if let Some(crash_reporter) = &macos.crash_reporter {
+      check_path(&mut missing, "crash reporter", &crash_reporter.path);
+      if let Some(entitlements) = &crash_reporter.entitlements {
+        check_path(&mut missing, "crash reporter entitlements", entitlements);
+      }
+    }
+  }
+
+  if cfg!(any(target_os = "macos", target_os = "linux")) {
+    if let Some(source_dir) = &settings.dmg().source_dir {
+      check_path(&mut missing, "DMG source directory", source_dir);
+    }
+  }
+
+  if cfg!(target_os = "macos") {
+    if let Some(app_path) = &settings.dmg().app_path {
+      check_path(&mut missing, "DMG app", app_path);
+    }
+  }
+
+  if cfg!(target_os = "linux") {
+    for path in settings.deb().files.values() {
+      check_path(&mut missing, "deb file", path);
+    }
+  }
+
+  if cfg!(target_os = "windows") {
+    let windows = settings.windows();
+    check_path(&mut missing, "Windows icon", &windows.icon_path);
+    if let Some(path) = &windows.webview_fixed_runtime_path {
+      check_path(&mut missing, "fixed WebView2 runtime", path);
+    }
+    if let Some(path) = windows
+      .key_provider
+      .as_ref()
+      .and_then(|provider| provider.certificate_path.as_ref())
+    {
+      check_path(&mut missing, "signing certificate", path);
+    }
+    if let Some(wix) = &windows.wix {
+      for (kind, path) in [
+        ("WiX template", wix.template.as_ref()),
+        ("WiX license", wix.license.as_ref()),
+        ("WiX banner", wix.banner_path.as_ref()),
+        ("WiX dialog image", wix.dialog_image_path.as_ref()),
+      ] {
+        if let Some(path) = path {
+          check_path(&mut missing, kind, path);
+        }
+      }
+      for path in &wix.fragment_paths {
+        check_path(&mut missing, "WiX fragment", path);
+      }
+    }
+  }
+
+  if missing.is_empty() {
+    Ok(())
+  } else {
+    Err(crate::Error::MissingFiles(missing))
+  }
+}
+
+fn check_path(missing: &mut Vec<String>, kind: &str, path: &Path) {
+  if !path.exists() {
+    missing.push(format!("{} {}", kind, path.display()));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::check;
+  use crate::{bundle::settings::test_settings, BundleSettings};
+
+  #[test]
+  fn reports_all_missing_files() {
+    let tmp = tempfile::tempdir().expect("unable to create tempdir");
+    let settings = test_settings(
+      tmp.path(),
+      BundleSettings {
+        resources: Some(vec!["missing/resource.txt".into()]),
+        icon: Some(vec!["missing/icon.png".into()]),
+        ..Default::default()
+      },
+      "x86_64-unknown-linux-gnu",
+    );
+
+    match check(&settings) {
+      Err(crate::Error::MissingFiles(missing)) => {
+        assert!(missing[0].starts_with("binary "));
+        assert!(missing[1].contains("missing/resource.txt"));
+        assert!(missing[2].contains("missing/icon.png"));
+      }
+      other => panic!("expected missing files, got {:?}", other),
+    }
+  }
+}
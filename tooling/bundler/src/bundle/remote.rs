@@ -0,0 +1,108 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::common::{self, CommandExt};
+use crate::RemoteResource;
+use anyhow::Context;
+use log::info;
+
+use std::{
+  fs,
+  path::{Component, Path, PathBuf},
+  process::Command,
+};
+
+/// Downloads the [`crate::BundleSettings::remote_resources`] to the cache and verifies their checksums.
+///
+/// Returns the cached file and the path in the resources directory of each resource.
+pub fn fetch_all(
+  remote_resources: &[RemoteResource],
+  out_dir: &Path,
+) -> crate::Result<Vec<(PathBuf, PathBuf)>> {
+  if remote_resources.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  let cache_dir = dirs_next::cache_dir()
+    .map_or_else(|| out_dir.to_path_buf(), |p| p.join("tauri"))
+    .join("remote-resources");
+
+  let mut files = Vec::new();
+  for resource in remote_resources {
+    validate_target(&resource.target)?;
+    files.push((fetch(resource, &cache_dir)?, resource.target.clone()));
+  }
+  Ok(files)
+}
+
+// Returns the cached download of the resource, downloading it if it is missing or corrupted.
+fn fetch(resource: &RemoteResource, cache_dir: &Path) -> crate::Result<PathBuf> {
+  let expected = resource.sha256.to_lowercase();
+  if expected.len() != 64 || hex::decode(&expected).is_err() {
+    return Err(crate::Error::GenericError(format!(
+      "invalid SHA-256 checksum `{}` of the remote resource {}",
+      resource.sha256, resource.url
+    )));
+  }
+  let file_name = resource
+    .target
+    .file_name()
+    .expect("validated remote resource target");
+  // the checksum keys the cache, so changing the pinned version downloads it again
+  let path = cache_dir.join(&expected).join(file_name);
+  if path.is_file() && common::hash_file(&path)? == expected {
+    return Ok(path);
+  }
+
+  info!(action = "Downloading"; "{}", resource.url);
+  let download_path = path.with_extension("download");
+  common::create_file(&download_path)?;
+  Command::new("curl")
+    .args(["-fsSL", "--retry", "3", "-o"])
+    .arg(&download_path)
+    .arg(&resource.url)
+    .output_ok()
+    .with_context(|| format!("failed to download the remote resource {}", resource.url))?;
+
+  let actual = common::hash_file(&download_path)?;
+  if actual != expected {
+    fs::remove_file(&download_path)?;
+    return Err(crate::Error::GenericError(format!(
+      "checksum mismatch of the remote resource {}: expected {}, got {}",
+      resource.url, expected, actual
+    )));
+  }
+  fs::rename(&download_path, &path)?;
+  Ok(path)
+}
+
+// Checks that the target stays inside the resources directory and names a file.
+fn validate_target(target: &Path) -> crate::Result<()> {
+  let valid = target.file_name().is_some()
+    && target
+      .components()
+      .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+  if valid {
+    Ok(())
+  } else {
+    Err(crate::Error::GenericError(format!(
+      "the remote resource target {} must be a relative file path",
+      target.display()
+    )))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::validate_target;
+  use std::path::Path;
+
+  #[test]
+  fn remote_resource_targets() {
+    assert!(validate_target(Path::new("models/model.onnx")).is_ok());
+    assert!(validate_target(Path::new("../model.onnx")).is_err());
+    assert!(validate_target(Path::new("/model.onnx")).is_err());
+    assert!(validate_target(Path::new("")).is_err());
+  }
+}
@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use super::common;
+use super::{common, paths};
 
 #[cfg(target_os = "macos")]
 use super::macos::app;
@@ -51,7 +51,7 @@ fn bundle_update(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<P
       bundle
         .bundle_paths
         .iter()
-        .find(|path| path.extension() == Some(OsStr::new("app")))
+        .find(|path| path.extension() == Some(OsStr::new(paths::app_bundle_extension(settings))))
     }) {
     Some(path) => vec![path.clone()],
     None => app::bundle_project(settings)?,
@@ -65,8 +65,8 @@ fn bundle_update(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<P
   let source_path = &bundle_path[0];
 
   // add .tar.gz to our path
-  let osx_archived = format!("{}.tar.gz", source_path.display());
-  let osx_archived_path = PathBuf::from(&osx_archived);
+  let osx_archived_path = paths::updater_archive_path(source_path);
+  let osx_archived = osx_archived_path.display().to_string();
 
   // Create our gzip file (need to send parent)
   // as we walk the source directory (source isnt added)
@@ -97,7 +97,7 @@ fn bundle_update(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<P
         .find(|path| path.extension() == Some(OsStr::new("AppImage")))
     }) {
     Some(path) => vec![path.clone()],
-    None => appimage::bundle_project(settings)?,
+    None => appimage::bundle_project(settings, &mut None)?,
   };
 
   // we expect our .app to be on bundle[0]
@@ -108,8 +108,8 @@ fn bundle_update(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<P
   let source_path = &bundle_path[0];
 
   // add .tar.gz to our path
-  let appimage_archived = format!("{}.tar.gz", source_path.display());
-  let appimage_archived_path = PathBuf::from(&appimage_archived);
+  let appimage_archived_path = paths::updater_archive_path(source_path);
+  let appimage_archived = appimage_archived_path.display().to_string();
 
   // Create our gzip file
   create_tar(source_path, &appimage_archived_path)
@@ -153,19 +153,7 @@ fn bundle_update(settings: &Settings, bundles: &[Bundle]) -> crate::Result<Vec<P
 
   for source_path in bundle_paths {
     // add .zip to our path
-    let msi_archived_path = source_path
-      .components()
-      .fold(PathBuf::new(), |mut p, c| {
-        if let std::path::Component::Normal(name) = c {
-          if name == msi::MSI_UPDATER_FOLDER_NAME {
-            p.push(msi::MSI_FOLDER_NAME);
-            return p;
-          }
-        }
-        p.push(c);
-        p
-      })
-      .with_extension("msi.zip");
+    let msi_archived_path = paths::updater_archive_path(&source_path);
 
     info!(action = "Bundling"; "{}", msi_archived_path.display());
 
@@ -5,3 +5,4 @@
 
 pub mod msi;
 pub mod sign;
+pub mod version_info;
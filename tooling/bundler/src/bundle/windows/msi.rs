@@ -5,10 +5,8 @@
 
 mod wix;
 
-pub use wix::{MSI_FOLDER_NAME, MSI_UPDATER_FOLDER_NAME};
-
 use crate::Settings;
-use log::warn;
+use log::{debug, warn};
 
 use std::{self, path::PathBuf};
 
@@ -28,19 +26,76 @@ const WIX_REQUIRED_FILES: &[&str] = &[
 /// Runs all of the commands to build the MSI installer.
 /// Returns a vector of PathBuf that shows where the MSI was created.
 pub fn bundle_project(settings: &Settings, updater: bool) -> crate::Result<Vec<PathBuf>> {
+  if settings.prebuilt_bundle().is_some() {
+    return Err(crate::Error::GenericError(
+      "the msi bundle does not support a prebuilt bundle".into(),
+    ));
+  }
+  let wix_settings = settings.windows().wix.as_ref();
+  let (url, sha256, dir_name) =
+    match wix_settings.map(|wix| (&wix.toolset_url, &wix.toolset_sha256)) {
+      Some((Some(url), Some(sha256))) => (
+        url.as_str(),
+        sha256.as_str(),
+        format!("WixTools-{}", sha256.to_lowercase()),
+      ),
+      Some((Some(_), None)) | Some((None, Some(_))) => {
+        return Err(crate::Error::GenericError(
+          "the WiX `toolset_url` and `toolset_sha256` must be set together".into(),
+        ))
+      }
+      _ => (wix::WIX_URL, wix::WIX_SHA256, "WixTools".into()),
+    };
+
   let mut wix_path = dirs_next::cache_dir().unwrap();
-  wix_path.push("tauri/WixTools");
+  wix_path.push("tauri");
+  wix_path.push(dir_name);
 
   if !wix_path.exists() {
-    wix::get_and_extract_wix(&wix_path)?;
+    wix::get_and_extract_wix(url, sha256, &wix_path)?;
   } else if WIX_REQUIRED_FILES
     .iter()
     .any(|p| !wix_path.join(p).exists())
   {
     warn!("WixTools directory is missing some files. Recreating it.");
     std::fs::remove_dir_all(&wix_path)?;
-    wix::get_and_extract_wix(&wix_path)?;
+    wix::get_and_extract_wix(url, sha256, &wix_path)?;
+  }
+
+  let version = wix::toolset_version(&wix_path)?;
+  debug!("using WiX toolset {} from {}", version, wix_path.display());
+  if let Some(required) = wix_settings.and_then(|wix| wix.toolset_version.as_ref()) {
+    if !version_matches(&version, required) {
+      return Err(crate::Error::GenericError(format!(
+        "the WiX toolset in {} is version {}, but version {} is required",
+        wix_path.display(),
+        version,
+        required
+      )));
+    }
   }
 
   wix::build_wix_app_installer(settings, &wix_path, updater)
 }
+
+// Whether the detected version starts with the components of the required version, e.g. `3.11.2.4516` matches `3.11.2`.
+fn version_matches(version: &str, required: &str) -> bool {
+  let mut version = version.split('.');
+  required
+    .split('.')
+    .all(|component| version.next() == Some(component))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::version_matches;
+
+  #[test]
+  fn matches_toolset_versions() {
+    assert!(version_matches("3.11.2.4516", "3.11.2"));
+    assert!(version_matches("3.11.2.4516", "3.11.2.4516"));
+    assert!(!version_matches("3.11.2.4516", "3.1"));
+    assert!(!version_matches("3.14.0.8606", "3.11"));
+    assert!(!version_matches("3.11", "3.11.2"));
+  }
+}
@@ -3,13 +3,18 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use super::super::sign::{sign, SignParams};
+use super::super::{
+  sign::{sign, SignParams},
+  version_info,
+};
 use crate::bundle::{
+  audit,
   common::CommandExt,
   path_utils::{copy_file, FileOpts},
-  settings::Settings,
+  paths,
+  settings::{Settings, WixUpgradeCode},
 };
-use anyhow::{bail, Context};
+use anyhow::Context;
 use handlebars::{to_json, Handlebars};
 use log::info;
 use regex::Regex;
@@ -30,8 +35,6 @@ use zip::ZipArchive;
 pub const WIX_URL: &str =
   "https://github.com/wixtoolset/wix3/releases/download/wix3112rtm/wix311-binaries.zip";
 pub const WIX_SHA256: &str = "2c1888d5d1dba377fc7fa14444cf556963747ff9a0a289a3599cf09da03b9e2e";
-pub const MSI_FOLDER_NAME: &str = "msi";
-pub const MSI_UPDATER_FOLDER_NAME: &str = "msi-updater";
 const WEBVIEW2_BOOTSTRAPPER_URL: &str = "https://go.microsoft.com/fwlink/p/?LinkId=2124703";
 const WEBVIEW2_X86_INSTALLER_GUID: &str = "a17bde80-b5ab-47b5-8bbb-1cbe93fc6ec9";
 const WEBVIEW2_X64_INSTALLER_GUID: &str = "aa5fd9b3-dc11-4cbc-8343-a50f57b311e1";
@@ -112,6 +115,14 @@ impl ResourceDirectory {
     self.files.push(file);
   }
 
+  /// Collects the paths of the files of this directory recursively.
+  fn collect_paths(&self, paths: &mut Vec<PathBuf>) {
+    paths.extend(self.files.iter().map(|file| PathBuf::from(&file.path)));
+    for directory in &self.directories {
+      directory.collect_paths(paths);
+    }
+  }
+
   /// Generates the wix XML string to bundle this directory resources recursively
   fn get_wix_data(self) -> crate::Result<(String, Vec<String>)> {
     let mut files = String::from("");
@@ -151,6 +162,42 @@ impl ResourceDirectory {
   }
 }
 
+/// Computes the disk space the installer reserves in addition to the `payload` files,
+/// which Windows Installer already accounts for, to require [`crate::WixSettings::required_disk_space`]
+/// and the [`crate::WixSettings::disk_space_buffer`].
+fn reserved_disk_space(settings: &Settings, payload: &[PathBuf]) -> crate::Result<u64> {
+  let mut payload_size = 0;
+  for path in payload {
+    payload_size += std::fs::metadata(path)
+      .with_context(|| format!("failed to read the size of {}", path.display()))?
+      .len();
+  }
+  let (required, buffer) = match &settings.windows().wix {
+    Some(wix) => (
+      wix.required_disk_space.unwrap_or(payload_size),
+      wix.disk_space_buffer,
+    ),
+    None => (payload_size, 0),
+  };
+  let required = required.checked_add(buffer).ok_or_else(|| {
+    crate::Error::GenericError("the required disk space and its buffer are too large".into())
+  })?;
+  info!(
+    "the installer requires {:.1} MB of disk space",
+    required as f64 / 1_000_000.0
+  );
+  let reserved = required.saturating_sub(payload_size);
+  // the `ReserveCost` columns are 32-bit signed integers
+  if reserved > i32::MAX as u64 {
+    return Err(crate::Error::GenericError(format!(
+      "the installer can reserve at most {} bytes of disk space beyond its files, but {} bytes are required",
+      i32::MAX,
+      reserved
+    )));
+  }
+  Ok(reserved)
+}
+
 /// Copies the icon to the binary path, under the `resources` folder,
 /// and returns the path to the file.
 fn copy_icon(settings: &Settings, filename: &str, path: &Path) -> crate::Result<PathBuf> {
@@ -198,42 +245,6 @@ fn download_and_verify(url: &str, hash: &str) -> crate::Result<Vec<u8>> {
   }
 }
 
-/// The app installer output path.
-fn app_installer_output_path(
-  settings: &Settings,
-  language: &str,
-  updater: bool,
-) -> crate::Result<PathBuf> {
-  let arch = match settings.binary_arch() {
-    "x86" => "x86",
-    "x86_64" => "x64",
-    target => {
-      return Err(crate::Error::ArchError(format!(
-        "Unsupported architecture: {}",
-        target
-      )))
-    }
-  };
-
-  let package_base_name = format!(
-    "{}_{}_{}_{}",
-    settings.main_binary_name().replace(".exe", ""),
-    settings.version_string(),
-    arch,
-    language,
-  );
-
-  Ok(settings.project_out_directory().to_path_buf().join(format!(
-    "bundle/{}/{}.msi",
-    if updater {
-      MSI_UPDATER_FOLDER_NAME
-    } else {
-      MSI_FOLDER_NAME
-    },
-    package_base_name
-  )))
-}
-
 /// Extracts the zips from Wix and VC_REDIST into a useable path.
 fn extract_zip(data: &[u8], path: &Path) -> crate::Result<()> {
   let cursor = Cursor::new(data);
@@ -264,6 +275,29 @@ fn generate_package_guid(settings: &Settings) -> Uuid {
   generate_guid(settings.bundle_identifier().as_bytes())
 }
 
+/// Generates the upgrade code of [`crate::WixSettings::upgrade_code`].
+fn upgrade_code(settings: &Settings) -> crate::Result<String> {
+  let source = settings
+    .windows()
+    .wix
+    .as_ref()
+    .map(|wix| wix.upgrade_code.clone())
+    .unwrap_or_default();
+  let upgrade_code = match source {
+    WixUpgradeCode::MainBinaryName => Uuid::new_v5(
+      &Uuid::NAMESPACE_DNS,
+      format!("{}.app.x64", &settings.main_binary_name()).as_bytes(),
+    ),
+    WixUpgradeCode::BundleIdentifier => Uuid::new_v5(
+      &Uuid::NAMESPACE_DNS,
+      settings.bundle_identifier().as_bytes(),
+    ),
+    WixUpgradeCode::Custom(guid) => Uuid::parse_str(guid.trim_matches(|c| c == '{' || c == '}'))
+      .map_err(|_| crate::Error::GenericError(format!("invalid WiX upgrade code `{}`", guid)))?,
+  };
+  Ok(upgrade_code.to_string())
+}
+
 /// Generates a GUID.
 fn generate_guid(key: &[u8]) -> Uuid {
   let namespace = Uuid::from_bytes(UUID_NAMESPACE);
@@ -271,16 +305,31 @@ fn generate_guid(key: &[u8]) -> Uuid {
 }
 
 // Specifically goes and gets Wix and verifies the download via Sha256
-pub fn get_and_extract_wix(path: &Path) -> crate::Result<()> {
+pub fn get_and_extract_wix(url: &str, sha256: &str, path: &Path) -> crate::Result<()> {
   info!("Verifying wix package");
 
-  let data = download_and_verify(WIX_URL, WIX_SHA256)?;
+  let data = download_and_verify(url, sha256)?;
 
   info!("extracting WIX");
 
   extract_zip(&data, path)
 }
 
+/// Reads the version of the WiX toolset from the candle.exe banner, e.g. `3.11.2.4516`.
+pub fn toolset_version(wix_toolset_path: &Path) -> crate::Result<String> {
+  let output = Command::new(wix_toolset_path.join("candle.exe"))
+    .arg("-?")
+    .output_unchecked()
+    .context("error running candle.exe")?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  Regex::new(r"version (\d+(?:\.\d+)+)")?
+    .captures(&stdout)
+    .map(|captures| captures[1].to_string())
+    .ok_or_else(|| {
+      crate::Error::GenericError("failed to read the WiX toolset version from candle.exe".into())
+    })
+}
+
 fn clear_env_for_wix(cmd: &mut Command) {
   cmd.env_clear();
   let required_vars: Vec<std::ffi::OsString> =
@@ -395,24 +444,6 @@ fn run_light(
 //   Ok(())
 // }
 
-fn validate_version(version: &str) -> anyhow::Result<()> {
-  let version = semver::Version::parse(version).context("invalid app version")?;
-  if version.major > 255 {
-    bail!("app version major number cannot be greater than 255");
-  }
-  if version.minor > 255 {
-    bail!("app version minor number cannot be greater than 255");
-  }
-  if version.patch > 65535 {
-    bail!("app version patch number cannot be greater than 65535");
-  }
-  if !(version.pre.is_empty() && version.build.is_empty()) {
-    bail!("app version cannot have build metadata or pre-release identifier");
-  }
-
-  Ok(())
-}
-
 // Entry point for bundling and creating the MSI installer. For now the only supported platform is Windows x64.
 pub fn build_wix_app_installer(
   settings: &Settings,
@@ -430,7 +461,7 @@ pub fn build_wix_app_installer(
     }
   };
 
-  validate_version(settings.version_string())?;
+  let version = settings.msi_version_string()?;
 
   // target only supports x64.
   info!("Target: {}", arch);
@@ -442,31 +473,44 @@ pub fn build_wix_app_installer(
     .ok_or_else(|| anyhow::anyhow!("Failed to get main binary"))?;
   let app_exe_source = settings.binary_path(main_binary);
   let try_sign = |file_path: &PathBuf| -> crate::Result<()> {
-    if let Some(certificate_thumbprint) = &settings.windows().certificate_thumbprint {
+    if settings.windows().certificate_thumbprint.is_some()
+      || settings.windows().key_provider.is_some()
+    {
       info!(action = "Signing"; "{}", file_path.display());
-      sign(
-        &file_path,
-        &SignParams {
-          product_name: settings.product_name().into(),
-          digest_algorithm: settings
-            .windows()
-            .digest_algorithm
-            .as_ref()
-            .map(|algorithm| algorithm.to_string())
-            .unwrap_or_else(|| "sha256".to_string()),
-          certificate_thumbprint: certificate_thumbprint.to_string(),
-          timestamp_url: settings
-            .windows()
-            .timestamp_url
-            .as_ref()
-            .map(|url| url.to_string()),
-          tsp: settings.windows().tsp,
-        },
+      let params = SignParams {
+        product_name: settings.product_name().into(),
+        digest_algorithm: settings
+          .windows()
+          .digest_algorithm
+          .as_ref()
+          .map(|algorithm| algorithm.to_string())
+          .unwrap_or_else(|| "sha256".to_string()),
+        certificate_thumbprint: settings.windows().certificate_thumbprint.clone(),
+        key_provider: settings.windows().key_provider.clone(),
+        timestamp_url: settings
+          .windows()
+          .timestamp_url
+          .as_ref()
+          .map(|url| url.to_string()),
+        tsp: settings.windows().tsp,
+      };
+      let result = sign(&file_path, &params);
+      let output = result.as_ref().ok().cloned();
+      audit::record(
+        settings,
+        "signtool",
+        &params.identity(),
+        file_path,
+        output.as_deref(),
+        result.map(|_| ()),
       )?;
     }
     Ok(())
   };
 
+  if settings.windows().embed_version_info {
+    version_info::embed(settings, &app_exe_source)?;
+  }
   try_sign(&app_exe_source)?;
 
   let output_path = settings.project_out_directory().join("wix").join(arch);
@@ -605,18 +649,14 @@ pub fn build_wix_app_installer(
     .unwrap_or_default();
 
   data.insert("product_name", to_json(settings.product_name()));
-  data.insert("version", to_json(settings.version_string()));
+  data.insert("version", to_json(&version));
   let bundle_id = settings.bundle_identifier();
   let manufacturer = settings
     .publisher()
     .unwrap_or_else(|| bundle_id.split('.').nth(1).unwrap_or(bundle_id));
   data.insert("bundle_id", to_json(bundle_id));
   data.insert("manufacturer", to_json(manufacturer));
-  let upgrade_code = Uuid::new_v5(
-    &Uuid::NAMESPACE_DNS,
-    format!("{}.app.x64", &settings.main_binary_name()).as_bytes(),
-  )
-  .to_string();
+  let upgrade_code = upgrade_code(settings)?;
 
   data.insert("upgrade_code", to_json(&upgrade_code.as_str()));
   data.insert(
@@ -639,6 +679,17 @@ pub fn build_wix_app_installer(
   data.insert("binaries", binaries_json);
 
   let resources = generate_resource_data(settings)?;
+
+  let mut payload = vec![app_exe_source.clone()];
+  payload.extend(binaries.iter().map(|bin| PathBuf::from(&bin.path)));
+  for dir in resources.values() {
+    dir.collect_paths(&mut payload);
+  }
+  let reserved_disk_space = reserved_disk_space(settings, &payload)?;
+  if reserved_disk_space > 0 {
+    data.insert("reserved_disk_space", to_json(reserved_disk_space));
+  }
+
   let mut resources_wix_string = String::from("");
   let mut files_ids = Vec::new();
   for (_, dir) in resources {
@@ -649,12 +700,40 @@ pub fn build_wix_app_installer(
     }
   }
 
+  let shared_resources = generate_shared_resource_data(settings)?;
+  let mut shared_resources_wix_string = String::new();
+  for (_, dir) in shared_resources {
+    let (wix_string, ids) = dir.get_wix_data()?;
+    shared_resources_wix_string.push_str(&wix_string);
+    files_ids.extend(ids);
+  }
+  if !shared_resources_wix_string.is_empty() {
+    data.insert("shared_resources", to_json(shared_resources_wix_string));
+    data.insert(
+      "shared_resources_name",
+      to_json(settings.shared_resources_name()),
+    );
+  }
+
   data.insert("resources", to_json(resources_wix_string));
   data.insert("resource_file_ids", to_json(files_ids));
 
   let merge_modules = get_merge_modules(settings)?;
   data.insert("merge_modules", to_json(merge_modules));
 
+  let registry_entries = generate_registry_data(settings)?;
+  data.insert("registry_entries", to_json(registry_entries));
+
+  if let Some(wix) = &settings.windows().wix {
+    let user_data_folders = generate_user_data_folders(settings, &wix.uninstall_cleanup.paths)?;
+    if !user_data_folders.is_empty() {
+      data.insert("user_data_folders", to_json(user_data_folders));
+    }
+    if let Some(command) = &wix.uninstall_cleanup.command {
+      data.insert("uninstall_command", to_json(command));
+    }
+  }
+
   data.insert("app_exe_source", to_json(&app_exe_source));
 
   // copy icon from `settings.windows().icon_path` folder to resource folder near msi
@@ -697,6 +776,30 @@ pub fn build_wix_app_installer(
       );
     }
 
+    if let Some(page) = &wix.post_install_page {
+      if page.starts_with("http://") || page.starts_with("https://") {
+        data.insert("post_install_page_target", to_json(page));
+      } else {
+        let page_path = std::env::current_dir()?.join(page);
+        if !page_path.is_file() {
+          return Err(crate::Error::GenericError(format!(
+            "post install page {} does not exist",
+            page_path.display()
+          )));
+        }
+        let filename = page_path
+          .file_name()
+          .unwrap()
+          .to_string_lossy()
+          .into_owned();
+        data.insert(
+          "post_install_page_target",
+          to_json(format!("[INSTALLDIR]{}", filename)),
+        );
+        data.insert("post_install_page_source", to_json(page_path));
+      }
+    }
+
     if let Some(dialog_image_path) = &wix.dialog_image_path {
       let filename = dialog_image_path
         .file_name()
@@ -854,7 +957,7 @@ pub fn build_wix_app_installer(
       "*.wixobj".into(),
     ];
     let msi_output_path = output_path.join("output.msi");
-    let msi_path = app_installer_output_path(settings, &language, updater)?;
+    let msi_path = paths::msi_path(settings, &language, updater)?;
     create_dir_all(msi_path.parent().unwrap())?;
 
     info!(action = "Running"; "light to produce {}", msi_path.display());
@@ -922,6 +1025,176 @@ fn generate_binaries_data(settings: &Settings) -> crate::Result<Vec<Binary>> {
   Ok(binaries)
 }
 
+/// A registry value to write with WIX.
+#[derive(Serialize)]
+struct RegistryValue {
+  /// the GUID to use on the WIX XML.
+  guid: String,
+  /// the id to use on the WIX XML.
+  id: String,
+  root: String,
+  key: String,
+  name: Option<String>,
+  value_type: String,
+  /// the value, unset for `multiString` values.
+  value: Option<String>,
+  /// the values of a `multiString` value.
+  multi_values: Vec<String>,
+}
+
+const REGISTRY_ROOTS: &[&str] = &["HKLM", "HKCU", "HKCR", "HKU", "HKMU"];
+
+/// A user data folder removed by the uninstaller.
+#[derive(Serialize)]
+struct UserDataFolder {
+  /// the custom action id to use on the WIX XML.
+  id: String,
+  /// the command removing the folder.
+  command: String,
+}
+
+/// The folders user data may be removed from and their Windows Installer properties.
+const USER_DATA_ROOTS: &[(&str, &str)] = &[
+  ("$APPDATA", "[AppDataFolder]"),
+  ("$LOCALAPPDATA", "[LocalAppDataFolder]"),
+  ("$INSTALLDIR", "[INSTALLDIR]"),
+];
+
+/// Validates the [`crate::WixUninstallCleanup::paths`] and generates the commands removing them.
+fn generate_user_data_folders(
+  settings: &Settings,
+  paths: &[String],
+) -> crate::Result<Vec<UserDataFolder>> {
+  let mut folders = Vec::new();
+  for (i, path) in paths.iter().enumerate() {
+    let folder = user_data_folder(settings, path)?;
+    folders.push(UserDataFolder {
+      id: format!("RemoveUserData{}", i),
+      command: format!("cmd.exe /c if exist \"{0}\" rmdir /s /q \"{0}\"", folder),
+    });
+  }
+  Ok(folders)
+}
+
+// Resolves a user data path to a Windows Installer formatted path,
+// making sure it is a folder of the app so the uninstaller never removes unrelated data.
+fn user_data_folder(settings: &Settings, path: &str) -> crate::Result<String> {
+  let invalid = |message: &str| {
+    crate::Error::GenericError(format!(
+      "invalid uninstall cleanup path `{}`: {}",
+      path, message
+    ))
+  };
+
+  let (root, property, rest) = USER_DATA_ROOTS
+    .iter()
+    .find_map(|(root, property)| {
+      path
+        .strip_prefix(root)
+        .filter(|rest| rest.starts_with('/') || rest.starts_with('\\'))
+        .map(|rest| (*root, *property, rest))
+    })
+    .ok_or_else(|| invalid("it must start with `$APPDATA/`, `$LOCALAPPDATA/` or `$INSTALLDIR/`"))?;
+
+  let components = rest
+    .split(['/', '\\'])
+    .filter(|c| !c.is_empty())
+    .collect::<Vec<_>>();
+  if components.is_empty() {
+    return Err(invalid("it must name a folder inside the root"));
+  }
+  if components
+    .iter()
+    .any(|c| *c == "." || *c == ".." || c.contains(|c| "[]\"%*?<>|:".contains(c)))
+  {
+    return Err(invalid(
+      "it must not contain `.` or `..` components or the characters []\"%*?<>|:",
+    ));
+  }
+  if root != "$INSTALLDIR" {
+    let app_folder = components[0];
+    if !app_folder.eq_ignore_ascii_case(settings.bundle_identifier())
+      && !app_folder.eq_ignore_ascii_case(settings.product_name())
+    {
+      return Err(invalid(&format!(
+        "it must be inside the `{}` or `{}` folder",
+        settings.bundle_identifier(),
+        settings.product_name()
+      )));
+    }
+  }
+  Ok(format!("{}{}", property, components.join("\\")))
+}
+
+/// Validates the custom registry entries and generates their data.
+fn generate_registry_data(settings: &Settings) -> crate::Result<Vec<RegistryValue>> {
+  let entries = match &settings.windows().wix {
+    Some(wix) => &wix.registry_entries,
+    None => return Ok(Vec::new()),
+  };
+
+  let mut values = Vec::new();
+  for (i, entry) in entries.iter().enumerate() {
+    let invalid = |message: String| {
+      crate::Error::GenericError(format!(
+        "invalid registry entry `{}\\{}`: {}",
+        entry.root, entry.key, message
+      ))
+    };
+
+    if !REGISTRY_ROOTS.contains(&entry.root.as_str()) {
+      return Err(invalid(format!(
+        "the root must be one of {}",
+        REGISTRY_ROOTS.join(", ")
+      )));
+    }
+
+    let (value, multi_values) = match entry.value_type.as_str() {
+      "string" | "expandable" => (Some(entry.value.clone()), Vec::new()),
+      "multiString" => (None, entry.value.lines().map(Into::into).collect()),
+      "integer" => {
+        entry
+          .value
+          .parse::<i32>()
+          .map_err(|_| invalid(format!("`{}` is not an integer", entry.value)))?;
+        (Some(entry.value.clone()), Vec::new())
+      }
+      "binary" => {
+        hex::decode(&entry.value)
+          .map_err(|_| invalid(format!("`{}` is not a hex encoded value", entry.value)))?;
+        (Some(entry.value.clone()), Vec::new())
+      }
+      other => {
+        return Err(invalid(format!(
+          "unknown type `{}`, expected string, expandable, multiString, integer or binary",
+          other
+        )))
+      }
+    };
+
+    values.push(RegistryValue {
+      guid: generate_guid(
+        format!(
+          "{}\\{}\\{}",
+          entry.root,
+          entry.key,
+          entry.name.as_deref().unwrap_or_default()
+        )
+        .as_bytes(),
+      )
+      .to_string(),
+      id: format!("CustomRegistryEntry{}", i),
+      root: entry.root.clone(),
+      key: entry.key.clone(),
+      name: entry.name.clone(),
+      value_type: entry.value_type.clone(),
+      value,
+      multi_values,
+    });
+  }
+  Ok(values)
+}
+
 #[derive(Serialize)]
 struct MergeModule {
   name: String,
@@ -954,16 +1227,19 @@ fn get_merge_modules(settings: &Settings) -> crate::Result<Vec<MergeModule>> {
   Ok(merge_modules)
 }
 
-/// Generates the data required for the resource bundling on wix
-fn generate_resource_data(settings: &Settings) -> crate::Result<ResourceMap> {
-  let mut resources = ResourceMap::new();
-  let cwd = std::env::current_dir()?;
-
-  let mut added_resources = Vec::new();
-
-  for src in settings.resource_files() {
-    let src = src?;
-
+/// Adds the resource files to the map.
+///
+/// With a `shared_name`, the component GUIDs are derived from it and the target path instead of being random,
+/// so every build and every app installing the same shared file uses the same component,
+/// which Windows Installer reference counts instead of removing the file with the first uninstalled app.
+fn add_resources(
+  resources: &mut ResourceMap,
+  added_resources: &mut Vec<String>,
+  sources: Vec<(PathBuf, PathBuf)>,
+  cwd: &Path,
+  shared_name: Option<&str>,
+) {
+  for (src, target_path) in sources {
     let resource_path = cwd
       .join(src.clone())
       .into_os_string()
@@ -979,14 +1255,17 @@ fn generate_resource_data(settings: &Settings) -> crate::Result<ResourceMap> {
 
     added_resources.push(resource_path.clone());
 
+    let guid = match shared_name {
+      Some(name) => shared_resource_guid(name, &target_path),
+      None => Uuid::new_v4(),
+    };
     let resource_entry = ResourceFile {
       id: format!("I{}", Uuid::new_v4().as_simple()),
-      guid: Uuid::new_v4().to_string(),
+      guid: guid.to_string(),
       path: resource_path,
     };
 
     // split the resource path directories
-    let target_path = resource_relpath(&src);
     let components_count = target_path.components().count();
     let directories = target_path
       .components()
@@ -1045,6 +1324,24 @@ fn generate_resource_data(settings: &Settings) -> crate::Result<ResourceMap> {
     }
     directory_entry.add_file(resource_entry);
   }
+}
+
+/// Generates the data required for the resource bundling on wix
+fn generate_resource_data(settings: &Settings) -> crate::Result<ResourceMap> {
+  let mut resources = ResourceMap::new();
+  let cwd = std::env::current_dir()?;
+
+  let mut added_resources = Vec::new();
+
+  let mut sources = Vec::new();
+  for src in settings.resource_files() {
+    let src = src?;
+    let target_path = resource_relpath(&src);
+    sources.push((src, target_path));
+  }
+  sources.extend(settings.remote_resource_files().iter().cloned());
+
+  add_resources(&mut resources, &mut added_resources, sources, &cwd, None);
 
   let mut dlls = Vec::new();
 
@@ -1078,5 +1375,151 @@ fn generate_resource_data(settings: &Settings) -> crate::Result<ResourceMap> {
     );
   }
 
+  let metadata_dir = settings.project_out_directory().join("wix");
+  let metadata_files = settings
+    .write_build_info(&metadata_dir)?
+    .into_iter()
+    .chain(settings.write_release_notes(&metadata_dir)?);
+  for metadata_path in metadata_files {
+    resources
+      .entry("".to_string())
+      .or_insert_with(|| ResourceDirectory {
+        path: "".to_string(),
+        name: "".to_string(),
+        directories: vec![],
+        files: vec![],
+      })
+      .add_file(ResourceFile {
+        id: format!("I{}", Uuid::new_v4().as_simple()),
+        guid: Uuid::new_v4().to_string(),
+        path: metadata_path.to_string_lossy().into_owned(),
+      });
+  }
+
+  Ok(resources)
+}
+
+/// Generates the data of the [`crate::ResourceScope::Shared`] resources, installed in `%ProgramData%`.
+fn generate_shared_resource_data(settings: &Settings) -> crate::Result<ResourceMap> {
+  let mut resources = ResourceMap::new();
+  let mut sources = Vec::new();
+  for src in settings.shared_resource_files() {
+    let src = src?;
+    let target_path = resource_relpath(&src);
+    sources.push((src, target_path));
+  }
+  add_resources(
+    &mut resources,
+    &mut Vec::new(),
+    sources,
+    &std::env::current_dir()?,
+    Some(settings.shared_resources_name()),
+  );
   Ok(resources)
 }
+
+/// The component GUID of a shared resource, from the shared resources folder name and its path in the folder.
+fn shared_resource_guid(shared_name: &str, target_path: &Path) -> Uuid {
+  let target_path = target_path
+    .components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/");
+  generate_guid(format!("shared-resource:{}/{}", shared_name, target_path).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{shared_resource_guid, upgrade_code, user_data_folder};
+  use crate::{
+    bundle::settings::test_settings, BundleSettings, Settings, WindowsSettings, WixSettings,
+    WixUpgradeCode,
+  };
+  use std::path::Path;
+
+  fn settings(identifier: &str, upgrade_code: WixUpgradeCode) -> Settings {
+    test_settings(
+      "target",
+      BundleSettings {
+        identifier: Some(identifier.into()),
+        windows: WindowsSettings {
+          wix: Some(WixSettings {
+            upgrade_code,
+            ..Default::default()
+          }),
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      "x86_64-pc-windows-msvc",
+    )
+  }
+
+  #[test]
+  fn derives_stable_upgrade_codes() {
+    let code = |identifier: &str| {
+      upgrade_code(&settings(identifier, WixUpgradeCode::BundleIdentifier)).unwrap()
+    };
+    assert_eq!(code("com.tauri.app"), code("com.tauri.app"));
+    assert_ne!(code("com.tauri.app"), code("com.tauri.other"));
+
+    // the default keeps the upgrade code of existing installers
+    assert_eq!(
+      upgrade_code(&settings("com.tauri.app", WixUpgradeCode::MainBinaryName)).unwrap(),
+      upgrade_code(&settings("com.tauri.other", WixUpgradeCode::MainBinaryName)).unwrap()
+    );
+
+    let custom = "{6E7D9B44-63B1-4C07-A6D8-6E0B8D4B6A3C}".to_string();
+    assert_eq!(
+      upgrade_code(&settings("com.tauri.app", WixUpgradeCode::Custom(custom))).unwrap(),
+      "6e7d9b44-63b1-4c07-a6d8-6e0b8d4b6a3c"
+    );
+    assert!(upgrade_code(&settings(
+      "com.tauri.app",
+      WixUpgradeCode::Custom("nope".into())
+    ))
+    .is_err());
+  }
+
+  #[test]
+  fn stable_shared_resource_guids() {
+    let guid = shared_resource_guid("com.tauri.suite", Path::new("models\\small.onnx"));
+    assert_eq!(
+      guid,
+      shared_resource_guid("com.tauri.suite", Path::new("models/small.onnx"))
+    );
+    assert_ne!(
+      guid,
+      shared_resource_guid("com.tauri.suite", Path::new("models/large.onnx"))
+    );
+    assert_ne!(
+      guid,
+      shared_resource_guid("com.tauri.other", Path::new("models/small.onnx"))
+    );
+  }
+
+  #[test]
+  fn validates_user_data_folders() {
+    let settings = test_settings(
+      "target",
+      BundleSettings {
+        identifier: Some("com.tauri.app".into()),
+        ..Default::default()
+      },
+      "x86_64-pc-windows-msvc",
+    );
+
+    assert_eq!(
+      user_data_folder(&settings, "$LOCALAPPDATA/com.tauri.app/cache").unwrap(),
+      "[LocalAppDataFolder]com.tauri.app\\cache"
+    );
+    assert_eq!(
+      user_data_folder(&settings, "$INSTALLDIR\\logs").unwrap(),
+      "[INSTALLDIR]logs"
+    );
+    assert!(user_data_folder(&settings, "C:/Users").is_err());
+    assert!(user_data_folder(&settings, "$APPDATA").is_err());
+    assert!(user_data_folder(&settings, "$APPDATA/Microsoft").is_err());
+    assert!(user_data_folder(&settings, "$APPDATA/App/../Microsoft").is_err());
+  }
+}
@@ -7,6 +7,7 @@ use crate::bundle::common::CommandExt;
 use bitness::{self, Bitness};
 use log::{debug, info};
 use std::{
+  env,
   path::{Path, PathBuf},
   process::Command,
 };
@@ -15,14 +16,29 @@ use winreg::{
   RegKey,
 };
 
+/// The environment variable holding the PIN of the [`crate::WindowsKeyProvider`] key container.
+const KEY_CONTAINER_PIN_ENV: &str = "TAURI_WINDOWS_KEY_CONTAINER_PIN";
+
 pub struct SignParams {
   pub product_name: String,
   pub digest_algorithm: String,
-  pub certificate_thumbprint: String,
+  pub certificate_thumbprint: Option<String>,
+  pub key_provider: Option<crate::WindowsKeyProvider>,
   pub timestamp_url: Option<String>,
   pub tsp: bool,
 }
 
+impl SignParams {
+  /// The identity shown in the logs and the audit log, the thumbprint or the key container.
+  pub fn identity(&self) -> String {
+    match (&self.key_provider, &self.certificate_thumbprint) {
+      (Some(provider), _) => format!("{} ({})", provider.key_container, provider.provider),
+      (None, Some(thumbprint)) => thumbprint.clone(),
+      (None, None) => String::new(),
+    }
+  }
+}
+
 // sign code forked from https://github.com/forbjok/rust-codesign
 fn locate_signtool() -> crate::Result<PathBuf> {
   const INSTALLED_ROOTS_REGKEY_PATH: &str = r"SOFTWARE\Microsoft\Windows Kits\Installed Roots";
@@ -90,21 +106,65 @@ fn locate_signtool() -> crate::Result<PathBuf> {
   Err(crate::Error::SignToolNotFound)
 }
 
-pub fn sign<P: AsRef<Path>>(path: P, params: &SignParams) -> crate::Result<()> {
+/// Signs the file with signtool and returns its output.
+pub fn sign<P: AsRef<Path>>(path: P, params: &SignParams) -> crate::Result<String> {
   // Convert path to string reference, as we need to pass it as a command-line parameter to signtool
   let path_str = path.as_ref().to_str().unwrap();
 
-  info!(action = "Signing"; "{} with identity \"{}\"", path_str, params.certificate_thumbprint);
+  info!(action = "Signing"; "{} with identity \"{}\"", path_str, params.identity());
 
   // Construct SignTool command
   let signtool = locate_signtool()?;
 
   debug!("Running signtool {:?}", signtool);
 
+  let pin = env::var(KEY_CONTAINER_PIN_ENV).ok();
+  let mut cmd = sign_command(&signtool, path_str, params, pin.as_deref())?;
+
+  // Execute SignTool command, without logging the PIN
+  let secrets = pin.as_deref().into_iter().collect::<Vec<_>>();
+  let output = cmd.output_ok_redacted(&secrets)?;
+
+  let stdout = String::from_utf8_lossy(output.stdout.as_slice()).into_owned();
+  info!("{:?}", stdout);
+
+  Ok(format!(
+    "{}{}",
+    stdout,
+    String::from_utf8_lossy(&output.stderr)
+  ))
+}
+
+// The signtool command signing the file, with the PIN of the key container if there is one.
+fn sign_command(
+  signtool: &Path,
+  path_str: &str,
+  params: &SignParams,
+  pin: Option<&str>,
+) -> crate::Result<Command> {
   let mut cmd = Command::new(signtool);
   cmd.arg("sign");
   cmd.args(&["/fd", &params.digest_algorithm]);
-  cmd.args(&["/sha1", &params.certificate_thumbprint]);
+  if let Some(thumbprint) = &params.certificate_thumbprint {
+    cmd.args(["/sha1", thumbprint]);
+  }
+  if let Some(provider) = &params.key_provider {
+    if let Some(certificate_path) = &provider.certificate_path {
+      cmd.arg("/f").arg(certificate_path);
+    } else if params.certificate_thumbprint.is_none() {
+      return Err(crate::Error::GenericError(
+        "signing with a key provider requires its `certificate_path` or the `certificate_thumbprint`"
+          .into(),
+      ));
+    }
+    cmd.args(["/csp", &provider.provider]);
+    // the PIN is passed in the container name, as providers like SafeNet expect it
+    let key_container = match pin {
+      Some(pin) => format!("[{{{{{}}}}}]={}", pin, provider.key_container),
+      None => provider.key_container.clone(),
+    };
+    cmd.args(["/kc", &key_container]);
+  }
   cmd.args(&["/d", &params.product_name]);
 
   if let Some(ref timestamp_url) = params.timestamp_url {
@@ -117,12 +177,42 @@ pub fn sign<P: AsRef<Path>>(path: P, params: &SignParams) -> crate::Result<()> {
   }
 
   cmd.arg(path_str);
+  Ok(cmd)
+}
 
-  // Execute SignTool command
-  let output = cmd.output_ok()?;
-
-  let stdout = String::from_utf8_lossy(output.stdout.as_slice()).into_owned();
-  info!("{:?}", stdout);
-
-  Ok(())
+#[cfg(test)]
+mod tests {
+  use super::{sign_command, SignParams};
+  use crate::{bundle::common::command_line, WindowsKeyProvider};
+  use std::path::Path;
+
+  #[test]
+  fn redacts_the_key_container_pin() {
+    let params = SignParams {
+      product_name: "App".into(),
+      digest_algorithm: "sha256".into(),
+      certificate_thumbprint: Some("ABCDEF".into()),
+      key_provider: Some(WindowsKeyProvider {
+        provider: "eToken Base Cryptographic Provider".into(),
+        key_container: "te-1234".into(),
+        certificate_path: None,
+      }),
+      timestamp_url: None,
+      tsp: false,
+    };
+    let cmd = sign_command(
+      Path::new("signtool.exe"),
+      "app.exe",
+      &params,
+      Some("s3cr3t"),
+    )
+    .expect("Failed to build the signtool command");
+    assert!(cmd
+      .get_args()
+      .any(|arg| arg.to_string_lossy() == "[{{s3cr3t}}]=te-1234"));
+
+    let logged = command_line(&cmd, &["s3cr3t"]);
+    assert!(!logged.contains("s3cr3t"));
+    assert!(logged.contains("/kc [{{<redacted>}}]=te-1234"));
+  }
 }
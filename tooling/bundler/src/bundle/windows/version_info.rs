@@ -0,0 +1,220 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use crate::bundle::{
+  common::{self, CommandExt},
+  settings::Settings,
+};
+use anyhow::Context;
+use log::{debug, info};
+
+use std::{fs, path::Path};
+
+/// The resource type of `VERSIONINFO` resources.
+const RT_VERSION: u32 = 16;
+
+/// Embeds a `VERSIONINFO` resource built from the settings in the executable with `rcedit`,
+/// unless the executable already has one.
+pub fn embed(settings: &Settings, exe_path: &Path) -> crate::Result<()> {
+  let exe = fs::read(exe_path)
+    .with_context(|| format!("Failed to read the executable {}", exe_path.display()))?;
+  if has_version_resource(&exe).ok_or_else(|| {
+    crate::Error::GenericError(format!(
+      "{} is not a valid PE executable",
+      exe_path.display()
+    ))
+  })? {
+    debug!("{} already has a version resource", exe_path.display());
+    return Ok(());
+  }
+
+  // the file and product versions are four numbers, validated by the MSI version
+  let version = settings.msi_version_string()?;
+  info!(action = "Embedding"; "version info {} in {}", version, exe_path.display());
+
+  let mut command = common::tool_command("rcedit");
+  command
+    .arg(exe_path)
+    .args(["--set-file-version", &version])
+    .args(["--set-product-version", &version]);
+  for (name, value) in version_strings(settings, exe_path) {
+    debug!("version info {}: {}", name, value);
+    command.args(["--set-version-string", name, &value]);
+  }
+  command
+    .output_ok()
+    .context("failed to embed the version info with rcedit, is it installed?")?;
+  Ok(())
+}
+
+// The string fields of the version resource, without the fields the settings have no value for.
+fn version_strings(settings: &Settings, exe_path: &Path) -> Vec<(&'static str, String)> {
+  let bundle_id = settings.bundle_identifier();
+  let company = settings
+    .publisher()
+    .unwrap_or_else(|| bundle_id.split('.').nth(1).unwrap_or(bundle_id));
+  let file_name = exe_path
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let internal_name = exe_path
+    .file_stem()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  [
+    ("ProductName", Some(settings.product_name())),
+    ("FileDescription", Some(settings.short_description())),
+    ("CompanyName", Some(company)),
+    ("LegalCopyright", settings.copyright_string()),
+    ("OriginalFilename", Some(file_name.as_str())),
+    ("InternalName", Some(internal_name.as_str())),
+  ]
+  .into_iter()
+  .filter_map(|(name, value)| {
+    value
+      .filter(|value| !value.is_empty())
+      .map(|value| (name, value.to_string()))
+  })
+  .collect()
+}
+
+// Whether the root directory of the PE resources has a `VERSIONINFO` entry,
+// or `None` if the file is not a valid PE file.
+//
+// The offsets are computed as `u64` so a malformed file cannot overflow them.
+fn has_version_resource(pe: &[u8]) -> Option<bool> {
+  let bytes = |offset: u64, len: usize| {
+    let start = usize::try_from(offset).ok()?;
+    pe.get(start..start.checked_add(len)?)
+  };
+  let read_u16 = |offset: u64| bytes(offset, 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u64);
+  let read_u32 =
+    |offset: u64| bytes(offset, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as u64);
+
+  if bytes(0, 2)? != b"MZ" {
+    return None;
+  }
+  let pe_offset = read_u32(0x3c)?;
+  if bytes(pe_offset, 4)? != b"PE\0\0" {
+    return None;
+  }
+  let coff = pe_offset + 4;
+  let sections = read_u16(coff + 2)?;
+  let optional_header = coff + 20;
+  let optional_header_size = read_u16(coff + 16)?;
+  // the data directories follow the fields of the PE32 or PE32+ optional header
+  let data_directories = match read_u16(optional_header)? {
+    0x10b => optional_header + 96,
+    0x20b => optional_header + 112,
+    _ => return None,
+  };
+  let directory_count = read_u32(data_directories - 4)?;
+  // the resource table is the third data directory
+  let resource_rva = if directory_count > 2 {
+    read_u32(data_directories + 16)?
+  } else {
+    0
+  };
+  if resource_rva == 0 {
+    return Some(false);
+  }
+
+  let section_table = optional_header + optional_header_size;
+  let resource_offset = (0..sections).find_map(|i| {
+    let section = section_table + i * 40;
+    let virtual_size = read_u32(section + 8)?;
+    let virtual_address = read_u32(section + 12)?;
+    let raw_size = read_u32(section + 16)?;
+    let raw_offset = read_u32(section + 20)?;
+    let end = virtual_address + virtual_size.max(raw_size);
+    (virtual_address..end)
+      .contains(&resource_rva)
+      .then(|| raw_offset + resource_rva - virtual_address)
+  })?;
+
+  // the named entries come before the entries with a numeric ID
+  let named_entries = read_u16(resource_offset + 12)?;
+  let id_entries = read_u16(resource_offset + 14)?;
+  let entries = resource_offset + 16;
+  for i in named_entries..named_entries + id_entries {
+    if read_u32(entries + i * 8)? == u64::from(RT_VERSION) {
+      return Some(true);
+    }
+  }
+  Some(false)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{has_version_resource, version_strings, RT_VERSION};
+  use crate::{bundle::settings::test_settings, BundleSettings};
+  use std::path::Path;
+
+  // A minimal PE32+ file with one section holding a resource directory with one entry of the type.
+  fn pe_file(resource_type: u32) -> Vec<u8> {
+    let mut pe = vec![0u8; 0x400];
+    let mut write =
+      |offset: usize, bytes: &[u8]| pe[offset..offset + bytes.len()].copy_from_slice(bytes);
+    write(0, b"MZ");
+    write(0x3c, &0x40u32.to_le_bytes());
+    write(0x40, b"PE\0\0");
+    // one section and the size of the PE32+ optional header
+    write(0x46, &1u16.to_le_bytes());
+    write(0x54, &240u16.to_le_bytes());
+    write(0x58, &0x20bu16.to_le_bytes());
+    write(0x58 + 108, &16u32.to_le_bytes());
+    write(0x58 + 112 + 16, &0x1000u32.to_le_bytes());
+    // the `.rsrc` section mapping the RVA 0x1000 to the offset 0x200
+    let section = 0x58 + 240;
+    write(section + 8, &0x100u32.to_le_bytes());
+    write(section + 12, &0x1000u32.to_le_bytes());
+    write(section + 16, &0x200u32.to_le_bytes());
+    write(section + 20, &0x200u32.to_le_bytes());
+    write(0x200 + 14, &1u16.to_le_bytes());
+    write(0x210, &resource_type.to_le_bytes());
+    pe
+  }
+
+  #[test]
+  fn detects_version_resources() {
+    assert_eq!(has_version_resource(&pe_file(RT_VERSION)), Some(true));
+    // an icon group resource
+    assert_eq!(has_version_resource(&pe_file(14)), Some(false));
+    assert_eq!(has_version_resource(b"MZ"), None);
+    assert_eq!(has_version_resource(&[0; 0x400]), None);
+
+    // sections and offsets pointing past the end of the file
+    let mut malformed = pe_file(RT_VERSION);
+    malformed[0x58 + 240 + 8..0x58 + 240 + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+    malformed[0x58 + 240 + 20..0x58 + 240 + 24].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(has_version_resource(&malformed), None);
+    let mut malformed = pe_file(RT_VERSION);
+    malformed[0x3c..0x40].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(has_version_resource(&malformed), None);
+  }
+
+  #[test]
+  fn fills_version_strings() {
+    let settings = test_settings(
+      "target",
+      BundleSettings {
+        identifier: Some("com.tauri.app".into()),
+        ..Default::default()
+      },
+      "x86_64-pc-windows-msvc",
+    );
+
+    assert_eq!(settings.msi_version_string().unwrap(), "1.0.0.0");
+    assert_eq!(
+      version_strings(&settings, Path::new("target/release/app.exe")),
+      vec![
+        ("ProductName", "App".to_string()),
+        ("FileDescription", "An app".to_string()),
+        ("CompanyName", "tauri".to_string()),
+        ("OriginalFilename", "app.exe".to_string()),
+        ("InternalName", "app".to_string()),
+      ]
+    );
+  }
+}
@@ -35,7 +35,7 @@ pub enum Error {
   #[error("`{0}`")]
   ConvertError(#[from] num::TryFromIntError),
   /// Zip error.
-  #[cfg(target_os = "windows")]
+  #[cfg(any(target_os = "linux", target_os = "windows"))]
   #[error("`{0}`")]
   ZipError(#[from] zip::result::ZipError),
   /// Hex error.
@@ -67,6 +67,12 @@ pub enum Error {
   /// Failed to validate downloaded file hash.
   #[error("hash mismatch of downloaded file")]
   HashError,
+  /// Invalid app version.
+  #[error("invalid app version `{0}`: {1}")]
+  InvalidVersion(String, String),
+  /// Unknown package type.
+  #[error("unknown bundle format `{0}`. {1}")]
+  UnknownPackageType(String, String),
   /// Unsupported architecture.
   #[error("Architecture Error: `{0}`")]
   ArchError(String),
@@ -103,6 +109,20 @@ pub enum Error {
   /// Failed to sign application.
   #[error("failed to sign app: {0}")]
   Sign(String),
+  /// Some package types failed to bundle, see [`crate::BundleSettings::fail_fast`].
+  #[error("failed to bundle {}", format_failures(.failures))]
+  BundleFailures {
+    /// The bundles that were created.
+    bundles: Vec<crate::Bundle>,
+    /// The package types that failed, with their error.
+    failures: Vec<(crate::PackageType, Error)>,
+  },
+  /// Files referenced by the settings do not exist, checked before bundling starts.
+  #[error("missing files:\n  {}", .0.join("\n  "))]
+  MissingFiles(Vec<String>),
+  /// Bundling was cancelled with the [`crate::BundleSettings::cancellation`] token.
+  #[error("bundling was cancelled")]
+  Cancelled,
   /// time error.
   #[cfg(target_os = "macos")]
   #[error("`{0}`")]
@@ -113,5 +133,13 @@ pub enum Error {
   Plist(#[from] plist::Error),
 }
 
+fn format_failures(failures: &[(crate::PackageType, Error)]) -> String {
+  failures
+    .iter()
+    .map(|(package_type, error)| format!("{}: {}", package_type.short_name(), error))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
 /// Convenient type alias of Result type.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -10,7 +10,7 @@
 //!
 //! # Platform support
 //! - macOS
-//!   - DMG and App bundles
+//!   - DMG, App bundles and zipped App bundles
 //! - Linux
 //!   - Appimage and Debian packages
 //! - Windows
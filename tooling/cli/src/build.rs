@@ -6,7 +6,9 @@ use crate::{
   helpers::{
     app_paths::{app_dir, tauri_dir},
     command_env,
-    config::{get as get_config, AppUrl, HookCommand, WindowUrl, MERGE_CONFIG_EXTENSION_NAME},
+    config::{
+      get as get_config, AppUrl, Config, HookCommand, WindowUrl, MERGE_CONFIG_EXTENSION_NAME,
+    },
     updater_signature::{read_key_from_file, secret_key as updater_secret_key, sign_file},
   },
   interface::{AppInterface, AppSettings, Interface},
@@ -44,7 +46,8 @@ pub struct Options {
   pub features: Option<Vec<String>>,
   /// Space or comma separated list of bundles to package.
   ///
-  /// Each bundle must be one of `deb`, `appimage`, `msi`, `app` or `dmg` on MacOS and `updater` on all platforms.
+  /// Each bundle must be one of `deb`, `appimage`, `msi`, `app`, `dmg` or `zip` on MacOS and `updater` on all platforms.
+  /// Names are case insensitive and `macos`, `osx`, `debian` and `wix` are accepted as aliases.
   /// If `none` is specified, the bundler will be skipped.
   ///
   /// Note that the `updater` bundle is not automatically added so you must specify it if the updater is enabled.
@@ -53,6 +56,9 @@ pub struct Options {
   /// JSON string or path to JSON file to merge with tauri.conf.json
   #[clap(short, long)]
   pub config: Option<String>,
+  /// Prints the resolved bundler settings as JSON, with secrets redacted, without building or bundling the app
+  #[clap(long)]
+  pub dump_settings: bool,
   /// Command line arguments passed to the runner
   pub args: Vec<String>,
 }
@@ -112,6 +118,19 @@ pub fn command(mut options: Options) -> Result<()> {
     std::process::exit(1);
   }
 
+  if options.runner.is_none() {
+    options.runner = config_.build.runner.clone();
+  }
+
+  if let Some(list) = options.features.as_mut() {
+    list.extend(config_.build.features.clone().unwrap_or_default());
+  }
+
+  // the settings do not depend on the web assets or on the bundle being active
+  if options.dump_settings {
+    return dump_settings(options, config_);
+  }
+
   if let Some(before_build) = config_.build.before_build_command.clone() {
     run_hook("beforeBuildCommand", before_build, options.debug)?;
   }
@@ -146,14 +165,6 @@ pub fn command(mut options: Options) -> Result<()> {
     }
   }
 
-  if options.runner.is_none() {
-    options.runner = config_.build.runner.clone();
-  }
-
-  if let Some(list) = options.features.as_mut() {
-    list.extend(config_.build.features.clone().unwrap_or_default());
-  }
-
   let mut interface = AppInterface::new(config_, options.target.clone())?;
   let app_settings = interface.app_settings();
   let interface_options = options.clone().into();
@@ -166,36 +177,7 @@ pub fn command(mut options: Options) -> Result<()> {
   let app_settings = interface.app_settings();
 
   if config_.tauri.bundle.active {
-    let package_types = if let Some(names) = &options.bundles {
-      let mut types = vec![];
-      for name in names
-        .iter()
-        .flat_map(|n| n.split(',').map(|s| s.to_string()).collect::<Vec<String>>())
-      {
-        if name == "none" {
-          break;
-        }
-        match PackageType::from_short_name(&name) {
-          Some(package_type) => {
-            types.push(package_type);
-          }
-          None => {
-            return Err(anyhow::anyhow!(format!(
-              "Unsupported bundle format: {}",
-              name
-            )));
-          }
-        }
-      }
-      Some(types)
-    } else {
-      let targets = config_.tauri.bundle.targets.to_vec();
-      if targets.is_empty() {
-        None
-      } else {
-        Some(targets.into_iter().map(Into::into).collect())
-      }
-    };
+    let package_types = package_types(&options, config_)?;
 
     if let Some(types) = &package_types {
       if config_.tauri.updater.active && !types.contains(&PackageType::Updater) {
@@ -309,6 +291,49 @@ pub fn command(mut options: Options) -> Result<()> {
   Ok(())
 }
 
+// Prints the bundler settings as JSON, without running the hooks or building the app.
+fn dump_settings(options: Options, config: &Config) -> Result<()> {
+  let interface = AppInterface::new(config, options.target.clone())?;
+  let app_settings = interface.app_settings();
+  let bin_path = app_settings.app_binary_path(&options.clone().into())?;
+  let out_dir = bin_path.parent().unwrap();
+  let package_types = package_types(&options, config)?;
+  let settings = app_settings
+    .get_bundler_settings(&options.into(), config, out_dir, package_types)
+    .with_context(|| "failed to build bundler settings")?;
+  println!(
+    "{}",
+    settings
+      .to_json()
+      .with_context(|| "failed to dump bundler settings")?
+  );
+  Ok(())
+}
+
+// The package types of the `--bundles` option, or of the configured bundle targets.
+fn package_types(options: &Options, config: &Config) -> Result<Option<Vec<PackageType>>> {
+  if let Some(names) = &options.bundles {
+    let mut types = vec![];
+    for name in names
+      .iter()
+      .flat_map(|n| n.split(',').map(|s| s.to_string()).collect::<Vec<String>>())
+    {
+      if name == "none" {
+        break;
+      }
+      types.push(name.parse::<PackageType>()?);
+    }
+    Ok(Some(types))
+  } else {
+    let targets = config.tauri.bundle.targets.to_vec();
+    if targets.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(targets.into_iter().map(Into::into).collect()))
+    }
+  }
+}
+
 fn run_hook(name: &str, hook: HookCommand, debug: bool) -> Result<()> {
   let (script, script_cwd) = match hook {
     HookCommand::Script(s) if s.is_empty() => (None, None),
@@ -93,6 +93,7 @@ pub fn wix_settings(config: WixConfig) -> tauri_bundler::WixSettings {
     banner_path: config.banner_path,
     dialog_image_path: config.dialog_image_path,
     fips_compliant: var_os("TAURI_FIPS_COMPLIANT").map_or(false, |v| v == "true"),
+    ..Default::default()
   }
 }
 
@@ -859,6 +859,7 @@ fn tauri_config_to_bundle_settings(
         Some(depends)
       },
       files: config.deb.files,
+      ..Default::default()
     },
     macos: MacOsSettings {
       frameworks: config.macos.frameworks,
@@ -876,6 +877,7 @@ fn tauri_config_to_bundle_settings(
           None
         }
       },
+      ..Default::default()
     },
     windows: WindowsSettings {
       timestamp_url: config.windows.timestamp_url,
@@ -14,10 +14,10 @@ mod interface;
 mod plugin;
 mod signer;
 
-use clap::{FromArgMatches, IntoApp, Parser, Subcommand};
-use env_logger::fmt::Color;
+use clap::{FromArgMatches, IntoApp, Parser, Subcommand, ValueEnum};
+use env_logger::fmt::{Color, Formatter};
 use env_logger::Builder;
-use log::{debug, log_enabled, Level};
+use log::{debug, kv, log_enabled, Level, Record};
 use serde::Deserialize;
 use std::io::{BufReader, Write};
 use std::process::{exit, Command, ExitStatus, Output, Stdio};
@@ -55,10 +55,19 @@ struct Cli {
   /// Enables verbose logging
   #[clap(short, long, global = true, parse(from_occurrences))]
   verbose: usize,
+  /// The format of the logs, `json` prints one JSON event per line for CI tooling
+  #[clap(long, global = true, value_enum, default_value = "human")]
+  log_format: LogFormat,
   #[clap(subcommand)]
   command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+  Human,
+  Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
   Build(build::Options),
@@ -119,13 +128,18 @@ where
   };
 
   let mut builder = Builder::from_default_env();
-  let init_res = builder
-    .format_indent(Some(12))
-    .filter(None, level_from_usize(cli.verbose).to_level_filter())
-    .format(|f, record| {
+  builder.filter(None, level_from_usize(cli.verbose).to_level_filter());
+  if let LogFormat::Json = cli.log_format {
+    builder.format(format_json);
+  } else {
+    builder.format_indent(Some(12)).format(|f, record| {
       let mut is_command_output = false;
       if let Some(action) = record.key_values().get("action".into()) {
         let action = action.to_str().unwrap();
+        // the bundles and their artifacts are listed by the `Finished` message
+        if action == "Bundled" || action == "Artifact" {
+          return Ok(());
+        }
         is_command_output = action == "stdout" || action == "stderr";
         if !is_command_output {
           let mut action_style = f.style();
@@ -152,8 +166,9 @@ where
       }
 
       writeln!(f, "{}", record.args())
-    })
-    .try_init();
+    });
+  }
+  let init_res = builder.try_init();
 
   if let Err(err) = init_res {
     eprintln!("Failed to attach logger: {}", err);
@@ -172,6 +187,50 @@ where
   Ok(())
 }
 
+/// Writes the log record as a JSON object on a single line, with its `action` and other key-values as fields.
+///
+/// The `action` names the event, e.g. `Bundling` when a bundle starts, `Running` for an external command,
+/// `Bundled` when a bundle ends, `Artifact` for each produced file and `Finished` when bundling ends.
+fn format_json(f: &mut Formatter, record: &Record) -> std::io::Result<()> {
+  writeln!(f, "{}", json_record(record, f.timestamp()))
+}
+
+// The JSON object of a log record, split from `format_json` since env_logger has no public `Formatter` constructor.
+fn json_record(record: &Record, timestamp: impl std::fmt::Display) -> serde_json::Value {
+  struct Fields(serde_json::Map<String, serde_json::Value>);
+
+  impl<'kvs> kv::Visitor<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+      let value = if let Some(value) = value.to_bool() {
+        value.into()
+      } else if let Some(value) = value.to_u64() {
+        value.into()
+      } else if let Some(value) = value.to_i64() {
+        value.into()
+      } else {
+        value.to_string().into()
+      };
+      self.0.insert(key.as_str().to_string(), value);
+      Ok(())
+    }
+  }
+
+  let mut fields = Fields(serde_json::Map::new());
+  fields
+    .0
+    .insert("timestamp".into(), timestamp.to_string().into());
+  fields
+    .0
+    .insert("level".into(), record.level().as_str().into());
+  fields.0.insert("target".into(), record.target().into());
+  // the visitor never fails
+  let _ = record.key_values().visit(&mut fields);
+  fields
+    .0
+    .insert("message".into(), record.args().to_string().into());
+  serde_json::Value::Object(fields.0)
+}
+
 /// This maps the occurrence of `--verbose` flags to the correct log level
 fn level_from_usize(num: usize) -> Level {
   match num {
@@ -270,3 +329,32 @@ impl CommandExt for Command {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::json_record;
+  use log::{Level, Record};
+
+  #[test]
+  fn json_record_has_the_key_values() {
+    let kvs: &[(&str, &str)] = &[("action", "Bundled"), ("package_type", "deb")];
+    let record = Record::builder()
+      .args(format_args!("deb (2 files)"))
+      .level(Level::Info)
+      .target("tauri_bundler::bundle")
+      .key_values(&kvs)
+      .build();
+
+    assert_eq!(
+      json_record(&record, "2022-10-01T00:00:00Z"),
+      serde_json::json!({
+        "timestamp": "2022-10-01T00:00:00Z",
+        "level": "INFO",
+        "target": "tauri_bundler::bundle",
+        "action": "Bundled",
+        "package_type": "deb",
+        "message": "deb (2 files)",
+      })
+    );
+  }
+}